@@ -0,0 +1,349 @@
+use crate::*;
+
+// ============== フォルダ一括比較 ==============
+
+// 比較方法（アプリの compareMode に相当）
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum BatchMode {
+    Simple,
+    Heatmap,
+}
+
+pub(crate) fn batch_mode_name(mode: BatchMode) -> &'static str {
+    match mode {
+        BatchMode::Simple => "simple",
+        BatchMode::Heatmap => "heatmap",
+    }
+}
+
+// ファイルの対応付け（アプリの pairingMode と同じ）
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PairingMode {
+    Order,
+    Name,
+}
+
+// 一括比較の設定（CLI引数、またはフロントエンドからJSONで渡される）
+#[derive(Deserialize)]
+#[serde(default)]
+pub(crate) struct BatchConfig {
+    pub(crate) folder_a: String,
+    pub(crate) folder_b: String,
+    pub(crate) mode: BatchMode,
+    pub(crate) pairing: PairingMode,
+    pub(crate) threshold: Option<u8>, // 未指定ならモードごとのアプリの標準値
+    pub(crate) color_metric: Option<ColorMetric>,
+    pub(crate) delta_e: Option<f64>,
+    pub(crate) align: Option<AlignMode>,
+    pub(crate) crop: Option<CropBounds>,
+    pub(crate) options: DiffOptions,
+    pub(crate) out_dir: Option<PathBuf>,
+    pub(crate) reports: Vec<PathBuf>,
+    pub(crate) html_dir: Option<PathBuf>,
+    pub(crate) pdf_path: Option<PathBuf>,
+    pub(crate) pdf_font: Option<PathBuf>, // PDFに埋め込む日本語フォント（未指定ならシステムのフォントを探す）
+    pub(crate) fail_probability: f64,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            folder_a: String::new(),
+            folder_b: String::new(),
+            mode: BatchMode::Simple,
+            pairing: PairingMode::Order,
+            threshold: None,
+            color_metric: None,
+            delta_e: None,
+            align: None,
+            crop: None,
+            options: DiffOptions::default(),
+            out_dir: None,
+            reports: Vec::new(),
+            html_dir: None,
+            pdf_path: None,
+            pdf_font: None,
+            fail_probability: 50.0,
+        }
+    }
+}
+
+impl BatchConfig {
+    // アプリと同じ標準値（simple: 5, heatmap: 70）
+    pub(crate) fn threshold(&self) -> u8 {
+        self.threshold.unwrap_or(match self.mode {
+            BatchMode::Simple => 5,
+            BatchMode::Heatmap => 70,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BatchStatus {
+    Same,
+    Diff,
+    MissingA, // Aにファイルがない
+    MissingB,
+    Error,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct BatchPairResult {
+    pub(crate) index: usize,
+    pub(crate) name_a: Option<String>,
+    pub(crate) name_b: Option<String>,
+    #[serde(default)]
+    pub(crate) path_a: Option<String>,
+    #[serde(default)]
+    pub(crate) path_b: Option<String>,
+    pub(crate) status: BatchStatus,
+    pub(crate) exceeded: bool, // fail_probability以上、またはページ欠け
+    pub(crate) image_width: u32,
+    pub(crate) image_height: u32,
+    pub(crate) diff_count: u32,
+    pub(crate) diff_probability: f64,
+    pub(crate) markers: Vec<DiffMarker>,
+    pub(crate) diff_image: Option<String>, // 書き出した差分画像のパス
+    #[serde(default)]
+    pub(crate) thumbnails: Option<PairThumbnails>, // HTMLレポート用（レポートフォルダからの相対パス）
+    pub(crate) error: Option<String>, // 複数ある場合は "; " 区切り
+}
+
+impl BatchPairResult {
+    // 先に起きたエラーを上書きしないよう追記する
+    pub(crate) fn push_error(&mut self, e: String) {
+        match &mut self.error {
+            Some(prev) => {
+                prev.push_str("; ");
+                prev.push_str(&e);
+            }
+            None => self.error = Some(e),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct PairThumbnails {
+    pub(crate) a: String,
+    pub(crate) b: String,
+    pub(crate) diff: String,
+}
+
+// 1ペア分の差分計算結果
+pub(crate) struct BatchDiff {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) diff_count: u32,
+    pub(crate) diff_buf: Vec<u8>,
+    pub(crate) markers: Vec<DiffMarker>,
+    pub(crate) probability: f64,
+    pub(crate) preview_a: DynamicImage, // 比較に使った画像（Bは位置合わせ後, heatmapのAはクロップ後）
+    pub(crate) preview_b: DynamicImage,
+}
+
+// A/Bのファイルパスの組（片方が欠けている場合はNone）
+pub(crate) type FilePair = (Option<String>, Option<String>);
+
+// アプリと同じ規則でフォルダ内のファイルを対応付ける
+pub(crate) fn pair_folder_files(
+    files_a: Vec<String>, files_b: Vec<String>, pairing: PairingMode,
+) -> Vec<FilePair> {
+    match pairing {
+        PairingMode::Order => {
+            let len = files_a.len().max(files_b.len());
+            (0..len).map(|i| (files_a.get(i).cloned(), files_b.get(i).cloned())).collect()
+        }
+        PairingMode::Name => {
+            let base_name = |p: &String| {
+                PathBuf::from(p).file_stem().and_then(|s| s.to_str()).unwrap_or("").to_string()
+            };
+            let mut map: HashMap<String, (Option<String>, Option<String>)> = HashMap::new();
+            for f in files_a {
+                let key = base_name(&f);
+                map.entry(key).or_default().0 = Some(f);
+            }
+            for f in files_b {
+                let key = base_name(&f);
+                map.entry(key).or_default().1 = Some(f);
+            }
+            let mut names: Vec<String> = map.keys().cloned().collect();
+            names.sort_by(|a, b| natord::compare(a, b));
+            names.into_iter().filter_map(|n| map.remove(&n)).collect()
+        }
+    }
+}
+
+// tiff-tiff / psd-psd（compute_diff_simpleと同じ処理）
+pub(crate) fn batch_diff_simple(path_a: &str, path_b: &str, cfg: &BatchConfig) -> Result<BatchDiff, String> {
+    let pair = load_diff_pair(path_a, path_b, cfg.align, None, None)?;
+    let metric = PixelMetric::from_params(cfg.threshold(), cfg.color_metric, cfg.delta_e);
+    let (diff_buf, diff_count, pixels) =
+        diff_simple_core(pair.a.view(), pair.b.view(), pair.width, pair.height, metric, None, None);
+    let params = cfg.options.resolve(DiffMode::Simple)?;
+    let mut markers = cluster_markers(&pixels, &params);
+    let probability = score_markers(&mut markers, &params.probability_model, metric.delta_scale());
+    let preview_b = plane_to_image(&pair.b, pair.width, pair.height)?;
+    Ok(BatchDiff {
+        width: pair.width,
+        height: pair.height,
+        diff_count,
+        diff_buf,
+        markers,
+        probability,
+        preview_a: pair.img_a,
+        preview_b,
+    })
+}
+
+// psd-tiff（compute_diff_heatmapと同じ処理, クロップ未指定ならPSD全体）
+pub(crate) fn batch_diff_heatmap(psd_path: &str, tiff_path: &str, cfg: &BatchConfig) -> Result<BatchDiff, String> {
+    let (psd_img, tiff_img) = rayon::join(
+        || decode_image_file(psd_path, None),
+        || decode_image_file(tiff_path, None),
+    );
+    let (psd_img, tiff_img) = (psd_img?, tiff_img?);
+    let (width, height) = tiff_img.dimensions();
+    let cropped = match cfg.crop {
+        Some(c) => psd_img.crop_imm(c.left, c.top, c.right - c.left, c.bottom - c.top),
+        None => psd_img,
+    };
+    let processed = cropped.resize_exact(width, height, FilterType::Nearest);
+    let (plane_a, plane_b) = PixelPlane::pair_from_images(&processed, &tiff_img);
+
+    let params = cfg.options.resolve(DiffMode::Heatmap)?;
+    let metric = PixelMetric::from_params(cfg.threshold(), cfg.color_metric, cfg.delta_e);
+    let (diff_buf, diff_count, pixels) =
+        diff_heatmap_core(plane_a.view(), plane_b.view(), width, height, metric, None, &params, None);
+    let mut markers = cluster_markers(&pixels, &params);
+    let probability = score_markers(&mut markers, &params.probability_model, metric.delta_scale());
+    Ok(BatchDiff {
+        width,
+        height,
+        diff_count,
+        diff_buf,
+        markers,
+        probability,
+        preview_a: processed,
+        preview_b: tiff_img,
+    })
+}
+
+pub(crate) fn run_batch_pair(
+    index: usize, path_a: Option<&str>, path_b: Option<&str>, cfg: &BatchConfig, pdf: Option<&mut ProofPdf>,
+) -> BatchPairResult {
+    let file_name = |p: Option<&str>| {
+        p.map(|p| PathBuf::from(p).file_name().and_then(|n| n.to_str()).unwrap_or(p).to_string())
+    };
+    let mut result = BatchPairResult {
+        index,
+        name_a: file_name(path_a),
+        name_b: file_name(path_b),
+        path_a: path_a.map(|p| p.to_string()),
+        path_b: path_b.map(|p| p.to_string()),
+        status: BatchStatus::Same,
+        exceeded: false,
+        image_width: 0,
+        image_height: 0,
+        diff_count: 0,
+        diff_probability: 0.0,
+        markers: Vec::new(),
+        diff_image: None,
+        thumbnails: None,
+        error: None,
+    };
+
+    let (path_a, path_b) = match (path_a, path_b) {
+        (Some(a), Some(b)) => (a, b),
+        (a, _) => {
+            result.status = if a.is_none() { BatchStatus::MissingA } else { BatchStatus::MissingB };
+            result.exceeded = true;
+            return result;
+        }
+    };
+
+    let diff = match cfg.mode {
+        BatchMode::Simple => batch_diff_simple(path_a, path_b, cfg),
+        BatchMode::Heatmap => batch_diff_heatmap(path_a, path_b, cfg),
+    };
+    let diff = match diff {
+        Ok(d) => d,
+        Err(e) => {
+            result.status = BatchStatus::Error;
+            result.error = Some(e);
+            return result;
+        }
+    };
+
+    result.image_width = diff.width;
+    result.image_height = diff.height;
+    result.diff_count = diff.diff_count;
+    result.diff_probability = diff.probability;
+    result.status = if diff.diff_count > 0 { BatchStatus::Diff } else { BatchStatus::Same };
+    result.exceeded = diff.diff_count > 0 && diff.probability >= cfg.fail_probability;
+
+    // 差分画像の書き出し（差分があるページのみ）
+    if let (Some(dir), BatchStatus::Diff) = (&cfg.out_dir, result.status) {
+        let stem = PathBuf::from(path_a).file_stem().and_then(|s| s.to_str()).unwrap_or("page").to_string();
+        let out = dir.join(format!("{:03}_{}_diff.png", index + 1, stem));
+        match image::save_buffer(&out, &diff.diff_buf, diff.width, diff.height, image::ExtendedColorType::Rgba8) {
+            Ok(()) => result.diff_image = Some(out.to_string_lossy().to_string()),
+            Err(e) => result.push_error(format!("Failed to write diff image: {}", e)),
+        }
+    }
+
+    // HTMLレポート用の縮小画像
+    if let Some(dir) = &cfg.html_dir {
+        match write_pair_thumbnails(dir, index, &diff) {
+            Ok(t) => result.thumbnails = Some(t),
+            Err(e) => result.push_error(e),
+        }
+    }
+
+    // PDFレポート（差分があるページのみ）
+    if let (Some(pdf), BatchStatus::Diff) = (pdf, result.status) {
+        if let Err(e) = pdf.add_pair(&result, &diff) {
+            result.push_error(e);
+        }
+    }
+    result.markers = diff.markers;
+    result
+}
+
+// A/Bフォルダの画像を一覧してペアにする
+pub(crate) fn list_batch_pairs(cfg: &BatchConfig) -> Result<Vec<FilePair>, String> {
+    let extensions: Vec<String> = IMAGE_EXTENSIONS.iter().map(|e| e.to_string()).collect();
+    let files_a = list_files_in_folder(cfg.folder_a.clone(), extensions.clone())?;
+    let files_b = list_files_in_folder(cfg.folder_b.clone(), extensions)?;
+    Ok(pair_folder_files(files_a, files_b, cfg.pairing))
+}
+
+pub(crate) fn run_batch(cfg: &BatchConfig) -> Result<Vec<BatchPairResult>, String> {
+    let pairs = list_batch_pairs(cfg)?;
+    if let Some(dir) = &cfg.out_dir {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create output folder: {}", e))?;
+    }
+    if let Some(dir) = &cfg.html_dir {
+        fs::create_dir_all(dir.join("images"))
+            .map_err(|e| format!("Failed to create report folder: {}", e))?;
+    }
+
+    // ページ単位の処理は内部で並列化されているのでペアは順番に処理
+    let mut pdf = cfg.pdf_path.as_ref().map(|_| ProofPdf::new(cfg.pdf_font.as_ref())).transpose()?;
+    let results: Vec<BatchPairResult> = pairs
+        .iter()
+        .enumerate()
+        .map(|(i, (a, b))| run_batch_pair(i, a.as_deref(), b.as_deref(), cfg, pdf.as_mut()))
+        .collect();
+
+    if let (Some(path), Some(pdf)) = (&cfg.pdf_path, pdf) {
+        let bytes = pdf.finish(&results, cfg);
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create report folder: {}", e))?;
+        }
+        fs::write(path, bytes).map_err(|e| format!("Failed to write PDF: {}", e))?;
+    }
+    Ok(results)
+}
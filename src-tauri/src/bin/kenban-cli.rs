@@ -0,0 +1,7 @@
+// 検版ビューアーのCLI（フォルダ一括比較）
+// 例: kenban-cli 初校/ 再校/ --mode simple --out diff/ --fail-probability 50
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(kenban_viewer_lib::run_cli(args));
+}
@@ -0,0 +1,318 @@
+use crate::*;
+
+// ============== ディスクキャッシュ ==============
+// アプリのローカルデータフォルダ（Tauriの app_local_data_dir と同じ場所）
+pub(crate) const APP_IDENTIFIER: &str = "com.kenban-viewer.app";
+pub(crate) const DEFAULT_DISK_CACHE_MB: u64 = 2048;
+pub(crate) const DISK_CACHE_MAGIC: &[u8; 4] = b"KBC1";
+// 参照日時（更新日時）の更新間隔（ヒットのたびに書き込まないよう、これより古いときだけ更新）
+pub(crate) const DISK_CACHE_TOUCH_SECS: u64 = 3600;
+
+// ファイルのパス・サイズ・更新日時と表示サイズで決まるエントリ（ファイルが変われば別キーになる）
+pub(crate) struct DiskCache {
+    pub(crate) dir: PathBuf,
+    pub(crate) budget_bytes: std::sync::atomic::AtomicU64,
+    pub(crate) bytes: std::sync::atomic::AtomicU64, // おおよその使用量（書き込みのたびに加算）
+    pub(crate) prune_lock: Mutex<()>,
+    pub(crate) write_errors: std::sync::atomic::AtomicU64, // 書き込みに失敗した回数（表示は続けるので統計で知らせる）
+    pub(crate) last_write_error: Mutex<Option<String>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DiskCacheStats {
+    pub(crate) dir: String,
+    pub(crate) entries: usize,
+    pub(crate) bytes: u64,
+    pub(crate) budget_bytes: u64,
+    pub(crate) write_errors: u64,
+    pub(crate) last_write_error: Option<String>,
+}
+
+// キャッシュファイル名用のハッシュ（バージョン間で変わらないようFNV-1aを使う）
+pub(crate) fn fnv1a64(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325u64, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+pub(crate) fn read_u32(data: &[u8], pos: &mut usize) -> Option<u32> {
+    let bytes = data.get(*pos..*pos + 4)?;
+    *pos += 4;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+impl DiskCache {
+    pub(crate) fn open(dir: PathBuf, budget_bytes: u64) -> Result<Self, String> {
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create cache folder: {}", e))?;
+        let cache = Self {
+            dir,
+            budget_bytes: budget_bytes.into(),
+            bytes: 0.into(),
+            prune_lock: Mutex::new(()),
+            write_errors: 0.into(),
+            last_write_error: Mutex::new(None),
+        };
+        cache.prune();
+        Ok(cache)
+    }
+
+    pub(crate) fn default_dir() -> Option<PathBuf> {
+        Some(dirs::data_local_dir()?.join(APP_IDENTIFIER).join("decode-cache"))
+    }
+
+    // 更新日時が取れないファイルはキャッシュしない
+    pub(crate) fn key(source: &ImageSource, max_width: u32, max_height: u32) -> Option<String> {
+        Some(format!(
+            "{}|{}|{}|{}x{}",
+            source.path, source.file_size, source.modified?, max_width, max_height,
+        ))
+    }
+
+    pub(crate) fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.kbc", fnv1a64(key.as_bytes())))
+    }
+
+    // 形式: "KBC1" + キー長 + キー + 元サイズ(w,h) + 表示サイズ(w,h) + PNG
+    pub(crate) fn get(&self, key: &str) -> Option<CachedImage> {
+        let path = self.entry_path(key);
+        let data = fs::read(&path).ok()?;
+        if !data.starts_with(DISK_CACHE_MAGIC) {
+            return None;
+        }
+        let mut pos = DISK_CACHE_MAGIC.len();
+        let key_len = read_u32(&data, &mut pos)? as usize;
+        // ハッシュ衝突に備えてキーを照合
+        if data.get(pos..pos + key_len)? != key.as_bytes() {
+            return None;
+        }
+        pos += key_len;
+        let original_width = read_u32(&data, &mut pos)?;
+        let original_height = read_u32(&data, &mut pos)?;
+        let width = read_u32(&data, &mut pos)?;
+        let height = read_u32(&data, &mut pos)?;
+        // 更新日時を参照日時として使う（古いものから削除するため, 間隔が空いたときだけ更新）
+        let touch_interval = std::time::Duration::from_secs(DISK_CACHE_TOUCH_SECS);
+        let stale = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .map_or(true, |t| t.elapsed().is_ok_and(|age| age >= touch_interval));
+        if stale {
+            if let Ok(file) = fs::File::options().write(true).open(&path) {
+                let _ = file.set_modified(std::time::SystemTime::now());
+            }
+        }
+        let source_path = key.split('|').next().unwrap_or_default();
+        Some(CachedImage {
+            data: data[pos..].to_vec(),
+            width,
+            height,
+            original_width,
+            original_height,
+            source: ImageSource::from_path(source_path),
+        })
+    }
+
+    pub(crate) fn put(&self, key: &str, image: &CachedImage) -> Result<(), String> {
+        use std::sync::atomic::Ordering;
+        let mut data = Vec::with_capacity(image.data.len() + key.len() + 24);
+        data.extend_from_slice(DISK_CACHE_MAGIC);
+        data.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        data.extend_from_slice(key.as_bytes());
+        for v in [image.original_width, image.original_height, image.width, image.height] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data.extend_from_slice(&image.data);
+
+        // 書きかけのファイルを読まないよう一時ファイルからリネーム
+        let path = self.entry_path(key);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, &data).map_err(|e| format!("Failed to write cache: {}", e))?;
+        fs::rename(&tmp, &path).map_err(|e| format!("Failed to write cache: {}", e))?;
+
+        let total = self.bytes.fetch_add(data.len() as u64, Ordering::Relaxed) + data.len() as u64;
+        if total > self.budget_bytes.load(Ordering::Relaxed) {
+            self.prune();
+        }
+        Ok(())
+    }
+
+    // 書き込み失敗を統計に残す（キャッシュなしでも表示はできるので呼び出し元には返さない）
+    pub(crate) fn record_write_error(&self, error: String) {
+        self.write_errors.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        if let Ok(mut last) = self.last_write_error.lock() {
+            *last = Some(error);
+        }
+    }
+
+    pub(crate) fn entries(&self) -> Vec<(PathBuf, u64, std::time::SystemTime)> {
+        let Ok(read_dir) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        read_dir
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let meta = e.metadata().ok()?;
+                let modified = meta.modified().unwrap_or(std::time::UNIX_EPOCH);
+                meta.is_file().then(|| (e.path(), meta.len(), modified))
+            })
+            .collect()
+    }
+
+    // 上限を超えていたら参照の古いものから削除（頻繁に走らないよう上限の8割まで減らす）
+    pub(crate) fn prune(&self) {
+        use std::sync::atomic::Ordering;
+        let Ok(_guard) = self.prune_lock.try_lock() else {
+            return; // 他のスレッドが削除中
+        };
+        let budget = self.budget_bytes.load(Ordering::Relaxed);
+        let mut entries = self.entries();
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        if total > budget {
+            entries.sort_by_key(|(_, _, modified)| *modified);
+            let target = budget / 10 * 8;
+            for (path, len, _) in entries {
+                if total <= target {
+                    break;
+                }
+                if fs::remove_file(&path).is_ok() {
+                    total -= len;
+                }
+            }
+        }
+        self.bytes.store(total, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_budget(&self, budget_bytes: u64) {
+        self.budget_bytes.store(budget_bytes, std::sync::atomic::Ordering::Relaxed);
+        self.prune();
+    }
+
+    pub(crate) fn stats(&self) -> DiskCacheStats {
+        let entries = self.entries();
+        DiskCacheStats {
+            dir: self.dir.to_string_lossy().to_string(),
+            entries: entries.len(),
+            bytes: entries.iter().map(|(_, len, _)| len).sum(),
+            budget_bytes: self.budget_bytes.load(std::sync::atomic::Ordering::Relaxed),
+            write_errors: self.write_errors.load(std::sync::atomic::Ordering::Relaxed),
+            last_write_error: self.last_write_error.lock().ok().and_then(|last| last.clone()),
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        for (path, _, _) in self.entries() {
+            let _ = fs::remove_file(path);
+        }
+        self.bytes.store(0, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(name: &str) -> DiskCache {
+        let dir = std::env::temp_dir().join(format!("kenban-test-disk-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        DiskCache::open(dir, 1 << 20).unwrap()
+    }
+
+    fn image() -> CachedImage {
+        CachedImage {
+            data: b"\x89PNG-data".to_vec(),
+            width: 30,
+            height: 20,
+            original_width: 300,
+            original_height: 200,
+            source: ImageSource { path: "/no/such/page.tif".to_string(), file_size: 1234, modified: Some(5678) },
+        }
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_values() {
+        assert_eq!(fnv1a64(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a64(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a64(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn key_changes_with_the_source_file() {
+        let source = image().source;
+        let key = DiskCache::key(&source, 800, 600).unwrap();
+        assert_eq!(key, "/no/such/page.tif|1234|5678|800x600");
+        // 上書きされたファイル（サイズ・更新日時が違う）は別のキー
+        let rewritten = ImageSource { modified: Some(5679), ..source.clone() };
+        assert_ne!(DiskCache::key(&rewritten, 800, 600).unwrap(), key);
+        let resized = ImageSource { file_size: 1235, ..source.clone() };
+        assert_ne!(DiskCache::key(&resized, 800, 600).unwrap(), key);
+        assert_ne!(DiskCache::key(&source, 400, 300).unwrap(), key);
+        // 更新日時が取れないファイルはキャッシュしない
+        assert!(DiskCache::key(&ImageSource { modified: None, ..source }, 800, 600).is_none());
+    }
+
+    #[test]
+    fn round_trips_the_on_disk_format() {
+        let cache = temp_cache("format");
+        let key = DiskCache::key(&image().source, 800, 600).unwrap();
+        cache.put(&key, &image()).unwrap();
+
+        let path = cache.entry_path(&key);
+        assert_eq!(path.file_name().unwrap().to_string_lossy(), format!("{:016x}.kbc", fnv1a64(key.as_bytes())));
+        let raw = fs::read(&path).unwrap();
+        let mut expected = b"KBC1".to_vec();
+        expected.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        expected.extend_from_slice(key.as_bytes());
+        for v in [300u32, 200, 30, 20] {
+            expected.extend_from_slice(&v.to_le_bytes());
+        }
+        expected.extend_from_slice(b"\x89PNG-data");
+        assert_eq!(raw, expected);
+
+        let cached = cache.get(&key).unwrap();
+        assert_eq!(cached.data, b"\x89PNG-data");
+        assert_eq!((cached.width, cached.height, cached.original_width, cached.original_height), (30, 20, 300, 200));
+        assert_eq!(cached.source.path, "/no/such/page.tif");
+
+        // 別のファイルになったキーでは読めない
+        let stale = DiskCache::key(&ImageSource { modified: Some(1), ..image().source }, 800, 600).unwrap();
+        assert!(cache.get(&stale).is_none());
+        // ハッシュが衝突してもキーを照合する
+        fs::copy(&path, cache.entry_path(&stale)).unwrap();
+        assert!(cache.get(&stale).is_none());
+        assert_eq!(cache.stats().write_errors, 0);
+        cache.clear();
+        assert_eq!(cache.stats().entries, 0);
+        let _ = fs::remove_dir_all(&cache.dir);
+    }
+
+    #[test]
+    fn hits_touch_only_old_entries() {
+        let cache = temp_cache("touch");
+        let key = DiskCache::key(&image().source, 800, 600).unwrap();
+        cache.put(&key, &image()).unwrap();
+        let path = cache.entry_path(&key);
+        let set_age = |secs: u64| {
+            let file = fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(std::time::SystemTime::now() - std::time::Duration::from_secs(secs)).unwrap();
+        };
+        let age = || fs::metadata(&path).unwrap().modified().unwrap().elapsed().unwrap().as_secs();
+
+        set_age(60);
+        assert!(cache.get(&key).is_some());
+        assert!(age() >= 59, "recently used entry should not be rewritten");
+
+        set_age(DISK_CACHE_TOUCH_SECS * 2);
+        assert!(cache.get(&key).is_some());
+        assert!(age() < 60);
+        let _ = fs::remove_dir_all(&cache.dir);
+    }
+
+    #[test]
+    fn write_errors_are_counted() {
+        let cache = temp_cache("errors");
+        fs::remove_dir_all(&cache.dir).unwrap();
+        let key = DiskCache::key(&image().source, 800, 600).unwrap();
+        let err = cache.put(&key, &image()).unwrap_err();
+        cache.record_write_error(err.clone());
+        let stats = cache.stats();
+        assert_eq!(stats.write_errors, 1);
+        assert_eq!(stats.last_write_error, Some(err));
+    }
+}
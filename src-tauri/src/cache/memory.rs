@@ -0,0 +1,278 @@
+use crate::*;
+
+// ============== 画像キャッシュ ==============
+pub(crate) const DEFAULT_IMAGE_CACHE_MB: u64 = 512;
+
+// キャッシュ元ファイルの情報
+#[derive(Serialize, Clone, PartialEq)]
+pub(crate) struct ImageSource {
+    pub(crate) path: String,
+    pub(crate) file_size: u64,
+    pub(crate) modified: Option<u64>, // UNIX時刻（ミリ秒）
+}
+
+impl ImageSource {
+    pub(crate) fn from_path(path: &str) -> Self {
+        let meta = fs::metadata(path).ok();
+        Self {
+            path: path.to_string(),
+            file_size: meta.as_ref().map_or(0, |m| m.len()),
+            modified: meta
+                .and_then(|m| m.modified().ok())
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as u64),
+        }
+    }
+
+    // キャッシュ後にファイルが上書き・削除されていないか
+    pub(crate) fn is_current(&self) -> bool {
+        self.matches(&Self::from_path(&self.path))
+    }
+
+    // 別途取得した現在のファイル情報と一致するか（キャッシュのロック外で stat するため）
+    pub(crate) fn matches(&self, now: &ImageSource) -> bool {
+        now.modified.is_some() && now.file_size == self.file_size && now.modified == self.modified
+    }
+}
+
+pub(crate) struct CachedImage {
+    pub(crate) data: Vec<u8>,  // PNG bytes
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) original_width: u32,  // リサイズ前のサイズ
+    pub(crate) original_height: u32,
+    pub(crate) source: ImageSource,
+}
+
+pub(crate) struct CacheEntry {
+    pub(crate) image: CachedImage,
+    pub(crate) last_used: u64,
+}
+
+#[derive(Serialize, Clone, Copy, Default)]
+pub(crate) struct ImageCacheStats {
+    pub(crate) entries: usize,
+    pub(crate) bytes: u64,
+    pub(crate) budget_bytes: u64,
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+    pub(crate) evictions: u64,
+    pub(crate) invalidations: u64, // 元ファイルの変更で破棄した件数
+}
+
+// メモリ量で上限を決めるLRUキャッシュ
+pub(crate) struct ImageCache {
+    pub(crate) cache: HashMap<String, CacheEntry>,
+    pub(crate) order: BTreeMap<u64, String>, // last_used → キー（先頭が最も古い）
+    pub(crate) tick: u64, // 参照のたびに進める（小さいほど古い）
+    pub(crate) bytes: u64,
+    pub(crate) budget_bytes: u64,
+    pub(crate) stats: ImageCacheStats,
+}
+
+impl ImageCache {
+    pub(crate) fn new(budget_bytes: u64) -> Self {
+        Self {
+            cache: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+            budget_bytes,
+            stats: ImageCacheStats::default(),
+        }
+    }
+
+    pub(crate) fn entry_bytes(key: &str, image: &CachedImage) -> u64 {
+        (key.len() + image.data.len() + image.source.path.len()) as u64
+    }
+
+    // 参照したエントリを最新にする（元ファイルが変わっていたら破棄してミス扱い）
+    // currentはロックの外で取得した元ファイルの情報
+    pub(crate) fn get(&mut self, key: &str, current: &ImageSource) -> Option<&CachedImage> {
+        self.tick += 1;
+        if self.cache.get(key).is_some_and(|e| !e.image.source.matches(current)) {
+            self.remove(key);
+            self.stats.invalidations += 1;
+        }
+        match self.cache.get_mut(key) {
+            Some(entry) => {
+                self.stats.hits += 1;
+                self.order.remove(&entry.last_used);
+                self.order.insert(self.tick, key.to_string());
+                entry.last_used = self.tick;
+                Some(&entry.image)
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    // 内部での読み出し・URLからの取得用（統計・順序は変えず, 元ファイルの確認は呼び出し側で行う）
+    pub(crate) fn peek(&self, key: &str) -> Option<&CachedImage> {
+        self.cache.get(key).map(|e| &e.image)
+    }
+
+    // 先読みの判定用（統計・順序は変えない）
+    pub(crate) fn contains(&self, key: &str, current: &ImageSource) -> bool {
+        self.peek(key).is_some_and(|image| image.source.matches(current))
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.cache.remove(key) {
+            self.order.remove(&entry.last_used);
+            self.bytes -= Self::entry_bytes(key, &entry.image);
+        }
+    }
+
+    // 指定ファイルから作ったエントリをすべて破棄（サイズ違いも含む）
+    pub(crate) fn invalidate_source(&mut self, path: &std::path::Path) -> usize {
+        let keys: Vec<String> = self
+            .cache
+            .iter()
+            .filter(|(_, e)| std::path::Path::new(&e.image.source.path) == path)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in &keys {
+            self.remove(key);
+        }
+        self.stats.invalidations += keys.len() as u64;
+        keys.len()
+    }
+
+    pub(crate) fn insert(&mut self, key: String, image: CachedImage) {
+        self.tick += 1;
+        self.remove(&key);
+        self.bytes += Self::entry_bytes(&key, &image);
+        self.order.insert(self.tick, key.clone());
+        self.cache.insert(key.clone(), CacheEntry { image, last_used: self.tick });
+        // 上限を超えたら古いものから削除（入れたばかりのものは残す）
+        self.evict_to(self.budget_bytes, Some(&key));
+    }
+
+    pub(crate) fn evict_to(&mut self, budget_bytes: u64, keep: Option<&str>) {
+        while self.bytes > budget_bytes {
+            let oldest = self.order.values().find(|k| Some(k.as_str()) != keep).cloned();
+            let Some(oldest) = oldest else { break };
+            self.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+    }
+
+    pub(crate) fn set_budget(&mut self, budget_bytes: u64) {
+        self.budget_bytes = budget_bytes;
+        self.evict_to(budget_bytes, None);
+    }
+
+    pub(crate) fn stats(&self) -> ImageCacheStats {
+        ImageCacheStats {
+            entries: self.cache.len(),
+            bytes: self.bytes,
+            budget_bytes: self.budget_bytes,
+            ..self.stats
+        }
+    }
+
+    // エントリと統計をすべて破棄（上限はそのまま）
+    pub(crate) fn clear(&mut self) {
+        self.cache.clear();
+        self.order.clear();
+        self.bytes = 0;
+        self.stats = ImageCacheStats::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(path: &str) -> ImageSource {
+        ImageSource { path: path.to_string(), file_size: 10, modified: Some(1) }
+    }
+
+    fn image(path: &str, len: usize) -> CachedImage {
+        CachedImage {
+            data: vec![0; len],
+            width: 1,
+            height: 1,
+            original_width: 1,
+            original_height: 1,
+            source: source(path),
+        }
+    }
+
+    // キー1文字 + データ + パス1文字
+    fn entry_size(len: usize) -> u64 {
+        len as u64 + 2
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let mut cache = ImageCache::new(entry_size(100) * 3);
+        for key in ["a", "b", "c"] {
+            cache.insert(key.to_string(), image(key, 100));
+        }
+        // aを参照したのでbが最も古い
+        assert!(cache.get("a", &source("a")).is_some());
+        cache.insert("d".to_string(), image("d", 100));
+        assert!(cache.peek("b").is_none());
+        assert!(cache.peek("a").is_some() && cache.peek("c").is_some() && cache.peek("d").is_some());
+        cache.insert("e".to_string(), image("e", 100));
+        assert!(cache.peek("c").is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.evictions), (3, entry_size(100) * 3, 2));
+        assert_eq!(cache.order.len(), 3);
+    }
+
+    #[test]
+    fn budget_keeps_the_newest_entry() {
+        let mut cache = ImageCache::new(entry_size(100) * 2);
+        cache.insert("a".to_string(), image("a", 100));
+        cache.insert("b".to_string(), image("b", 100));
+        // 上限より大きくても入れたばかりのものは残す
+        cache.insert("c".to_string(), image("c", 500));
+        assert_eq!(cache.stats().entries, 1);
+        assert!(cache.peek("c").is_some());
+
+        cache.set_budget(0);
+        assert_eq!((cache.stats().entries, cache.stats().bytes), (0, 0));
+        assert!(cache.order.is_empty());
+    }
+
+    #[test]
+    fn replacing_a_key_updates_size_and_order() {
+        let mut cache = ImageCache::new(entry_size(100) * 3);
+        cache.insert("a".to_string(), image("a", 100));
+        cache.insert("b".to_string(), image("b", 100));
+        cache.insert("a".to_string(), image("a", 50));
+        assert_eq!(cache.stats().bytes, entry_size(100) + entry_size(50));
+        assert_eq!(cache.order.values().collect::<Vec<_>>(), ["b", "a"]);
+
+        cache.insert("c".to_string(), image("c", 100));
+        cache.insert("d".to_string(), image("d", 100));
+        // 置き換えたaより前に入れたbが先に消える
+        assert!(cache.peek("b").is_none());
+        assert!(cache.peek("a").is_some());
+    }
+
+    #[test]
+    fn changed_source_is_a_miss_and_clear_resets_stats() {
+        let mut cache = ImageCache::new(1 << 20);
+        cache.insert("a".to_string(), image("a", 10));
+        assert!(cache.get("a", &source("a")).is_some());
+        let changed = ImageSource { modified: Some(2), ..source("a") };
+        assert!(cache.get("a", &changed).is_none());
+        assert!(!cache.contains("a", &source("a")));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.invalidations, stats.entries), (1, 1, 1, 0));
+
+        cache.insert("b".to_string(), image("b", 10));
+        cache.clear();
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.invalidations, stats.entries, stats.bytes), (0, 0, 0, 0, 0));
+        assert_eq!(stats.budget_bytes, 1 << 20);
+    }
+}
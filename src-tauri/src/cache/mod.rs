@@ -0,0 +1,256 @@
+use crate::*;
+
+mod memory;
+mod disk;
+
+pub(crate) use memory::*;
+pub(crate) use disk::*;
+
+// ============== 並列ビューモード用の高速画像処理 ==============
+
+// 画像をリサイズしてBase64 PNGとして返す（内部ヘルパー）
+pub(crate) fn resize_image_to_png(img: &DynamicImage, max_width: u32, max_height: u32) -> Result<(Vec<u8>, u32, u32), String> {
+    let (orig_w, orig_h) = img.dimensions();
+
+    // アスペクト比を保ちながらリサイズ
+    let scale_w = max_width as f64 / orig_w as f64;
+    let scale_h = max_height as f64 / orig_h as f64;
+    let scale = scale_w.min(scale_h).min(1.0); // 拡大はしない
+
+    let new_w = (orig_w as f64 * scale).round() as u32;
+    let new_h = (orig_h as f64 * scale).round() as u32;
+
+    // PNGエンコード（リサイズが必要な場合のみリサイズ）
+    let mut png_data = Cursor::new(Vec::new());
+    if scale < 1.0 {
+        let resized = img.resize(new_w, new_h, FilterType::Triangle);
+        resized.write_to(&mut png_data, image::ImageFormat::Png)
+            .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+    } else {
+        img.write_to(&mut png_data, image::ImageFormat::Png)
+            .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+    }
+
+    Ok((png_data.into_inner(), new_w, new_h))
+}
+
+// ファイルを読み込んでキャッシュ用のエントリを作る（元サイズとファイル情報も保持）
+// ディスクキャッシュにあればデコードせずに使う
+pub(crate) fn load_cached_image(
+    disk: Option<&DiskCache>, path: &str, max_width: u32, max_height: u32,
+) -> Result<CachedImage, String> {
+    // 読み込み中に更新された場合に古い情報で残らないよう、デコード前に取得
+    let source = ImageSource::from_path(path);
+    let disk_key = disk.and_then(|_| DiskCache::key(&source, max_width, max_height));
+    if let (Some(disk), Some(key)) = (disk, &disk_key) {
+        if let Some(cached) = disk.get(key) {
+            return Ok(cached);
+        }
+    }
+
+    let img = image::open(path)
+        .map_err(|e| format!("Failed to open image: {}", e))?;
+    let (original_width, original_height) = img.dimensions();
+    let (data, width, height) = resize_image_to_png(&img, max_width, max_height)?;
+    let cached = CachedImage { data, width, height, original_width, original_height, source };
+
+    // ディスクへの書き込み失敗は表示に影響させない（失敗はディスクキャッシュの統計に残る）
+    if let (Some(disk), Some(key)) = (disk, &disk_key) {
+        if let Err(e) = disk.put(key, &cached) {
+            disk.record_write_error(e);
+        }
+    }
+    Ok(cached)
+}
+
+// キャッシュした画像のフォルダを監視対象に加える
+pub(crate) fn watch_source(state: &AppState, path: &str) {
+    if let Ok(mut watcher) = state.source_watcher.lock() {
+        if let Some(watcher) = watcher.as_mut() {
+            watcher.watch_parent(path);
+        }
+    }
+}
+
+// キャッシュ済み画像の返し方（binaryはキャッシュキーを使ったURL）
+pub(crate) fn cached_image_url(delivery: Option<ImageDelivery>, cache_key: &str, cached: &CachedImage) -> String {
+    match delivery.unwrap_or_default() {
+        ImageDelivery::DataUrl => format!("data:image/png;base64,{}", STANDARD.encode(&cached.data)),
+        ImageDelivery::Binary => image_uri(&format!("cache/{}", percent_encode(cache_key))),
+    }
+}
+
+// TIFF/PNG/JPG画像をデコード+リサイズして返す
+#[tauri::command]
+pub(crate) fn decode_and_resize_image(
+    state: State<'_, AppState>,
+    path: String,
+    max_width: u32,
+    max_height: u32,
+    delivery: Option<ImageDelivery>,
+) -> Result<ImageResult, String> {
+    // キャッシュキー生成
+    let cache_key = format!("{}:{}x{}", path, max_width, max_height);
+
+    // キャッシュチェック（元ファイルの stat はロックの外で行う）
+    let source = ImageSource::from_path(&path);
+    {
+        let mut cache = state.image_cache.lock().map_err(|e| e.to_string())?;
+        if let Some(cached) = cache.get(&cache_key, &source) {
+            return Ok(ImageResult {
+                data_url: cached_image_url(delivery, &cache_key, cached),
+                width: cached.width,
+                height: cached.height,
+                original_width: cached.original_width,
+                original_height: cached.original_height,
+            });
+        }
+    }
+
+    // 画像読み込み+リサイズ+PNGエンコード
+    let loaded = load_cached_image(state.disk_cache.as_ref(), &path, max_width, max_height)?;
+    let (width, height) = (loaded.width, loaded.height);
+    let (original_width, original_height) = (loaded.original_width, loaded.original_height);
+
+    watch_source(&state, &path);
+
+    // キャッシュに保存し、キャッシュからbase64エンコード（clone回避）
+    let data_url = {
+        let mut cache = state.image_cache.lock().map_err(|e| e.to_string())?;
+        cache.insert(cache_key.clone(), loaded);
+        // ミスとして数えた直後なのでヒットには数えない
+        let cached = cache.peek(&cache_key).ok_or("Cached image was evicted")?;
+        cached_image_url(delivery, &cache_key, cached)
+    };
+    Ok(ImageResult {
+        data_url,
+        width,
+        height,
+        original_width,
+        original_height,
+    })
+}
+
+// 複数画像を先読み（バックグラウンドでキャッシュ）- rayon並列化版
+#[tauri::command]
+pub(crate) async fn preload_images(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    paths: Vec<String>,
+    max_width: u32,
+    max_height: u32,
+    job_id: Option<String>,
+) -> Result<Vec<String>, String> {
+    // 既にキャッシュにあるパスを除外（元ファイルの stat はロックの外で行う）
+    let sources: Vec<ImageSource> = paths.iter().map(|path| ImageSource::from_path(path)).collect();
+    let paths_to_load: Vec<String> = {
+        let cache = state.image_cache.lock().map_err(|e| e.to_string())?;
+        paths.into_iter()
+            .zip(&sources)
+            .filter(|(path, source)| {
+                let cache_key = format!("{}:{}x{}", path, max_width, max_height);
+                !cache.contains(&cache_key, source)
+            })
+            .map(|(path, _)| path)
+            .collect()
+    };
+
+    if paths_to_load.is_empty() {
+        return Ok(vec!["all cached".to_string()]);
+    }
+
+    // rayonで並列に画像を読み込み・リサイズ（キャンセル後は未着手の画像を飛ばす）
+    let job = Job::start(&app, job_id)?;
+    job.phase(JobPhase::Decode, paths_to_load.len() as u64);
+    let loaded: Vec<(String, Result<CachedImage, String>)> = paths_to_load
+        .par_iter()
+        .map(|path| {
+            if job.is_cancelled() {
+                return (path.clone(), Err(JOB_CANCELLED.to_string()));
+            }
+            let result = load_cached_image(state.disk_cache.as_ref(), path, max_width, max_height);
+            job.advance(1);
+            (path.clone(), result)
+        })
+        .collect();
+
+    // 監視の登録はウォッチャーのスレッドを待つので、キャッシュをロックする前に行う
+    for (path, result) in &loaded {
+        if result.is_ok() {
+            watch_source(&state, path);
+        }
+    }
+
+    // キャッシュに一括登録
+    let mut results = Vec::new();
+    {
+        let mut cache = state.image_cache.lock().map_err(|e| e.to_string())?;
+        for (path, result) in loaded {
+            let cache_key = format!("{}:{}x{}", path, max_width, max_height);
+            match result {
+                Ok(image) => {
+                    cache.insert(cache_key, image);
+                    results.push(format!("loaded:{}", path));
+                }
+                Err(e) if e == JOB_CANCELLED => results.push(format!("cancelled:{}", path)),
+                Err(e) => results.push(format!("error:{}:{}", path, e)),
+            }
+        }
+    }
+
+    // キャンセル時も読み込み済みの分はキャッシュに残し、未着手の画像は cancelled:<path> で返す
+    job.finish();
+    Ok(results)
+}
+
+// キャッシュクリア
+#[tauri::command]
+pub(crate) fn clear_image_cache(state: State<'_, AppState>) -> Result<(), String> {
+    let mut cache = state.image_cache.lock().map_err(|e| e.to_string())?;
+    cache.clear();
+    state.image_store.lock().map_err(|e| e.to_string())?.clear();
+    state.tile_store.lock().map_err(|e| e.to_string())?.clear();
+    if let Some(watcher) = state.source_watcher.lock().map_err(|e| e.to_string())?.as_mut() {
+        watcher.unwatch_all();
+    }
+    Ok(())
+}
+
+// キャッシュの使用量とヒット率
+#[tauri::command]
+pub(crate) fn get_image_cache_stats(state: State<'_, AppState>) -> Result<ImageCacheStats, String> {
+    let cache = state.image_cache.lock().map_err(|e| e.to_string())?;
+    Ok(cache.stats())
+}
+
+// キャッシュの上限（MB）を変更（超えた分はすぐに削除）
+#[tauri::command]
+pub(crate) fn set_image_cache_budget(state: State<'_, AppState>, budget_mb: u64) -> Result<ImageCacheStats, String> {
+    let mut cache = state.image_cache.lock().map_err(|e| e.to_string())?;
+    cache.set_budget(budget_mb.saturating_mul(1024 * 1024));
+    Ok(cache.stats())
+}
+
+// ディスクキャッシュの使用量
+#[tauri::command]
+pub(crate) fn get_disk_cache_stats(state: State<'_, AppState>) -> Result<DiskCacheStats, String> {
+    let disk = state.disk_cache.as_ref().ok_or("Disk cache is not available")?;
+    Ok(disk.stats())
+}
+
+// ディスクキャッシュの上限（MB）を変更
+#[tauri::command]
+pub(crate) async fn set_disk_cache_budget(state: State<'_, AppState>, budget_mb: u64) -> Result<DiskCacheStats, String> {
+    let disk = state.disk_cache.as_ref().ok_or("Disk cache is not available")?;
+    disk.set_budget(budget_mb.saturating_mul(1024 * 1024));
+    Ok(disk.stats())
+}
+
+// ディスクキャッシュを削除
+#[tauri::command]
+pub(crate) async fn clear_disk_cache(state: State<'_, AppState>) -> Result<(), String> {
+    if let Some(disk) = state.disk_cache.as_ref() {
+        disk.clear();
+    }
+    Ok(())
+}
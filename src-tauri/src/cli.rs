@@ -0,0 +1,305 @@
+use crate::*;
+
+// ============== CLI（フォルダ一括比較） ==============
+
+pub(crate) const CLI_USAGE: &str = "\
+Usage: kenban-cli <folder_a> <folder_b> [options]
+
+Options:
+  --mode <simple|heatmap>        simple: tiff-tiff/psd-psd, heatmap: psd(A)-tiff(B) (default: simple)
+  --pairing <order|name>         pair files by sorted order or by base name (default: order)
+  --threshold <0-255>            per-channel threshold for rgb metric (default: 5, heatmap: 70)
+  --color-metric <rgb|de76|de2000>
+  --delta-e <0-100>              delta E threshold for de76/de2000
+  --align <none|translation|similarity>   (simple mode only, default: translation)
+  --crop <left,top,right,bottom> PSD crop bounds (heatmap mode, default: whole image)
+  --preset <standard|high_res|low_res>
+  --out <dir>                    write diff images to <dir>
+  --report <file>                write a report (.json / .csv / .xml = JUnit), repeatable
+  --html <dir>                   write an offline HTML report (index.html + thumbnails) to <dir>
+  --pdf <file>                   write a printable PDF proof of the pages with differences
+  --pdf-font <file>              TrueType font (.ttf/.ttc) for the PDF text (default: system Japanese font)
+  --fail-probability <0-100>     exit 1 when a page's diff probability reaches this (default: 50)
+
+Exit status: 0 = no differences above threshold, 1 = differences or missing pages,
+             2 = error (including failed diff image / report writes)";
+
+// serdeの表記（"de2000" など）のまま列挙型に変換
+pub(crate) fn parse_cli_enum<T: serde::de::DeserializeOwned>(flag: &str, value: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_string()))
+        .map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+pub(crate) fn parse_cli_args(args: &[String]) -> Result<BatchConfig, String> {
+    let mut positional = Vec::new();
+    let mut cfg = BatchConfig::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if !arg.starts_with("--") {
+            positional.push(arg.clone());
+            continue;
+        }
+        let value = iter.next().ok_or_else(|| format!("Missing value for {}", arg))?;
+        let number = |v: &str| {
+            v.parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .ok_or_else(|| format!("Invalid number for {}: {}", arg, v))
+        };
+        // 範囲外の値は丸めずにエラーにする（打ち間違いに気付けるように）
+        let in_range = |v: f64, min: f64, max: f64| {
+            if (min..=max).contains(&v) {
+                Ok(v)
+            } else {
+                Err(format!("Invalid value for {}: {} (expected {} to {})", arg, v, min, max))
+            }
+        };
+        match arg.as_str() {
+            "--mode" => cfg.mode = parse_cli_enum(arg, value)?,
+            "--pairing" => cfg.pairing = parse_cli_enum(arg, value)?,
+            "--threshold" => {
+                let v = value
+                    .parse::<u8>()
+                    .map_err(|_| format!("Invalid value for --threshold: {} (expected an integer 0 to 255)", value))?;
+                cfg.threshold = Some(v);
+            }
+            "--color-metric" => cfg.color_metric = Some(parse_cli_enum(arg, value)?),
+            "--delta-e" => cfg.delta_e = Some(in_range(number(value)?, 0.0, 100.0)?),
+            "--align" => cfg.align = Some(parse_cli_enum(arg, value)?),
+            "--preset" => cfg.options.preset = Some(parse_cli_enum(arg, value)?),
+            "--out" => cfg.out_dir = Some(PathBuf::from(value)),
+            "--html" => cfg.html_dir = Some(PathBuf::from(value)),
+            "--pdf" => cfg.pdf_path = Some(PathBuf::from(value)),
+            "--pdf-font" => cfg.pdf_font = Some(PathBuf::from(value)),
+            "--report" => {
+                report_format_for_path(value)?;
+                cfg.reports.push(PathBuf::from(value));
+            }
+            "--fail-probability" => cfg.fail_probability = in_range(number(value)?, 0.0, 100.0)?,
+            "--crop" => {
+                let v: Vec<u32> = value
+                    .split(',')
+                    .map(|p| p.trim().parse::<u32>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| format!("Invalid value for --crop: {}", value))?;
+                if v.len() != 4 || v[2] <= v[0] || v[3] <= v[1] {
+                    return Err(format!("Invalid value for --crop: {}", value));
+                }
+                cfg.crop = Some(CropBounds { left: v[0], top: v[1], right: v[2], bottom: v[3] });
+            }
+            _ => return Err(format!("Unknown option: {}", arg)),
+        }
+    }
+
+    let [a, b]: [String; 2] = positional
+        .try_into()
+        .map_err(|_| "Expected two folders".to_string())?;
+    cfg.folder_a = a;
+    cfg.folder_b = b;
+    Ok(cfg)
+}
+
+// kenban-cli のエントリポイント（戻り値は終了コード）
+pub fn run_cli(args: Vec<String>) -> i32 {
+    if args.is_empty() || args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", CLI_USAGE);
+        return if args.is_empty() { 2 } else { 0 };
+    }
+    let cfg = match parse_cli_args(&args) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, CLI_USAGE);
+            return 2;
+        }
+    };
+    let results = match run_batch(&cfg) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return 2;
+        }
+    };
+
+    for r in &results {
+        let label = match r.status {
+            BatchStatus::Same => "OK     ",
+            BatchStatus::Diff if r.exceeded => "DIFF   ",
+            BatchStatus::Diff => "minor  ",
+            BatchStatus::MissingA | BatchStatus::MissingB => "MISSING",
+            BatchStatus::Error => "ERROR  ",
+        };
+        println!(
+            "[{}] {:>3}  {} <-> {}  pixels={} markers={} probability={:.1}%{}",
+            label,
+            r.index + 1,
+            r.name_a.as_deref().unwrap_or("-"),
+            r.name_b.as_deref().unwrap_or("-"),
+            r.diff_count,
+            r.markers.len(),
+            r.diff_probability,
+            r.error.as_ref().map(|e| format!("  ({})", e)).unwrap_or_default(),
+        );
+    }
+
+    let exceeded = results.iter().filter(|r| r.exceeded).count();
+    // 差分は取れても画像・レポートの書き出しに失敗したペアもエラーとして数える
+    let errors = results.iter().filter(|r| r.status == BatchStatus::Error || r.error.is_some()).count();
+
+    let report = DiffReport {
+        generated_at: unix_time_secs(),
+        folder_a: Some(cfg.folder_a.clone()),
+        folder_b: Some(cfg.folder_b.clone()),
+        mode: batch_mode_name(cfg.mode).to_string(),
+        fail_probability: cfg.fail_probability,
+        pairs: results,
+    };
+    let mut report_failed = false;
+    if let Some(dir) = &cfg.html_dir {
+        if let Err(e) = write_html_report(dir, &report) {
+            eprintln!("error: {}", e);
+            report_failed = true;
+        }
+    }
+    for path in &cfg.reports {
+        if let Err(e) = report_format_for_path(&path.to_string_lossy())
+            .and_then(|format| write_report_file(path, format, &report))
+        {
+            eprintln!("error: {}", e);
+            report_failed = true;
+        }
+    }
+    let results = report.pairs;
+    println!(
+        "\n{} pairs, {} with differences above {:.1}%, {} errors",
+        results.len(), exceeded, cfg.fail_probability, errors,
+    );
+    if errors > 0 || report_failed {
+        2
+    } else if exceeded > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn parse(list: &[&str]) -> Result<BatchConfig, String> {
+        parse_cli_args(&args(list))
+    }
+
+    #[test]
+    fn parses_options() {
+        let cfg = parse(&[
+            "初校", "--mode", "heatmap", "再校", "--pairing", "name", "--threshold", "12", "--color-metric", "de2000",
+            "--delta-e", "2.5", "--crop", "10, 20, 300, 400", "--report", "out/report.xml", "--report", "r.csv",
+            "--fail-probability", "80",
+        ])
+        .unwrap();
+        assert_eq!((cfg.folder_a.as_str(), cfg.folder_b.as_str()), ("初校", "再校"));
+        assert!(cfg.mode == BatchMode::Heatmap && cfg.pairing == PairingMode::Name);
+        assert_eq!((cfg.threshold, cfg.delta_e, cfg.fail_probability), (Some(12), Some(2.5), 80.0));
+        let crop = cfg.crop.unwrap();
+        assert_eq!((crop.left, crop.top, crop.right, crop.bottom), (10, 20, 300, 400));
+        assert_eq!(cfg.reports, [PathBuf::from("out/report.xml"), PathBuf::from("r.csv")]);
+
+        let defaults = parse(&["a", "b"]).unwrap();
+        assert!(defaults.threshold.is_none() && defaults.mode == BatchMode::Simple);
+        assert_eq!(defaults.threshold(), 5);
+    }
+
+    #[test]
+    fn rejects_out_of_range_and_malformed_values() {
+        let invalid: &[&[&str]] = &[
+            &["a", "b", "--threshold", "256"],
+            &["a", "b", "--threshold", "-1"],
+            &["a", "b", "--threshold", "5.5"],
+            &["a", "b", "--threshold", "abc"],
+            &["a", "b", "--delta-e", "NaN"],
+            &["a", "b", "--delta-e", "-2"],
+            &["a", "b", "--fail-probability", "150"],
+            &["a", "b", "--fail-probability", "inf"],
+            &["a", "b", "--crop", "10,20,5,400"],
+            &["a", "b", "--crop", "1,2,3"],
+            &["a", "b", "--mode", "ssim"],
+            &["a", "b", "--report", "report.txt"],
+            &["a", "b", "--unknown", "1"],
+            &["a", "b", "--threshold"],
+            &["a"],
+            &["a", "b", "c"],
+        ];
+        for list in invalid {
+            assert!(parse(list).is_err(), "{:?}", list);
+        }
+        assert!(parse(&["a", "b", "--threshold", "300"]).err().unwrap().contains("0 to 255"));
+        assert!(parse(&["a", "b", "--threshold", "0"]).is_ok());
+        assert!(parse(&["a", "b", "--threshold", "255"]).is_ok());
+    }
+
+    fn write_png(path: &std::path::Path, mark: bool) {
+        let mut img = image::GrayImage::from_pixel(64, 64, image::Luma([255]));
+        if mark {
+            for y in 20..40 {
+                for x in 20..40 {
+                    img.put_pixel(x, y, image::Luma([0]));
+                }
+            }
+        }
+        img.save(path).unwrap();
+    }
+
+    #[test]
+    fn exit_codes() {
+        let root = std::env::temp_dir().join(format!("kenban-test-cli-{}", std::process::id()));
+        let (dir_a, dir_b, dir_c) = (root.join("a"), root.join("b"), root.join("c"));
+        for dir in [&dir_a, &dir_b, &dir_c] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        for name in ["p001.png", "p002.png"] {
+            write_png(&dir_a.join(name), false);
+            write_png(&dir_b.join(name), false);
+        }
+        write_png(&dir_c.join("p001.png"), false);
+        write_png(&dir_c.join("p002.png"), true);
+        let (a, b, c) = (dir_a.to_string_lossy(), dir_b.to_string_lossy(), dir_c.to_string_lossy());
+        // ファイルの下にフォルダは作れないのでレポートの書き出しに失敗する
+        let blocked = root.join("p.txt");
+        fs::write(&blocked, "").unwrap();
+        let blocked_report = blocked.join("report.json");
+        let blocked_report = blocked_report.to_string_lossy();
+
+        let codes = [
+            run_cli(args(&[&a, &b])),
+            run_cli(args(&[&a, &c])),
+            run_cli(args(&[&a, &c, "--fail-probability", "100", "--threshold", "255"])),
+            run_cli(args(&[&a, &root.join("missing").to_string_lossy()])),
+            run_cli(args(&[&a, &b, "--report", &blocked_report])),
+            run_cli(args(&[&a, &b, "--threshold", "999"])),
+            run_cli(args(&["--help"])),
+            run_cli(Vec::new()),
+        ];
+        let _ = fs::remove_dir_all(&root);
+        assert_eq!(codes, [0, 1, 0, 2, 2, 2, 0, 2]);
+    }
+
+    #[test]
+    fn missing_page_exits_with_one() {
+        let root = std::env::temp_dir().join(format!("kenban-test-cli-missing-{}", std::process::id()));
+        let (dir_a, dir_b) = (root.join("a"), root.join("b"));
+        fs::create_dir_all(&dir_a).unwrap();
+        fs::create_dir_all(&dir_b).unwrap();
+        write_png(&dir_a.join("p001.png"), false);
+        write_png(&dir_a.join("p002.png"), false);
+        write_png(&dir_b.join("p001.png"), false);
+        let code = run_cli(args(&[&dir_a.to_string_lossy(), &dir_b.to_string_lossy()]));
+        let _ = fs::remove_dir_all(&root);
+        assert_eq!(code, 1);
+    }
+}
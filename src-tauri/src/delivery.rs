@@ -0,0 +1,435 @@
+use crate::*;
+
+// ============== 画像の受け渡し（data URL / バイナリ） ==============
+
+// 結果画像の返し方（binaryの場合はURLだけ返し、フロントエンドが kenban:// から取得する）
+// 未指定は従来どおり data URL。App.tsx はまだ data URL 前提
+// （結果を diffCache に持ち続ける・canvas に描いて toDataURL する）ので、
+// binary を標準にするのは release_images の呼び出しと CORS 対応を入れた後のフロントエンド側の移行で行う
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ImageDelivery {
+    #[default]
+    DataUrl,
+    Binary,
+}
+
+pub(crate) const IMAGE_URI_SCHEME: &str = "kenban";
+// 差分結果の画像は原寸なので件数ではなく画素のバイト数で上限を決める（差分1回で3〜4枚）
+pub(crate) const IMAGE_STORE_BUDGET_MB: usize = 1024;
+
+// ImageStoreの容量計算用（メモリ上のおおよそのバイト数）
+pub(crate) trait ByteSize {
+    fn byte_size(&self) -> usize;
+}
+
+// 取得されるまでエンコードしない画像
+pub(crate) enum StoredImage {
+    Image(DynamicImage),
+    Plane { plane: PixelPlane, width: u32, height: u32 },
+}
+
+impl StoredImage {
+    pub(crate) fn rgba(buf: Vec<u8>, width: u32, height: u32) -> Self {
+        StoredImage::Plane { plane: PixelPlane::Rgba(buf), width, height }
+    }
+
+    pub(crate) fn dimensions(&self) -> (u32, u32) {
+        match self {
+            StoredImage::Image(img) => img.dimensions(),
+            StoredImage::Plane { width, height, .. } => (*width, *height),
+        }
+    }
+
+    // 範囲を切り出す（範囲は画像内に収まっていること）
+    pub(crate) fn crop(&self, x: u32, y: u32, w: u32, h: u32) -> Result<DynamicImage, String> {
+        let (plane, width) = match self {
+            StoredImage::Image(img) => return Ok(img.crop_imm(x, y, w, h)),
+            StoredImage::Plane { plane, width, .. } => (plane, *width as usize),
+        };
+        let (x, y, w, h) = (x as usize, y as usize, w as usize, h as usize);
+        let rows = y..y + h;
+        let err = || "Failed to create image buffer".to_string();
+        Ok(match plane {
+            PixelPlane::Rgba(buf) => {
+                let data = rows.flat_map(|row| &buf[(row * width + x) * 4..(row * width + x + w) * 4]).copied().collect();
+                DynamicImage::ImageRgba8(ImageBuffer::from_raw(w as u32, h as u32, data).ok_or_else(err)?)
+            }
+            PixelPlane::Gray(buf) => {
+                let data = rows.flat_map(|row| &buf[row * width + x..row * width + x + w]).copied().collect();
+                DynamicImage::ImageLuma8(ImageBuffer::from_raw(w as u32, h as u32, data).ok_or_else(err)?)
+            }
+            PixelPlane::Bilevel(bits) => {
+                let stride = bilevel_stride(width);
+                let data = rows
+                    .flat_map(|row| (x..x + w).map(move |col| (row, col)))
+                    .map(|(row, col)| if bits[row * stride + col / 8] & (0x80 >> (col % 8)) != 0 { 0 } else { 255 })
+                    .collect();
+                DynamicImage::ImageLuma8(ImageBuffer::from_raw(w as u32, h as u32, data).ok_or_else(err)?)
+            }
+        })
+    }
+
+    pub(crate) fn to_data_url(&self) -> Result<String, String> {
+        match self {
+            StoredImage::Image(img) => encode_to_data_url(img),
+            StoredImage::Plane { plane, width, height } => encode_plane_to_data_url(plane, *width, *height),
+        }
+    }
+
+    // 表示用に圧縮率より速度を優先したPNG
+    pub(crate) fn to_fast_png(&self) -> Result<Vec<u8>, String> {
+        use image::codecs::png::{CompressionType, FilterType as PngFilter, PngEncoder};
+        use image::{ExtendedColorType, ImageEncoder};
+        let mut png = Vec::new();
+        let encoder = PngEncoder::new_with_quality(&mut png, CompressionType::Fast, PngFilter::Sub);
+        let result = match self {
+            StoredImage::Image(img) => img.write_with_encoder(encoder),
+            StoredImage::Plane { plane, width, height } => match plane {
+                PixelPlane::Rgba(buf) => encoder.write_image(buf, *width, *height, ExtendedColorType::Rgba8),
+                PixelPlane::Gray(buf) => encoder.write_image(buf, *width, *height, ExtendedColorType::L8),
+                PixelPlane::Bilevel(bits) => {
+                    let gray = unpack_bilevel(bits, *width as usize, *height as usize);
+                    encoder.write_image(&gray, *width, *height, ExtendedColorType::L8)
+                }
+            },
+        };
+        result.map_err(|e| format!("PNG encode error: {}", e))?;
+        Ok(png)
+    }
+
+    // 無圧縮の画素（グレースケールは1ch, それ以外は4ch）
+    pub(crate) fn to_raw(&self) -> (Vec<u8>, u32, u32, u8) {
+        match self {
+            StoredImage::Image(img) => {
+                let (w, h) = img.dimensions();
+                if is_gray_image(img) {
+                    (img.to_luma8().into_raw(), w, h, 1)
+                } else {
+                    (img.to_rgba8().into_raw(), w, h, 4)
+                }
+            }
+            StoredImage::Plane { plane, width, height } => match plane {
+                PixelPlane::Rgba(buf) => (buf.clone(), *width, *height, 4),
+                PixelPlane::Gray(buf) => (buf.clone(), *width, *height, 1),
+                PixelPlane::Bilevel(bits) => {
+                    (unpack_bilevel(bits, *width as usize, *height as usize), *width, *height, 1)
+                }
+            },
+        }
+    }
+}
+
+impl ByteSize for StoredImage {
+    fn byte_size(&self) -> usize {
+        match self {
+            StoredImage::Image(img) => img.as_bytes().len(),
+            StoredImage::Plane { plane, .. } => match plane {
+                PixelPlane::Rgba(buf) | PixelPlane::Gray(buf) | PixelPlane::Bilevel(buf) => buf.len(),
+            },
+        }
+    }
+}
+
+// 取得待ちの結果画像・タイルピラミッド（容量を超えたら古いものから破棄）
+// 取り出すときはArcを複製してすぐロックを外し、エンコードはロックの外で行う
+pub(crate) struct ImageStore<T> {
+    pub(crate) images: HashMap<u64, std::sync::Arc<T>>,
+    pub(crate) order: VecDeque<u64>,
+    pub(crate) next_id: u64,
+    pub(crate) bytes: usize,
+    pub(crate) budget_bytes: usize,
+}
+
+impl<T: ByteSize> ImageStore<T> {
+    pub(crate) fn new(budget_bytes: usize) -> Self {
+        Self {
+            images: HashMap::new(),
+            order: VecDeque::new(),
+            next_id: 1,
+            bytes: 0,
+            budget_bytes,
+        }
+    }
+
+    // 追加した画像自体は容量を超えていても残す（取得される前に消えないように）
+    pub(crate) fn insert(&mut self, image: T) -> u64 {
+        let size = image.byte_size();
+        while self.bytes + size > self.budget_bytes {
+            match self.order.pop_front() {
+                Some(oldest) => self.remove(oldest),
+                None => break,
+            }
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.order.push_back(id);
+        self.bytes += size;
+        self.images.insert(id, std::sync::Arc::new(image));
+        id
+    }
+
+    pub(crate) fn get(&self, id: u64) -> Option<std::sync::Arc<T>> {
+        self.images.get(&id).cloned()
+    }
+
+    pub(crate) fn remove(&mut self, id: u64) {
+        if let Some(image) = self.images.remove(&id) {
+            self.bytes -= image.byte_size();
+        }
+        self.order.retain(|&i| i != id);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.images.clear();
+        self.order.clear();
+        self.bytes = 0;
+    }
+}
+
+// WindowsとAndroidのWebViewはカスタムスキームを http://<scheme>.localhost で扱う
+pub(crate) fn image_uri(path: &str) -> String {
+    if cfg!(any(windows, target_os = "android")) {
+        format!("http://{}.localhost/{}", IMAGE_URI_SCHEME, path)
+    } else {
+        format!("{}://localhost/{}", IMAGE_URI_SCHEME, path)
+    }
+}
+
+// キャッシュキー（ファイルパスを含む）をURLのパスに埋め込めるようにする
+pub(crate) fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+pub(crate) fn percent_decode(text: &str) -> Result<String, String> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = text.get(i + 1..i + 3).ok_or("Invalid percent encoding")?;
+            out.push(u8::from_str_radix(hex, 16).map_err(|_| "Invalid percent encoding")?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).map_err(|e| e.to_string())
+}
+
+// 複数の結果画像をまとめて返す（data URLは並列エンコード, binaryは登録してURLを返す）
+pub(crate) fn deliver_images<const N: usize>(
+    state: &AppState, delivery: Option<ImageDelivery>, images: [StoredImage; N],
+) -> Result<[String; N], String> {
+    let urls: Vec<String> = match delivery.unwrap_or_default() {
+        ImageDelivery::DataUrl => images
+            .par_iter()
+            .map(StoredImage::to_data_url)
+            .collect::<Result<Vec<_>, String>>()?,
+        ImageDelivery::Binary => {
+            let mut store = state.image_store.lock().map_err(|e| e.to_string())?;
+            images
+                .into_iter()
+                .map(|img| image_uri(&format!("image/{}", store.insert(img))))
+                .collect()
+        }
+    };
+    urls.try_into().map_err(|_| "Image count mismatch".to_string())
+}
+
+// 画像データの実体（PNGかraw）
+pub(crate) struct ImageBytes {
+    pub(crate) data: Vec<u8>,
+    pub(crate) content_type: &'static str,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) channels: Option<u8>, // rawの場合のチャンネル数
+}
+
+// StoredImageをPNGかrawで書き出す
+pub(crate) fn stored_image_bytes(image: &StoredImage, raw: bool) -> Result<ImageBytes, String> {
+    if raw {
+        let (data, width, height, channels) = image.to_raw();
+        return Ok(ImageBytes {
+            data, content_type: "application/octet-stream", width, height, channels: Some(channels),
+        });
+    }
+    let (width, height) = image.dimensions();
+    let data = image.to_fast_png()?;
+    Ok(ImageBytes { data, content_type: "image/png", width, height, channels: None })
+}
+
+// URLのパス（image/<id>, tiles/<id>/<level>/<col>_<row>.png, cache/<キャッシュキー>）から画像データを取り出す
+pub(crate) fn resolve_image_bytes(state: &AppState, path: &str, raw: bool) -> Result<Option<ImageBytes>, String> {
+    let path = path.trim_start_matches('/');
+    if let Some(id) = path.strip_prefix("image/") {
+        let id: u64 = id.parse().map_err(|_| format!("Invalid image id: {}", id))?;
+        // 原寸のPNGエンコードは時間がかかるのでロックを外してから行う
+        let image = state.image_store.lock().map_err(|e| e.to_string())?.get(id);
+        return image.map(|image| stored_image_bytes(&image, raw)).transpose();
+    }
+    if let Some(tile) = path.strip_prefix("tiles/") {
+        let (id, level, col, row) = parse_tile_path(tile).ok_or_else(|| format!("Invalid tile path: {}", tile))?;
+        // タイルの切り出しとエンコードはロックの外で行う
+        let Some(pyramid) = state.tile_store.lock().map_err(|e| e.to_string())?.get(id) else {
+            return Ok(None);
+        };
+        return match pyramid.tile(level, col, row)? {
+            Some(img) => stored_image_bytes(&StoredImage::Image(img), raw).map(Some),
+            None => Ok(None),
+        };
+    }
+    if let Some(key) = path.strip_prefix("cache/") {
+        let key = percent_decode(key)?;
+        // 返したURLの取得はヒット・ミスに数えない（decode_and_resize_image側で数え済み）
+        let (data, width, height, source) = {
+            let cache = state.image_cache.lock().map_err(|e| e.to_string())?;
+            match cache.peek(&key) {
+                Some(cached) => (cached.data.clone(), cached.width, cached.height, cached.source.clone()),
+                None => return Ok(None),
+            }
+        };
+        // 元ファイルが変わっていたら返さない（stat はロックの外で行う）
+        if !source.is_current() {
+            return Ok(None);
+        }
+        // キャッシュはPNGで持っているのでrawはデコードして返す
+        if raw {
+            let img = image::load_from_memory(&data).map_err(|e| format!("Failed to decode PNG: {}", e))?;
+            let data = img.to_rgba8().into_raw();
+            return Ok(Some(ImageBytes {
+                data, content_type: "application/octet-stream", width, height, channels: Some(4),
+            }));
+        }
+        return Ok(Some(ImageBytes { data, content_type: "image/png", width, height, channels: None }));
+    }
+    Err(format!("Unknown image path: {}", path))
+}
+
+// 結果画像を読めるのはアプリ自身のページだけにする（開発時はdevサーバーも許可）
+pub(crate) fn allowed_cors_origin(origin: Option<&str>) -> Option<&str> {
+    const APP_ORIGINS: [&str; 3] = ["tauri://localhost", "http://tauri.localhost", "https://tauri.localhost"];
+    const DEV_ORIGIN: &str = "http://localhost:1420"; // tauri.conf.json の devUrl
+    origin.filter(|o| APP_ORIGINS.contains(o) || (cfg!(debug_assertions) && *o == DEV_ORIGIN))
+}
+
+// kenban:// スキームのハンドラ（?format=raw で無圧縮の画素を返す）
+pub(crate) fn handle_image_request<R: tauri::Runtime>(
+    ctx: tauri::UriSchemeContext<'_, R>, request: tauri::http::Request<Vec<u8>>,
+) -> tauri::http::Response<Vec<u8>> {
+    let raw = request
+        .uri()
+        .query()
+        .is_some_and(|q| q.split('&').any(|kv| kv == "format=raw"));
+    let state = ctx.app_handle().state::<AppState>();
+    let (status, bytes) = match resolve_image_bytes(&state, request.uri().path(), raw) {
+        Ok(Some(bytes)) => (200, Ok(bytes)),
+        Ok(None) => (404, Err("Image not found".to_string())),
+        Err(e) => (400, Err(e)),
+    };
+    let origin = request.headers().get("Origin").and_then(|v| v.to_str().ok());
+    let builder = tauri::http::Response::builder()
+        .status(status)
+        .header("Vary", "Origin")
+        .header("Cache-Control", "no-store");
+    let builder = match allowed_cors_origin(origin) {
+        Some(origin) => builder
+            .header("Access-Control-Allow-Origin", origin)
+            .header("Access-Control-Expose-Headers", "X-Image-Width, X-Image-Height, X-Image-Channels"),
+        None => builder,
+    };
+    let response = match bytes {
+        Ok(bytes) => {
+            let builder = builder
+                .header("Content-Type", bytes.content_type)
+                .header("X-Image-Width", bytes.width)
+                .header("X-Image-Height", bytes.height);
+            let builder = match bytes.channels {
+                Some(channels) => builder.header("X-Image-Channels", channels as u32),
+                None => builder,
+            };
+            builder.body(bytes.data)
+        }
+        Err(e) => builder.header("Content-Type", "text/plain").body(e.into_bytes()),
+    };
+    response.unwrap_or_else(|_| tauri::http::Response::new(Vec::new()))
+}
+
+// IPC経由で画像のバイト列を取得（スキームが使えない環境向け）
+#[tauri::command]
+pub(crate) fn fetch_image(state: State<'_, AppState>, path: String, raw: Option<bool>) -> Result<tauri::ipc::Response, String> {
+    // URLをそのまま渡されても良いようにパス部分だけ使う
+    let path = path
+        .split_once(".localhost/")
+        .or_else(|| path.split_once("://localhost/"))
+        .map_or(path.as_str(), |(_, p)| p);
+    let path = path.split('?').next().unwrap_or(path);
+    match resolve_image_bytes(&state, path, raw.unwrap_or(false))? {
+        Some(bytes) => Ok(tauri::ipc::Response::new(bytes.data)),
+        None => Err(format!("Image not found: {}", path)),
+    }
+}
+
+// 結果画像のURL（.../image/<id>）からIDを取り出す
+pub(crate) fn stored_image_id(url: &str) -> Option<u64> {
+    let (_, id) = url.split_once("/image/")?;
+    id.split('?').next()?.parse().ok()
+}
+
+// 取得済みの結果画像を解放
+#[tauri::command]
+pub(crate) fn release_images(state: State<'_, AppState>, urls: Vec<String>) -> Result<(), String> {
+    let mut store = state.image_store.lock().map_err(|e| e.to_string())?;
+    for url in urls {
+        if let Some(id) = stored_image_id(&url) {
+            store.remove(id);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_encoding_round_trips_japanese_paths() {
+        let paths = [
+            "C:\\原稿\\第1話\\p001 修正版.psd",
+            "/Users/作者/ドキュメント/検版/100%完成#2.tif",
+            "|cache|key?w=800&h=600",
+            "",
+        ];
+        for path in paths {
+            let encoded = percent_encode(path);
+            assert!(encoded.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.~%".contains(&b)));
+            assert_eq!(percent_decode(&encoded).unwrap(), path);
+        }
+        assert_eq!(percent_encode("原"), "%E5%8E%9F");
+        assert_eq!(percent_decode("%e5%8e%9f").unwrap(), "原");
+    }
+
+    #[test]
+    fn percent_decode_rejects_malformed_input() {
+        assert!(percent_decode("abc%").is_err());
+        assert!(percent_decode("abc%4").is_err());
+        assert!(percent_decode("%ZZ").is_err());
+        assert!(percent_decode("%E5%8E").is_err()); // UTF-8として不完全
+        assert!(percent_decode("%E5%").is_err());
+    }
+
+    #[test]
+    fn cors_is_limited_to_app_origins() {
+        assert_eq!(allowed_cors_origin(Some("tauri://localhost")), Some("tauri://localhost"));
+        assert_eq!(allowed_cors_origin(Some("http://tauri.localhost")), Some("http://tauri.localhost"));
+        assert_eq!(allowed_cors_origin(Some("https://example.com")), None);
+        assert_eq!(allowed_cors_origin(Some("null")), None);
+        assert_eq!(allowed_cors_origin(None), None);
+    }
+}
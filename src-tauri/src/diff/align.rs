@@ -0,0 +1,425 @@
+use crate::*;
+
+// ============== 位置合わせ（レジストレーション） ==============
+
+// 位置合わせモード（未指定なら平行移動のみ補正, 補正しない場合は None を指定）
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum AlignMode {
+    None,
+    #[default]
+    Translation, // 平行移動のみ
+    Similarity,  // 平行移動 + 微小回転 + 拡縮
+}
+
+// 検出した変換（Aの座標(x, y)がBの(x + dx, y + dy)に対応 = Bが(dx, dy)ずれている）
+#[derive(Serialize, Clone, Copy)]
+pub(crate) struct Registration {
+    pub(crate) dx: f64,
+    pub(crate) dy: f64,
+    pub(crate) rotation_deg: f64, // 画像中心まわりの回転（度）
+    pub(crate) scale: f64,
+    pub(crate) residual: f64, // 位置合わせ後の平均輝度差 (0-255)
+}
+
+// 探索用の変換パラメータ（angleはラジアン）
+#[derive(Clone, Copy)]
+pub(crate) struct AlignParams {
+    pub(crate) dx: f64,
+    pub(crate) dy: f64,
+    pub(crate) angle: f64,
+    pub(crate) scale: f64,
+}
+
+impl AlignParams {
+    pub(crate) fn identity() -> Self {
+        Self { dx: 0.0, dy: 0.0, angle: 0.0, scale: 1.0 }
+    }
+
+    pub(crate) fn is_pure_translation(&self) -> bool {
+        self.angle == 0.0 && self.scale == 1.0
+    }
+
+    // A側座標 → B側座標（cx, cyは回転中心）
+    pub(crate) fn map(&self, x: f64, y: f64, cx: f64, cy: f64) -> (f64, f64) {
+        let (sin, cos) = self.angle.sin_cos();
+        let ux = x - cx;
+        let uy = y - cy;
+        (
+            cx + self.scale * (cos * ux - sin * uy) + self.dx,
+            cy + self.scale * (sin * ux + cos * uy) + self.dy,
+        )
+    }
+}
+
+// 位置合わせ用の輝度プレーン
+pub(crate) struct LumaPlane {
+    pub(crate) data: Vec<f32>,
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+}
+
+impl LumaPlane {
+    // channels: 1 = グレー, 4 = RGBA
+    pub(crate) fn from_pixels(buf: &[u8], width: u32, height: u32, channels: usize) -> Self {
+        let data = if channels == 1 {
+            buf.par_iter().map(|&v| v as f32).collect()
+        } else {
+            buf.par_chunks(channels)
+                .map(|p| (p[0] as f32 * 0.299) + (p[1] as f32 * 0.587) + (p[2] as f32 * 0.114))
+                .collect()
+        };
+        Self { data, width: width as usize, height: height as usize }
+    }
+
+    pub(crate) fn from_view(view: PixelView, width: u32, height: u32) -> Self {
+        match view {
+            PixelView::Rgba(buf) => Self::from_pixels(buf, width, height, 4),
+            PixelView::Gray(buf) => Self::from_pixels(buf, width, height, 1),
+            PixelView::Bilevel(bits) => {
+                let gray = unpack_bilevel(bits, width as usize, height as usize);
+                Self::from_pixels(&gray, width, height, 1)
+            }
+        }
+    }
+
+    // 2x2平均で1/2に縮小
+    pub(crate) fn downsample(&self) -> Self {
+        let w = (self.width / 2).max(1);
+        let h = (self.height / 2).max(1);
+        let src_w = self.width;
+        let data = (0..h)
+            .into_par_iter()
+            .flat_map_iter(|y| {
+                let y0 = (y * 2).min(self.height - 1);
+                let y1 = (y * 2 + 1).min(self.height - 1);
+                (0..w).map(move |x| {
+                    let x0 = (x * 2).min(src_w - 1);
+                    let x1 = (x * 2 + 1).min(src_w - 1);
+                    (self.data[y0 * src_w + x0] + self.data[y0 * src_w + x1]
+                        + self.data[y1 * src_w + x0] + self.data[y1 * src_w + x1]) * 0.25
+                })
+            })
+            .collect();
+        Self { data, width: w, height: h }
+    }
+
+    // 3x3平均（端は範囲内の画素のみ）
+    pub(crate) fn box_blur3(&self) -> Self {
+        let (w, h) = (self.width, self.height);
+        let data = (0..h)
+            .into_par_iter()
+            .flat_map_iter(|y| {
+                let (y0, y1) = (y.saturating_sub(1), (y + 1).min(h - 1));
+                (0..w).map(move |x| {
+                    let (x0, x1) = (x.saturating_sub(1), (x + 1).min(w - 1));
+                    let mut sum = 0f32;
+                    for yy in y0..=y1 {
+                        sum += self.data[yy * w + x0..=yy * w + x1].iter().sum::<f32>();
+                    }
+                    sum / ((x1 - x0 + 1) * (y1 - y0 + 1)) as f32
+                })
+            })
+            .collect();
+        Self { data, width: w, height: h }
+    }
+
+    // バイリニア補間（範囲外はNone）
+    pub(crate) fn sample(&self, x: f64, y: f64) -> Option<f32> {
+        if x < 0.0 || y < 0.0 || x > (self.width - 1) as f64 || y > (self.height - 1) as f64 {
+            return None;
+        }
+        let x0 = x.floor() as usize;
+        let y0 = y.floor() as usize;
+        let x1 = (x0 + 1).min(self.width - 1);
+        let y1 = (y0 + 1).min(self.height - 1);
+        let fx = (x - x0 as f64) as f32;
+        let fy = (y - y0 as f64) as f32;
+        let top = self.data[y0 * self.width + x0] * (1.0 - fx) + self.data[y0 * self.width + x1] * fx;
+        let bottom = self.data[y1 * self.width + x0] * (1.0 - fx) + self.data[y1 * self.width + x1] * fx;
+        Some(top * (1.0 - fy) + bottom * fy)
+    }
+}
+
+// 変換パラメータでBをAに重ねたときの平均輝度差（重なりが半分未満なら無限大）
+pub(crate) fn alignment_cost(a: &LumaPlane, b: &LumaPlane, p: &AlignParams) -> f64 {
+    // 大きい画像は間引いて評価（約65,000点）
+    let stride = (((a.width * a.height) as f64 / 65536.0).sqrt().floor() as usize).max(1);
+    let cx = (a.width - 1) as f64 / 2.0;
+    let cy = (a.height - 1) as f64 / 2.0;
+
+    let (sum, count, total) = (0..a.height)
+        .into_par_iter()
+        .step_by(stride)
+        .map(|y| {
+            let mut sum = 0f64;
+            let mut count = 0usize;
+            let mut total = 0usize;
+            for x in (0..a.width).step_by(stride) {
+                total += 1;
+                let (bx, by) = p.map(x as f64, y as f64, cx, cy);
+                if let Some(vb) = b.sample(bx, by) {
+                    sum += (a.data[y * a.width + x] - vb).abs() as f64;
+                    count += 1;
+                }
+            }
+            (sum, count, total)
+        })
+        .reduce(|| (0.0, 0, 0), |l, r| (l.0 + r.0, l.1 + r.1, l.2 + r.2));
+
+    if count == 0 || count * 2 < total {
+        f64::INFINITY
+    } else {
+        sum / count as f64
+    }
+}
+
+// 各パラメータを±1px / ±stepずつ動かして改善がなくなるまで山登り
+pub(crate) fn refine_alignment(
+    a: &LumaPlane, b: &LumaPlane, mut p: AlignParams, mut cost: f64,
+    angle_step: f64, scale_step: f64, similarity: bool,
+) -> (AlignParams, f64) {
+    for _ in 0..32 {
+        let mut candidates = vec![
+            AlignParams { dx: p.dx + 1.0, ..p },
+            AlignParams { dx: p.dx - 1.0, ..p },
+            AlignParams { dy: p.dy + 1.0, ..p },
+            AlignParams { dy: p.dy - 1.0, ..p },
+        ];
+        if similarity {
+            candidates.push(AlignParams { angle: p.angle + angle_step, ..p });
+            candidates.push(AlignParams { angle: p.angle - angle_step, ..p });
+            candidates.push(AlignParams { scale: p.scale + scale_step, ..p });
+            candidates.push(AlignParams { scale: p.scale - scale_step, ..p });
+        }
+        let best = candidates
+            .into_par_iter()
+            .map(|c| (c, alignment_cost(a, b, &c)))
+            .min_by(|l, r| l.1.total_cmp(&r.1));
+        match best {
+            Some((c, c_cost)) if c_cost < cost - 1e-6 => {
+                p = c;
+                cost = c_cost;
+            }
+            _ => break,
+        }
+    }
+    (p, cost)
+}
+
+// ピラミッド探索でBのAに対するずれを推定
+pub(crate) fn estimate_registration(
+    a: &[u8], b: &[u8], width: u32, height: u32, channels: usize, similarity: bool,
+) -> (AlignParams, Registration) {
+    // ピラミッド構築（最上位が256px以下になるまで）
+    let mut pyramid = vec![(
+        LumaPlane::from_pixels(a, width, height, channels),
+        LumaPlane::from_pixels(b, width, height, channels),
+    )];
+    while pyramid.len() < 8 {
+        let (top_a, top_b) = pyramid.last().unwrap();
+        if top_a.width.max(top_a.height) <= 256 {
+            break;
+        }
+        let next = (top_a.downsample(), top_b.downsample());
+        pyramid.push(next);
+    }
+    let top_level = pyramid.len() - 1;
+
+    // 最上位: 平行移動を全探索（フル解像度で長辺の3%、最低8px）
+    let (coarse_a, coarse_b) = &pyramid[top_level];
+    let max_shift_full = (width.max(height) as f64 * 0.03).max(8.0);
+    let r = (max_shift_full / (1u32 << top_level) as f64).ceil() as i32;
+    let shifts: Vec<(i32, i32)> = (-r..=r).flat_map(|dy| (-r..=r).map(move |dx| (dx, dy))).collect();
+    let (mut params, mut cost) = shifts
+        .into_par_iter()
+        .map(|(dx, dy)| {
+            let p = AlignParams { dx: dx as f64, dy: dy as f64, ..AlignParams::identity() };
+            (p, alignment_cost(coarse_a, coarse_b, &p))
+        })
+        .min_by(|l, r| l.1.total_cmp(&r.1))
+        .unwrap_or((AlignParams::identity(), f64::INFINITY));
+
+    // 回転・拡縮の粗い格子探索（±1度, ±2%）
+    if similarity {
+        let grid: Vec<AlignParams> = (-4..=4)
+            .flat_map(|ai| (-4..=4).map(move |si| (ai, si)))
+            .map(|(ai, si)| AlignParams {
+                angle: (ai as f64 * 0.25).to_radians(),
+                scale: 1.0 + si as f64 * 0.005,
+                ..params
+            })
+            .collect();
+        if let Some((p, c)) = grid
+            .into_par_iter()
+            .map(|p| (p, alignment_cost(coarse_a, coarse_b, &p)))
+            .min_by(|l, r| l.1.total_cmp(&r.1))
+        {
+            if c < cost {
+                params = p;
+                cost = c;
+            }
+        }
+    }
+
+    // 上位から順に精密化
+    for level in (0..=top_level).rev() {
+        let (la, lb) = &pyramid[level];
+        if level != top_level {
+            params.dx *= 2.0;
+            params.dy *= 2.0;
+            cost = alignment_cost(la, lb, &params);
+        }
+        let depth = (top_level - level) as i32;
+        let angle_step = (0.25f64 / 2f64.powi(depth + 1)).max(0.01).to_radians();
+        let scale_step = (0.005 / 2f64.powi(depth + 1)).max(0.0005);
+        (params, cost) = refine_alignment(la, lb, params, cost, angle_step, scale_step, similarity);
+    }
+
+    // 微小な回転・拡縮は誤検出とみなして平行移動のみに戻す
+    let (full_a, full_b) = &pyramid[0];
+    if !params.is_pure_translation() {
+        let translation_only = AlignParams {
+            dx: params.dx.round(),
+            dy: params.dy.round(),
+            ..AlignParams::identity()
+        };
+        let translation_cost = alignment_cost(full_a, full_b, &translation_only);
+        let negligible = params.angle.abs() < 0.01f64.to_radians() && (params.scale - 1.0).abs() < 0.0005;
+        if negligible || cost >= translation_cost * 0.98 {
+            params = translation_only;
+            cost = translation_cost;
+        }
+    }
+
+    let registration = Registration {
+        dx: params.dx,
+        dy: params.dy,
+        rotation_deg: (params.angle.to_degrees() * 1000.0).round() / 1000.0,
+        scale: (params.scale * 10000.0).round() / 10000.0,
+        residual: if cost.is_finite() { (cost * 100.0).round() / 100.0 } else { 0.0 },
+    };
+    (params, registration)
+}
+
+// BをAの座標系へ変換（範囲外は白で埋める, channels: 1 = グレー, 4 = RGBA）
+pub(crate) fn warp_pixels(src: &[u8], width: u32, height: u32, channels: usize, p: &AlignParams) -> Vec<u8> {
+    let w = width as usize;
+    let h = height as usize;
+    let n = channels;
+    let cx = (w - 1) as f64 / 2.0;
+    let cy = (h - 1) as f64 / 2.0;
+    let mut out = vec![255u8; w * h * n];
+
+    out.par_chunks_mut(w * n).enumerate().for_each(|(y, row)| {
+        for x in 0..w {
+            let (bx, by) = p.map(x as f64, y as f64, cx, cy);
+            let di = x * n;
+            if p.is_pure_translation() && bx.fract() == 0.0 && by.fract() == 0.0 {
+                // 整数シフトは補間なしでコピー（線画のにじみ防止）
+                if bx >= 0.0 && by >= 0.0 && (bx as usize) < w && (by as usize) < h {
+                    let si = ((by as usize) * w + bx as usize) * n;
+                    row[di..di + n].copy_from_slice(&src[si..si + n]);
+                }
+                continue;
+            }
+            if bx < 0.0 || by < 0.0 || bx > (w - 1) as f64 || by > (h - 1) as f64 {
+                continue;
+            }
+            let x0 = bx.floor() as usize;
+            let y0 = by.floor() as usize;
+            let x1 = (x0 + 1).min(w - 1);
+            let y1 = (y0 + 1).min(h - 1);
+            let fx = bx - x0 as f64;
+            let fy = by - y0 as f64;
+            for c in 0..n {
+                let v00 = src[(y0 * w + x0) * n + c] as f64;
+                let v01 = src[(y0 * w + x1) * n + c] as f64;
+                let v10 = src[(y1 * w + x0) * n + c] as f64;
+                let v11 = src[(y1 * w + x1) * n + c] as f64;
+                let top = v00 * (1.0 - fx) + v01 * fx;
+                let bottom = v10 * (1.0 - fx) + v11 * fx;
+                row[di + c] = (top * (1.0 - fy) + bottom * fy).round() as u8;
+            }
+        }
+    });
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 線画風の矩形を散らしたグレー画像
+    fn page(width: usize, height: usize) -> Vec<u8> {
+        let mut buf = vec![255u8; width * height];
+        let mut seed = 12345u32;
+        let mut next = |n: usize| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as usize % n
+        };
+        for _ in 0..60 {
+            let (x, y, w, h) = (next(width - 20), next(height - 20), 3 + next(16), 3 + next(16));
+            let v = next(160) as u8;
+            for row in buf[y * width..(y + h) * width].chunks_mut(width) {
+                row[x..x + w].fill(v);
+            }
+        }
+        buf
+    }
+
+    // Bの(x, y)にAの(x - dx, y - dy)を置く（範囲外は白）
+    fn shift(src: &[u8], width: usize, height: usize, dx: i32, dy: i32) -> Vec<u8> {
+        let mut out = vec![255u8; src.len()];
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                let (sx, sy) = (x - dx, y - dy);
+                if sx >= 0 && sy >= 0 && sx < width as i32 && sy < height as i32 {
+                    out[(y * width as i32 + x) as usize] = src[(sy * width as i32 + sx) as usize];
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn detects_translation_offset() {
+        let (w, h) = (320usize, 240usize);
+        let a = page(w, h);
+        let b = shift(&a, w, h, 2, -1);
+        let (params, registration) = estimate_registration(&a, &b, w as u32, h as u32, 1, false);
+        assert!(params.is_pure_translation());
+        assert_eq!((registration.dx, registration.dy), (2.0, -1.0));
+
+        // Bを戻すとAに重なる（ずらした分の端以外）
+        let warped = warp_pixels(&b, w as u32, h as u32, 1, &params);
+        for y in 1..h - 1 {
+            assert_eq!(warped[y * w..y * w + w - 2], a[y * w..y * w + w - 2], "row {}", y);
+        }
+    }
+
+    #[test]
+    fn identical_images_need_no_alignment() {
+        let (w, h) = (200usize, 150usize);
+        let a = page(w, h);
+        let (params, registration) = estimate_registration(&a, &a, w as u32, h as u32, 1, true);
+        assert!(params.is_pure_translation());
+        assert_eq!((params.dx, params.dy), (0.0, 0.0));
+        assert_eq!(registration.residual, 0.0);
+    }
+
+    #[test]
+    fn warp_interpolates_and_fills_outside() {
+        // 2x1 RGBA: 黒と白の中間を半画素ずらしで補間する
+        let src = [0, 0, 0, 255, 200, 200, 200, 255];
+        let half = AlignParams { dx: 0.5, ..AlignParams::identity() };
+        let out = warp_pixels(&src, 2, 1, 4, &half);
+        assert_eq!(out[..4], [100, 100, 100, 255]);
+        // 右端は範囲外なので白
+        assert_eq!(out[4..], [255, 255, 255, 255]);
+
+        let whole = AlignParams { dx: -1.0, ..AlignParams::identity() };
+        let out = warp_pixels(&src, 2, 1, 4, &whole);
+        assert_eq!(out, [255, 255, 255, 255, 0, 0, 0, 255]);
+    }
+}
@@ -0,0 +1,328 @@
+use crate::*;
+
+// ============== マーカーのクラスタリング ==============
+
+// Union-Findクラスタリング → DiffMarkerリスト
+// connectivity: 同じクラスタとみなすグリッドセルの距離（1 = 隣接8セル, 0 = 同じセルのみ）
+pub(crate) fn cluster_markers(pixels: &[DiffPixel], params: &DiffParams) -> Vec<DiffMarker> {
+    if pixels.is_empty() {
+        return Vec::new();
+    }
+    let DiffParams { grid_size, min_cluster, min_radius, marker_padding, connectivity, .. } = *params;
+    let with_hull = params.with_hull;
+
+    // グリッドにピクセルを分配
+    struct GridCell {
+        gx: i32,
+        gy: i32,
+        count: u32,
+        min_x: u32,
+        max_x: u32,
+        min_y: u32,
+        max_y: u32,
+        delta_sum: f64,
+        delta_max: f32,
+        kinds: [u32; 3], // DiffKind別のピクセル数
+        rows: HashMap<u32, (u32, u32)>, // 凸包用: 行ごとの左端・右端（with_hull時のみ）
+    }
+
+    let mut grid: HashMap<(i32, i32), GridCell> = HashMap::new();
+    for p in pixels {
+        let gx = (p.x / grid_size) as i32;
+        let gy = (p.y / grid_size) as i32;
+        let cell = grid.entry((gx, gy)).or_insert(GridCell {
+            gx, gy, count: 0, min_x: p.x, max_x: p.x, min_y: p.y, max_y: p.y,
+            delta_sum: 0.0, delta_max: 0.0, kinds: [0; 3], rows: HashMap::new(),
+        });
+        if with_hull {
+            let ext = cell.rows.entry(p.y).or_insert((p.x, p.x));
+            ext.0 = ext.0.min(p.x);
+            ext.1 = ext.1.max(p.x);
+        }
+        cell.count += 1;
+        cell.kinds[p.kind.index()] += 1;
+        cell.min_x = cell.min_x.min(p.x);
+        cell.max_x = cell.max_x.max(p.x);
+        cell.min_y = cell.min_y.min(p.y);
+        cell.max_y = cell.max_y.max(p.y);
+        cell.delta_sum += p.delta as f64;
+        cell.delta_max = cell.delta_max.max(p.delta);
+    }
+
+    let cells: Vec<GridCell> = grid.into_values().collect();
+    if cells.is_empty() {
+        return Vec::new();
+    }
+    let index: HashMap<(i32, i32), usize> =
+        cells.iter().enumerate().map(|(i, c)| ((c.gx, c.gy), i)).collect();
+
+    // Union-Find（近傍セルだけを引くので計算量はセル数に比例）
+    let mut parent: Vec<usize> = (0..cells.len()).collect();
+    let find = |parent: &mut Vec<usize>, mut i: usize| -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    };
+
+    let reach = connectivity as i32;
+    for (i, cell) in cells.iter().enumerate() {
+        // 自分より後ろ側の近傍だけを見る（前側は相手のセルから処理される）
+        for dy in 0..=reach {
+            for dx in -reach..=reach {
+                if dy == 0 && dx <= 0 {
+                    continue;
+                }
+                let Some(&j) = index.get(&(cell.gx + dx, cell.gy + dy)) else { continue };
+                let pi = find(&mut parent, i);
+                let pj = find(&mut parent, j);
+                if pi != pj {
+                    parent[pi] = pj;
+                }
+            }
+        }
+    }
+
+    // グループ集約（セルと同じ構造で合算）
+    let mut groups: HashMap<usize, GridCell> = HashMap::new();
+    for (i, cell) in cells.iter().enumerate() {
+        let root = find(&mut parent, i);
+        let g = groups.entry(root).or_insert(GridCell {
+            gx: cell.gx, gy: cell.gy, count: 0, min_x: u32::MAX, max_x: 0, min_y: u32::MAX, max_y: 0,
+            delta_sum: 0.0, delta_max: 0.0, kinds: [0; 3], rows: HashMap::new(),
+        });
+        for (&y, &(x1, x2)) in &cell.rows {
+            let ext = g.rows.entry(y).or_insert((x1, x2));
+            ext.0 = ext.0.min(x1);
+            ext.1 = ext.1.max(x2);
+        }
+        for (k, n) in g.kinds.iter_mut().zip(cell.kinds) {
+            *k += n;
+        }
+        g.min_x = g.min_x.min(cell.min_x);
+        g.max_x = g.max_x.max(cell.max_x);
+        g.min_y = g.min_y.min(cell.min_y);
+        g.max_y = g.max_y.max(cell.max_y);
+        g.count += cell.count;
+        g.delta_sum += cell.delta_sum;
+        g.delta_max = g.delta_max.max(cell.delta_max);
+    }
+
+    let mut markers: Vec<DiffMarker> = groups.values()
+        .filter(|g| g.count >= min_cluster)
+        .map(|g| {
+            let cx = (g.min_x as f64 + g.max_x as f64) / 2.0;
+            let cy = (g.min_y as f64 + g.max_y as f64) / 2.0;
+            let radius_x = (g.max_x as f64 - g.min_x as f64) / 2.0 + marker_padding;
+            let radius_y = (g.max_y as f64 - g.min_y as f64) / 2.0 + marker_padding;
+            let marker_radius = min_radius.max(radius_x.max(radius_y));
+            DiffMarker {
+                x: cx,
+                y: cy,
+                radius: marker_radius,
+                count: g.count,
+                bbox: CropBounds { left: g.min_x, top: g.min_y, right: g.max_x + 1, bottom: g.max_y + 1 },
+                hull: with_hull.then(|| hull_from_rows(&g.rows)),
+                max_delta: (g.delta_max as f64 * 100.0).round() / 100.0,
+                mean_delta: (g.delta_sum / g.count as f64 * 100.0).round() / 100.0,
+                added: g.kinds[DiffKind::Added.index()],
+                removed: g.kinds[DiffKind::Removed.index()],
+                changed: g.kinds[DiffKind::Changed.index()],
+                probability: 0.0, // score_markersで設定
+            }
+        })
+        .collect();
+
+    markers.sort_by_key(|m| std::cmp::Reverse(m.count));
+    markers
+}
+
+// 行ごとの左右端から凸包を作り、2px以内の頂点を間引く（凹んだ形や穴は表さない）
+pub(crate) fn hull_from_rows(rows: &HashMap<u32, (u32, u32)>) -> Vec<Point> {
+    let mut pts: Vec<(f64, f64)> = Vec::with_capacity(rows.len() * 4);
+    for (&y, &(x1, x2)) in rows {
+        let (y, x1, x2) = (y as f64, x1 as f64, x2 as f64 + 1.0);
+        pts.extend([(x1, y), (x1, y + 1.0), (x2, y), (x2, y + 1.0)]);
+    }
+    pts.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    pts.dedup();
+
+    // Andrew's monotone chain
+    let cross = |o: (f64, f64), a: (f64, f64), b: (f64, f64)| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+    let mut hull: Vec<(f64, f64)> = Vec::new();
+    for pass in 0..2 {
+        let start = hull.len();
+        let iter: Box<dyn Iterator<Item = &(f64, f64)>> =
+            if pass == 0 { Box::new(pts.iter()) } else { Box::new(pts.iter().rev()) };
+        for &p in iter {
+            while hull.len() >= start + 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0 {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        hull.pop(); // 終点は次のチェーンの始点と重複
+    }
+
+    simplify_polygon(&hull, 2.0)
+        .into_iter()
+        .map(|(x, y)| Point { x, y })
+        .collect()
+}
+
+// 閉じた多角形をDouglas-Peuckerで簡略化（最も離れた2頂点で分割して両側を処理）
+pub(crate) fn simplify_polygon(poly: &[(f64, f64)], epsilon: f64) -> Vec<(f64, f64)> {
+    if poly.len() <= 4 {
+        return poly.to_vec();
+    }
+    fn dp(pts: &[(f64, f64)], epsilon: f64, out: &mut Vec<(f64, f64)>) {
+        let (a, b) = (pts[0], pts[pts.len() - 1]);
+        let len = ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt().max(f64::EPSILON);
+        let (idx, dist) = pts[1..pts.len() - 1]
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (i + 1, ((b.0 - a.0) * (a.1 - p.1) - (a.0 - p.0) * (b.1 - a.1)).abs() / len))
+            .fold((0, 0.0), |m, c| if c.1 > m.1 { c } else { m });
+        if dist > epsilon {
+            dp(&pts[..=idx], epsilon, out);
+            dp(&pts[idx..], epsilon, out);
+        } else {
+            out.push(a);
+        }
+    }
+    let far = (1..poly.len())
+        .max_by(|&i, &j| {
+            let d = |k: usize| (poly[k].0 - poly[0].0).powi(2) + (poly[k].1 - poly[0].1).powi(2);
+            d(i).total_cmp(&d(j))
+        })
+        .unwrap_or(0);
+    let mut closed = poly.to_vec();
+    closed.push(poly[0]);
+    let mut out = Vec::new();
+    dp(&closed[..=far], epsilon, &mut out);
+    dp(&closed[far..], epsilon, &mut out);
+    // 小さいクラスタが線分に潰れないよう、多角形にならなければ簡略化しない
+    if out.len() < 3 {
+        return poly.to_vec();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn px(x: u32, y: u32) -> DiffPixel {
+        DiffPixel { x, y, delta: 50.0, kind: DiffKind::Changed }
+    }
+
+    fn params(connectivity: u32) -> DiffParams {
+        DiffParams {
+            grid_size: 10,
+            min_cluster: 1,
+            min_radius: 0.0,
+            marker_padding: 0.0,
+            connectivity,
+            ..DiffParams::defaults(DiffMode::Simple)
+        }
+    }
+
+    fn marker_counts(pixels: &[DiffPixel], connectivity: u32) -> Vec<u32> {
+        cluster_markers(pixels, &params(connectivity)).iter().map(|m| m.count).collect()
+    }
+
+    #[test]
+    fn diagonal_cells_merge_with_connectivity_one() {
+        // セル(0,0)と(1,1)、セル(3,0)と(2,1)は角だけで接する（4近傍では別、8近傍では同じクラスタ）
+        let diagonal = [px(8, 8), px(9, 9), px(11, 11)];
+        let anti_diagonal = [px(31, 8), px(28, 12), px(29, 13)];
+        for pixels in [&diagonal[..], &anti_diagonal[..]] {
+            assert_eq!(marker_counts(pixels, 1), [3]);
+            let mut separate = marker_counts(pixels, 0);
+            separate.sort();
+            assert_eq!(separate, [1, 2]);
+        }
+    }
+
+    #[test]
+    fn connectivity_sets_how_far_cells_reach() {
+        // 1セル空けて並ぶ2つの塊
+        let pixels = [px(1, 1), px(2, 2), px(25, 5)];
+        assert_eq!(marker_counts(&pixels, 1), [2, 1]);
+        assert_eq!(marker_counts(&pixels, 2), [3]);
+        // 斜めに2セル離れた場合も距離2で届く
+        let diagonal = [px(1, 1), px(25, 25)];
+        assert_eq!(marker_counts(&diagonal, 1).len(), 2);
+        assert_eq!(marker_counts(&diagonal, 2), [2]);
+    }
+
+    #[test]
+    fn merged_marker_covers_all_cells() {
+        let pixels = [px(8, 8), px(11, 11), px(15, 19)];
+        let markers = cluster_markers(&pixels, &params(1));
+        assert_eq!(markers.len(), 1);
+        let m = &markers[0];
+        assert_eq!((m.bbox.left, m.bbox.top, m.bbox.right, m.bbox.bottom), (8, 8, 16, 20));
+        assert_eq!((m.x, m.y, m.radius), (11.5, 13.5, 5.5));
+        assert_eq!(m.changed, 3);
+    }
+
+    #[test]
+    fn simplify_keeps_start_and_far_vertices() {
+        // 10x10の正方形の各辺に1px間隔の頂点を並べ、少しだけ揺らす
+        let mut square = Vec::new();
+        for i in 0..10 {
+            square.push((i as f64, if i % 2 == 0 { 0.0 } else { 0.5 }));
+        }
+        for i in 0..10 {
+            square.push((10.0, i as f64));
+        }
+        for i in 0..10 {
+            square.push((10.0 - i as f64, 10.0));
+        }
+        for i in 0..10 {
+            square.push((0.0, 10.0 - i as f64));
+        }
+        let simplified = simplify_polygon(&square, 2.0);
+        assert_eq!(simplified[0], square[0]);
+        assert!(simplified.contains(&(10.0, 10.0))); // 始点から最も遠い頂点で分割される
+        for corner in [(10.0, 0.0), (0.0, 10.0)] {
+            assert!(simplified.contains(&corner));
+        }
+        assert_eq!(simplified.len(), 4);
+        assert!(simplified.iter().all(|p| square.contains(p)));
+        // 4頂点以下はそのまま
+        assert_eq!(simplify_polygon(&square[..3], 2.0), square[..3]);
+    }
+
+    #[test]
+    fn hull_encloses_every_row_extent() {
+        // L字（凹んだ部分は凸包で埋まる）
+        let rows: HashMap<u32, (u32, u32)> = (0..40).map(|y| (y, (0, if y < 30 { 3 } else { 39 }))).collect();
+        let hull: Vec<(f64, f64)> = hull_from_rows(&rows).iter().map(|p| (p.x, p.y)).collect();
+        assert_eq!(hull.len(), 5);
+        for corner in [(0.0, 0.0), (4.0, 0.0), (40.0, 30.0), (40.0, 40.0), (0.0, 40.0)] {
+            assert!(hull.contains(&corner), "{:?} not in {:?}", corner, hull);
+        }
+        // 全ピクセルの角が凸包の内側（反時計回りなので各辺の左側）に入る
+        // 間引いた頂点の分だけ最大2pxはみ出しうる
+        let inside = |p: (f64, f64)| {
+            (0..hull.len()).all(|i| {
+                let (a, b) = (hull[i], hull[(i + 1) % hull.len()]);
+                let len = ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+                (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0) >= -2.0 * len
+            })
+        };
+        for (&y, &(x1, x2)) in &rows {
+            for p in [(x1 as f64, y as f64), (x2 as f64 + 1.0, y as f64 + 1.0)] {
+                assert!(inside(p), "{:?}", p);
+            }
+        }
+
+        let markers = cluster_markers(&[px(3, 3), px(4, 3), px(3, 4)], &DiffParams { with_hull: true, ..params(1) });
+        assert!(markers[0].hull.as_ref().is_some_and(|hull| hull.len() >= 3));
+        assert!(cluster_markers(&[px(3, 3)], &params(1))[0].hull.is_none());
+    }
+}
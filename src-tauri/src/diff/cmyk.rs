@@ -0,0 +1,325 @@
+use crate::*;
+
+// ============== CMYK版ごとの差分 ==============
+
+// CMYK画像（C,M,Y,Kの順にインターリーブ、インク量 0-255 / 255 = 100%）
+pub(crate) struct CmykImage {
+    pub(crate) data: Vec<u8>,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) native: bool, // 元ファイルがCMYKだったか（falseはRGBから簡易変換）
+}
+
+// 版の指定
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Plate {
+    C,
+    M,
+    Y,
+    K,
+}
+
+impl Plate {
+    pub(crate) fn channel(self) -> usize {
+        match self {
+            Plate::C => 0,
+            Plate::M => 1,
+            Plate::Y => 2,
+            Plate::K => 3,
+        }
+    }
+
+    // オーバーレイでの表示色（Kは黒背景で見えるよう白）
+    pub(crate) fn overlay_color(self) -> [u8; 3] {
+        match self {
+            Plate::C => [0, 255, 255],
+            Plate::M => [255, 0, 255],
+            Plate::Y => [255, 255, 0],
+            Plate::K => [255, 255, 255],
+        }
+    }
+}
+
+// RGBA → CMYK（カラープロファイルなしの簡易変換、グレーはK版のみになる）
+pub(crate) fn rgba_to_cmyk(rgba: &[u8]) -> Vec<u8> {
+    rgba.par_chunks(4)
+        .flat_map_iter(|p| {
+            let k = 255 - p[0].max(p[1]).max(p[2]);
+            if k == 255 {
+                return [0, 0, 0, 255];
+            }
+            let denom = (255 - k) as u32;
+            let ink = |v: u8| ((255 - v - k) as u32 * 255 / denom) as u8;
+            [ink(p[0]), ink(p[1]), ink(p[2]), k]
+        })
+        .collect()
+}
+
+// CMYK → RGBA（プレビュー用の簡易変換）
+pub(crate) fn cmyk_to_rgba(cmyk: &[u8]) -> Vec<u8> {
+    cmyk.par_chunks(4)
+        .flat_map_iter(|p| {
+            let white = 255 - p[3] as u32;
+            let ch = |v: u8| ((255 - v as u32) * white / 255) as u8;
+            [ch(p[0]), ch(p[1]), ch(p[2]), 255]
+        })
+        .collect()
+}
+
+// TIFFをCMYKのまま読み込む（CMYKでなければNone）
+pub(crate) fn decode_cmyk_tiff(path: &str) -> Result<Option<CmykImage>, String> {
+    let file = fs::File::open(path).map_err(|e| format!("Failed to open TIFF: {}", e))?;
+    let mut decoder = tiff::decoder::Decoder::new(std::io::BufReader::new(file))
+        .map_err(|e| format!("Failed to read TIFF: {}", e))?
+        .with_limits(tiff::decoder::Limits::unlimited());
+    let channels = match decoder.colortype().map_err(|e| format!("Failed to read TIFF: {}", e))? {
+        tiff::ColorType::CMYK(_) => 4,
+        tiff::ColorType::CMYKA(_) => 5,
+        _ => return Ok(None),
+    };
+    let (width, height) = decoder.dimensions().map_err(|e| format!("Failed to read TIFF: {}", e))?;
+    let raw: Vec<u8> = match decoder.read_image().map_err(|e| format!("Failed to decode TIFF: {}", e))? {
+        tiff::decoder::DecodingResult::U8(v) => v,
+        tiff::decoder::DecodingResult::U16(v) => v.into_iter().map(|x| (x >> 8) as u8).collect(),
+        _ => return Err("Unsupported CMYK TIFF bit depth".to_string()),
+    };
+    // アルファ付きはアルファを捨てる
+    let data = if channels == 5 {
+        raw.chunks_exact(5).flat_map(|p| [p[0], p[1], p[2], p[3]]).collect()
+    } else {
+        raw
+    };
+    Ok(Some(CmykImage { data, width, height, native: true }))
+}
+
+// CMYK PSDを読み込む（CMYKでなければNone）
+// psdクレートはCMYKの4チャネルを反転値のままRGBAの位置に入れるので戻す
+// ※アルファチャネル付き（5チャネル）のCMYK PSDはK版が取得できないため非対応
+pub(crate) fn decode_cmyk_psd(path: &str) -> Result<Option<CmykImage>, String> {
+    let psd = read_psd(path)?;
+    if psd.color_mode() != psd::ColorMode::Cmyk {
+        return Ok(None);
+    }
+    let data = psd.rgba().into_par_iter().map(|v| 255 - v).collect();
+    Ok(Some(CmykImage { data, width: psd.width(), height: psd.height(), native: true }))
+}
+
+// 拡張子で判定してCMYKとしてデコード（RGB/グレー画像は簡易変換）
+pub(crate) fn decode_cmyk_file(path: &str) -> Result<CmykImage, String> {
+    let lower = path.to_lowercase();
+    let native = if lower.ends_with(".psd") {
+        decode_cmyk_psd(path)?
+    } else if lower.ends_with(".tif") || lower.ends_with(".tiff") {
+        decode_cmyk_tiff(path)?
+    } else {
+        None
+    };
+    if let Some(img) = native {
+        return Ok(img);
+    }
+    let img = decode_image_file(path, None)?;
+    let (width, height) = img.dimensions();
+    Ok(CmykImage { data: rgba_to_cmyk(img.to_rgba8().as_raw()), width, height, native: false })
+}
+
+// 指定サイズに揃える（版の値を混ぜないよう最近傍補間）
+pub(crate) fn resize_cmyk(img: CmykImage, width: u32, height: u32) -> Result<CmykImage, String> {
+    if img.width == width && img.height == height {
+        return Ok(img);
+    }
+    let buf: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_raw(img.width, img.height, img.data)
+        .ok_or_else(|| "Failed to create CMYK buffer".to_string())?;
+    let resized = image::imageops::resize(&buf, width, height, FilterType::Nearest);
+    Ok(CmykImage { data: resized.into_raw(), width, height, native: img.native })
+}
+
+// 1版分の差分計算（インク量の差がthresholdを超えたピクセル、rayon行並列）
+pub(crate) fn diff_plate_core(
+    a: &[u8], b: &[u8], width: u32, height: u32, plate: Plate, threshold: u8,
+) -> (Vec<u8>, u32, Vec<DiffPixel>) {
+    let w = width as usize;
+    let ch = plate.channel();
+    let color = plate.overlay_color();
+    let threshold = threshold as i16;
+
+    let rows: Vec<(Vec<u8>, u32, Vec<DiffPixel>)> = (0..height as usize)
+        .into_par_iter()
+        .map(|y| {
+            let mut row_buf = vec![0u8; w * 4];
+            let mut count = 0u32;
+            let mut pixels = Vec::new();
+            for x in 0..w {
+                let i = (y * w + x) * 4 + ch;
+                let d = (a[i] as i16 - b[i] as i16).abs();
+                let di = x * 4;
+                if d > threshold {
+                    row_buf[di..di + 3].copy_from_slice(&color);
+                    count += 1;
+                    // 版のインキ量が増えていれば描き足し
+                    let kind = if b[i] > a[i] { DiffKind::Added } else { DiffKind::Removed };
+                    pixels.push(DiffPixel { x: x as u32, y: y as u32, delta: d as f32, kind });
+                }
+                // 黒背景（alpha=255）
+                row_buf[di + 3] = 255;
+            }
+            (row_buf, count, pixels)
+        })
+        .collect();
+
+    let mut diff_buf = vec![0u8; w * height as usize * 4];
+    let mut total_count = 0u32;
+    let mut all_pixels = Vec::new();
+    for (y, (row_buf, count, pixels)) in rows.into_iter().enumerate() {
+        diff_buf[y * w * 4..(y + 1) * w * 4].copy_from_slice(&row_buf);
+        total_count += count;
+        all_pixels.extend(pixels);
+    }
+    (diff_buf, total_count, all_pixels)
+}
+
+#[derive(Serialize)]
+pub(crate) struct PlateDiffResult {
+    pub(crate) plate: Plate,
+    pub(crate) diff_src: String,
+    pub(crate) has_diff: bool,
+    pub(crate) diff_count: u32,
+    pub(crate) markers: Vec<DiffMarker>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DiffCmykResult {
+    pub(crate) src_a: String, // RGBプレビュー
+    pub(crate) src_b: String,
+    pub(crate) plates: Vec<PlateDiffResult>,
+    pub(crate) has_diff: bool,
+    pub(crate) native_cmyk_a: bool, // falseの場合はRGBからの簡易変換で比較している
+    pub(crate) native_cmyk_b: bool,
+    pub(crate) image_width: u32,
+    pub(crate) image_height: u32,
+}
+
+// CMYK版ごとの差分計算（plates未指定なら4版すべて、モノクロ原稿は ["k"] でK版のみ）
+#[tauri::command]
+#[allow(clippy::too_many_arguments)] // フロントエンドから個別の引数で渡される
+pub(crate) async fn compute_diff_cmyk(
+    app: tauri::AppHandle,
+    path_a: String, path_b: String, threshold: u8, plates: Option<Vec<Plate>>,
+    options: Option<DiffOptions>, delivery: Option<ImageDelivery>, job_id: Option<String>,
+) -> Result<DiffCmykResult, String> {
+    let job = Job::start(&app, job_id)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        diff_cmyk_job(&state, &job, &path_a, &path_b, threshold, plates, options.as_ref(), delivery)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn diff_cmyk_job(
+    state: &AppState, job: &Job,
+    path_a: &str, path_b: &str, threshold: u8, plates: Option<Vec<Plate>>,
+    options: Option<&DiffOptions>, delivery: Option<ImageDelivery>,
+) -> Result<DiffCmykResult, String> {
+    job.phase(JobPhase::Decode, 2);
+    let (img_a, img_b) = rayon::join(
+        || job.check().and_then(|_| decode_cmyk_file(path_a)),
+        || job.check().and_then(|_| decode_cmyk_file(path_b)),
+    );
+    job.check()?;
+    let img_a = img_a?;
+    let img_b = img_b?;
+    job.advance(2);
+
+    let width = img_a.width.max(img_b.width);
+    let height = img_a.height.max(img_b.height);
+    let img_a = resize_cmyk(img_a, width, height)?;
+    let img_b = resize_cmyk(img_b, width, height)?;
+    job.check()?;
+
+    // 版ごとの差分とクラスタリング（マーカーの設定は単純比較と同じ）
+    let plates = plates.unwrap_or_else(|| vec![Plate::C, Plate::M, Plate::Y, Plate::K]);
+    let params = resolve_diff_options(options, DiffMode::Simple)?;
+    job.phase(JobPhase::Diff, plates.len() as u64);
+    let plate_results: Vec<Result<PlateDiffResult, String>> = plates
+        .par_iter()
+        .map(|&plate| {
+            job.check()?;
+            let (diff_buf, diff_count, diff_pixels) =
+                diff_plate_core(&img_a.data, &img_b.data, width, height, plate, threshold);
+            let mut markers = cluster_markers(&diff_pixels, &params);
+            score_markers(&mut markers, &params.probability_model, 255.0);
+            let [diff_src] = deliver_images(state, delivery, [StoredImage::rgba(diff_buf, width, height)])?;
+            job.advance(1);
+            Ok(PlateDiffResult {
+                plate,
+                diff_src,
+                has_diff: diff_count > 0,
+                diff_count,
+                markers,
+            })
+        })
+        .collect();
+    let plate_results = plate_results.into_iter().collect::<Result<Vec<_>, String>>()?;
+    job.check()?;
+
+    job.phase(JobPhase::Encode, 2);
+    let [src_a, src_b] = deliver_images(state, delivery, [
+        StoredImage::rgba(cmyk_to_rgba(&img_a.data), width, height),
+        StoredImage::rgba(cmyk_to_rgba(&img_b.data), width, height),
+    ])?;
+    job.advance(2);
+    job.finish();
+
+    Ok(DiffCmykResult {
+        src_a,
+        src_b,
+        has_diff: plate_results.iter().any(|p| p.has_diff),
+        plates: plate_results,
+        native_cmyk_a: img_a.native,
+        native_cmyk_b: img_b.native,
+        image_width: width,
+        image_height: height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pure_k_change_only_affects_k_plate() {
+        let (w, h) = (64u32, 48u32);
+        // C40% M20% の平網の上に、BだけK版の文字（矩形）を追加
+        let a: Vec<u8> = [102, 51, 0, 0].repeat((w * h) as usize);
+        let mut b = a.clone();
+        for y in 10..20 {
+            for x in 20..40 {
+                b[(y * w as usize + x) * 4 + 3] = 255;
+            }
+        }
+
+        let params = resolve_diff_options(None, DiffMode::Simple).unwrap();
+        for plate in [Plate::C, Plate::M, Plate::Y, Plate::K] {
+            let (overlay, count, pixels) = diff_plate_core(&a, &b, w, h, plate, 5);
+            let markers = cluster_markers(&pixels, &params);
+            if plate == Plate::K {
+                assert_eq!(count, 200);
+                assert!(pixels.iter().all(|p| p.kind == DiffKind::Added));
+                assert_eq!(markers.len(), 1);
+                assert_eq!(overlay[(15 * w as usize + 30) * 4..][..4], [255, 255, 255, 255]);
+            } else {
+                assert_eq!(count, 0, "plate {:?}", plate.channel());
+                assert!(markers.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn gray_converts_to_k_only() {
+        let cmyk = rgba_to_cmyk(&[128, 128, 128, 255, 255, 255, 255, 255, 0, 0, 0, 255]);
+        assert_eq!(cmyk, [0, 0, 0, 127, 0, 0, 0, 0, 0, 0, 0, 255]);
+        assert_eq!(cmyk_to_rgba(&cmyk)[..4], [128, 128, 128, 255]);
+    }
+}
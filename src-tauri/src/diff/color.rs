@@ -0,0 +1,222 @@
+use crate::*;
+
+// ============== 色差（Lab / ΔE） ==============
+
+// 差分判定に使う色差の種類
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ColorMetric {
+    Rgb,    // チャネルごとのRGB差（従来）
+    De76,   // Lab空間のユークリッド距離（高速）
+    De2000, // CIEDE2000
+}
+
+// ピクセル比較方法（閾値込み）
+#[derive(Clone, Copy)]
+pub(crate) enum PixelMetric {
+    Rgb(i16),
+    De76(f32),
+    De2000(f32),
+}
+
+impl PixelMetric {
+    // コマンド引数から生成（ΔE閾値のデフォルト: ΔE76=2.3, ΔE2000=2.0）
+    pub(crate) fn from_params(threshold: u8, color_metric: Option<ColorMetric>, delta_e: Option<f64>) -> Self {
+        match color_metric.unwrap_or(ColorMetric::Rgb) {
+            ColorMetric::Rgb => PixelMetric::Rgb(threshold as i16),
+            ColorMetric::De76 => PixelMetric::De76(delta_e.unwrap_or(2.3) as f32),
+            ColorMetric::De2000 => PixelMetric::De2000(delta_e.unwrap_or(2.0) as f32),
+        }
+    }
+
+    // 差分の大きさの目安となる最大値（確率計算で強度を0-1に正規化する）
+    pub(crate) fn delta_scale(&self) -> f64 {
+        match self {
+            PixelMetric::Rgb(_) => 255.0,
+            PixelMetric::De76(_) | PixelMetric::De2000(_) => 50.0,
+        }
+    }
+
+    // 差分ならその大きさ（RGB: 最大チャネル差, Lab: ΔE）を返す
+    pub(crate) fn delta(&self, pa: &[u8], pb: &[u8]) -> Option<f32> {
+        if pa[..3] == pb[..3] {
+            return None;
+        }
+        let (delta, threshold) = match *self {
+            PixelMetric::Rgb(threshold) => {
+                let dr = (pa[0] as i16 - pb[0] as i16).abs();
+                let dg = (pa[1] as i16 - pb[1] as i16).abs();
+                let db = (pa[2] as i16 - pb[2] as i16).abs();
+                (dr.max(dg).max(db) as f32, threshold as f32)
+            }
+            PixelMetric::De76(threshold) => {
+                let la = srgb_to_lab(pa);
+                let lb = srgb_to_lab(pb);
+                let d = ((la[0] - lb[0]).powi(2) + (la[1] - lb[1]).powi(2) + (la[2] - lb[2]).powi(2)).sqrt();
+                (d, threshold)
+            }
+            PixelMetric::De2000(threshold) => (delta_e2000(srgb_to_lab(pa), srgb_to_lab(pb)), threshold),
+        };
+        if delta > threshold { Some(delta) } else { None }
+    }
+}
+
+// sRGB(8bit) → リニアの変換テーブル
+pub(crate) fn srgb_linear_lut() -> &'static [f32; 256] {
+    static LUT: std::sync::OnceLock<[f32; 256]> = std::sync::OnceLock::new();
+    LUT.get_or_init(|| {
+        std::array::from_fn(|i| {
+            let c = i as f32 / 255.0;
+            if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        })
+    })
+}
+
+// sRGB → CIE L*a*b*（D65白色点）
+pub(crate) fn srgb_to_lab(p: &[u8]) -> [f32; 3] {
+    let lut = srgb_linear_lut();
+    let r = lut[p[0] as usize];
+    let g = lut[p[1] as usize];
+    let b = lut[p[2] as usize];
+    let x = (0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047;
+    let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+    let z = (0.0193339 * r + 0.119192 * g + 0.9503041 * b) / 1.08883;
+    let f = |t: f32| if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+// CIEDE2000色差 (Sharma et al. 2005)
+pub(crate) fn delta_e2000(lab1: [f32; 3], lab2: [f32; 3]) -> f32 {
+    let (l1, a1, b1) = (lab1[0] as f64, lab1[1] as f64, lab1[2] as f64);
+    let (l2, a2, b2) = (lab2[0] as f64, lab2[1] as f64, lab2[2] as f64);
+    const POW25_7: f64 = 6_103_515_625.0; // 25^7
+
+    let c_bar = ((a1 * a1 + b1 * b1).sqrt() + (a2 * a2 + b2 * b2).sqrt()) / 2.0;
+    let c_bar7 = c_bar.powi(7);
+    let g = 0.5 * (1.0 - (c_bar7 / (c_bar7 + POW25_7)).sqrt());
+    let a1p = (1.0 + g) * a1;
+    let a2p = (1.0 + g) * a2;
+    let c1p = a1p.hypot(b1);
+    let c2p = a2p.hypot(b2);
+    let hue = |b: f64, a: f64| {
+        if a == 0.0 && b == 0.0 { 0.0 } else { b.atan2(a).to_degrees().rem_euclid(360.0) }
+    };
+    let h1p = hue(b1, a1p);
+    let h2p = hue(b2, a2p);
+
+    let dl = l2 - l1;
+    let dc = c2p - c1p;
+    let dh_angle = if c1p * c2p == 0.0 {
+        0.0
+    } else {
+        let d = h2p - h1p;
+        if d > 180.0 { d - 360.0 } else if d < -180.0 { d + 360.0 } else { d }
+    };
+    let dh = 2.0 * (c1p * c2p).sqrt() * (dh_angle / 2.0).to_radians().sin();
+
+    let l_bar = (l1 + l2) / 2.0;
+    let c_bar_p = (c1p + c2p) / 2.0;
+    let h_bar = if c1p * c2p == 0.0 {
+        h1p + h2p
+    } else if (h1p - h2p).abs() <= 180.0 {
+        (h1p + h2p) / 2.0
+    } else if h1p + h2p < 360.0 {
+        (h1p + h2p + 360.0) / 2.0
+    } else {
+        (h1p + h2p - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_bar - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_bar).to_radians().cos()
+        + 0.32 * (3.0 * h_bar + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_bar - 63.0).to_radians().cos();
+    let d_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+    let c_bar_p7 = c_bar_p.powi(7);
+    let r_c = 2.0 * (c_bar_p7 / (c_bar_p7 + POW25_7)).sqrt();
+    let s_l = 1.0 + 0.015 * (l_bar - 50.0).powi(2) / (20.0 + (l_bar - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar_p;
+    let s_h = 1.0 + 0.015 * c_bar_p * t;
+    let r_t = -(2.0 * d_theta).to_radians().sin() * r_c;
+
+    let (tl, tc, th) = (dl / s_l, dc / s_c, dh / s_h);
+    (tl * tl + tc * tc + th * th + r_t * tc * th).max(0.0).sqrt() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sharma, Wu, Dalal (2005) "The CIEDE2000 Color-Difference Formula" Table 1
+    const SHARMA_PAIRS: [([f32; 3], [f32; 3], f32); 34] = [
+        ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+        ([50.0, 3.1571, -77.2803], [50.0, 0.0, -82.7485], 2.8615),
+        ([50.0, 2.8361, -74.0200], [50.0, 0.0, -82.7485], 3.4412),
+        ([50.0, -1.3802, -84.2814], [50.0, 0.0, -82.7485], 1.0000),
+        ([50.0, -1.1848, -84.8006], [50.0, 0.0, -82.7485], 1.0000),
+        ([50.0, -0.9009, -85.5211], [50.0, 0.0, -82.7485], 1.0000),
+        ([50.0, 0.0, 0.0], [50.0, -1.0, 2.0], 2.3669),
+        ([50.0, -1.0, 2.0], [50.0, 0.0, 0.0], 2.3669),
+        ([50.0, 2.49, -0.001], [50.0, -2.49, 0.0009], 7.1792),
+        ([50.0, 2.49, -0.001], [50.0, -2.49, 0.0010], 7.1792),
+        ([50.0, 2.49, -0.001], [50.0, -2.49, 0.0011], 7.2195),
+        ([50.0, 2.49, -0.001], [50.0, -2.49, 0.0012], 7.2195),
+        ([50.0, -0.001, 2.49], [50.0, 0.0009, -2.49], 4.8045),
+        ([50.0, -0.001, 2.49], [50.0, 0.0010, -2.49], 4.8045),
+        ([50.0, -0.001, 2.49], [50.0, 0.0011, -2.49], 4.7461),
+        ([50.0, 2.5, 0.0], [50.0, 0.0, -2.5], 4.3065),
+        ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+        ([50.0, 2.5, 0.0], [61.0, -5.0, 29.0], 22.8977),
+        ([50.0, 2.5, 0.0], [56.0, -27.0, -3.0], 31.9030),
+        ([50.0, 2.5, 0.0], [58.0, 24.0, 15.0], 19.4535),
+        ([50.0, 2.5, 0.0], [50.0, 3.1736, 0.5854], 1.0000),
+        ([50.0, 2.5, 0.0], [50.0, 3.2972, 0.0], 1.0000),
+        ([50.0, 2.5, 0.0], [50.0, 1.8634, 0.5757], 1.0000),
+        ([50.0, 2.5, 0.0], [50.0, 3.2592, 0.3350], 1.0000),
+        ([60.2574, -34.0099, 36.2677], [60.4626, -34.1751, 39.4387], 1.2644),
+        ([63.0109, -31.0961, -5.8663], [62.8187, -29.7946, -4.0864], 1.2630),
+        ([61.2901, 3.7196, -5.3901], [61.4292, 2.2480, -4.9620], 1.8731),
+        ([35.0831, -44.1164, 3.7933], [35.0232, -40.0716, 1.5901], 1.8645),
+        ([22.7233, 20.0904, -46.6940], [23.0331, 14.9730, -42.5619], 2.0373),
+        ([36.4612, 47.8580, 18.3852], [36.2715, 50.5065, 21.2231], 1.4146),
+        ([90.8027, -2.0831, 1.4410], [91.1528, -1.6435, 0.0447], 1.4441),
+        ([90.9257, -0.5406, -0.9208], [88.6381, -0.8985, -0.7239], 1.5381),
+        ([6.7747, -0.2908, -2.4247], [5.8714, -0.0985, -2.2286], 0.6377),
+        ([2.0776, 0.0795, -1.1350], [0.9033, -0.0636, -0.5514], 0.9082),
+    ];
+
+    #[test]
+    fn delta_e2000_matches_sharma_reference() {
+        for (i, (lab1, lab2, expected)) in SHARMA_PAIRS.iter().enumerate() {
+            let d = delta_e2000(*lab1, *lab2);
+            assert!((d - expected).abs() <= 1e-4, "pair {}: {} != {}", i + 1, d, expected);
+            // 対称
+            assert!((delta_e2000(*lab2, *lab1) - d).abs() < 1e-5, "pair {} is not symmetric", i + 1);
+        }
+    }
+
+    #[test]
+    fn srgb_to_lab_white_and_black() {
+        let white = srgb_to_lab(&[255, 255, 255]);
+        let black = srgb_to_lab(&[0, 0, 0]);
+        assert!((white[0] - 100.0).abs() < 1e-3 && white[1].abs() < 1e-2 && white[2].abs() < 1e-2);
+        assert!(black.iter().all(|v| v.abs() < 1e-3));
+    }
+
+    #[test]
+    fn from_params_selects_metric_and_default_threshold() {
+        assert!(matches!(PixelMetric::from_params(5, None, None), PixelMetric::Rgb(5)));
+        assert!(matches!(PixelMetric::from_params(5, Some(ColorMetric::Rgb), Some(9.0)), PixelMetric::Rgb(5)));
+        assert!(matches!(PixelMetric::from_params(5, Some(ColorMetric::De76), None), PixelMetric::De76(t) if t == 2.3));
+        assert!(matches!(PixelMetric::from_params(5, Some(ColorMetric::De2000), None), PixelMetric::De2000(t) if t == 2.0));
+        assert!(matches!(PixelMetric::from_params(5, Some(ColorMetric::De2000), Some(4.5)), PixelMetric::De2000(t) if t == 4.5));
+
+        // 閾値を超えたものだけ差分（RGBは最大チャネル差）
+        let metric = PixelMetric::from_params(5, None, None);
+        assert_eq!(metric.delta(&[10, 10, 10, 255], &[15, 10, 10, 255]), None);
+        assert_eq!(metric.delta(&[10, 10, 10, 255], &[16, 12, 10, 255]), Some(6.0));
+        let metric = PixelMetric::from_params(0, Some(ColorMetric::De2000), None);
+        assert_eq!(metric.delta(&[128, 128, 128, 255], &[129, 128, 128, 255]), None);
+        assert!(metric.delta(&[128, 128, 128, 255], &[128, 100, 128, 255]).is_some());
+    }
+}
@@ -0,0 +1,481 @@
+use crate::*;
+
+// ============== 差分の検出（単純・ヒートマップ・SSIM） ==============
+
+// 差分ピクセルの種類
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum DiffKind {
+    Added,   // Bで暗くなった（描き足し）
+    Removed, // Bで明るくなった（消し）
+    Changed, // 色相の変化
+}
+
+impl DiffKind {
+    // 明るさの変化より色の偏りが大きければ色変化、そうでなければ明暗で判定
+    pub(crate) fn classify(pa: &[u8], pb: &[u8]) -> Self {
+        let d = [
+            pb[0] as i32 - pa[0] as i32,
+            pb[1] as i32 - pa[1] as i32,
+            pb[2] as i32 - pa[2] as i32,
+        ];
+        let dl = (d[0] * 299 + d[1] * 587 + d[2] * 114) / 1000;
+        let hue = d.iter().map(|c| (c - dl).abs()).max().unwrap_or(0);
+        if dl == 0 || hue > dl.abs() {
+            DiffKind::Changed
+        } else if dl < 0 {
+            DiffKind::Added
+        } else {
+            DiffKind::Removed
+        }
+    }
+
+    pub(crate) fn from_gray(va: u8, vb: u8) -> Self {
+        if vb < va { DiffKind::Added } else { DiffKind::Removed }
+    }
+
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+
+    // オーバーレイの色（描き足し=赤, 消し=青, 色変化=黄）
+    pub(crate) fn overlay_color(self) -> [u8; 3] {
+        match self {
+            DiffKind::Added => [255, 0, 0],
+            DiffKind::Removed => [0, 128, 255],
+            DiffKind::Changed => [255, 208, 0],
+        }
+    }
+}
+
+pub(crate) struct DiffPixel {
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) delta: f32, // 差分の大きさ（RGB: 最大チャネル差, Lab: ΔE, SSIM: 1-SSIM）
+    pub(crate) kind: DiffKind,
+}
+
+// ピクセル単位の単純差分計算 (rayon行並列)
+// 返り値: (差分RGBAバッファ, 差分ピクセル数, 差分ピクセル座標リスト)
+// jobがキャンセルされた場合は残りの行を比較せずに返す（呼び出し側でjob.check()する）
+pub(crate) fn diff_simple_core(
+    a: PixelView, b: PixelView, width: u32, height: u32, metric: PixelMetric, ignore: Option<&[u8]>,
+    job: Option<&Job>,
+) -> (Vec<u8>, u32, Vec<DiffPixel>) {
+    let row_size = (width as usize) * 4;
+
+    // 行ごとに並列処理
+    let rows: Vec<(Vec<u8>, u32, Vec<DiffPixel>)> = (0..height)
+        .into_par_iter()
+        .map(|y| {
+            // 黒背景（alpha=255）
+            let mut row_buf: Vec<u8> = [0u8, 0, 0, 255].repeat(width as usize);
+            let mut count = 0u32;
+            let mut pixels = Vec::new();
+            if job.is_some_and(Job::is_cancelled) {
+                return (row_buf, count, pixels);
+            }
+            let ignore_row = ignore.map(|m| &m[y as usize * width as usize..(y as usize + 1) * width as usize]);
+
+            diff_row(a, b, y as usize, width as usize, &metric, |x, delta, kind| {
+                if ignore_row.is_some_and(|m| m[x] != 0) {
+                    return;
+                }
+                // 種類ごとに色分け（描き足し=赤, 消し=青, 色変化=黄）
+                row_buf[x * 4..x * 4 + 3].copy_from_slice(&kind.overlay_color());
+                count += 1;
+                pixels.push(DiffPixel { x: x as u32, y, delta, kind });
+            });
+
+            // 除外領域を塗る
+            if let Some(m) = ignore_row {
+                for (x, _) in m.iter().enumerate().filter(|(_, &v)| v != 0) {
+                    row_buf[x * 4..x * 4 + 3].copy_from_slice(&ignored_color(x, y as usize));
+                }
+            }
+            if let Some(job) = job {
+                job.advance(1);
+            }
+            (row_buf, count, pixels)
+        })
+        .collect();
+
+    let total_size = (width as usize) * (height as usize) * 4;
+    let mut diff_buf = vec![0u8; total_size];
+    let mut total_count = 0u32;
+    let mut all_pixels = Vec::new();
+
+    for (y, (row_buf, count, pixels)) in rows.into_iter().enumerate() {
+        let offset = y * row_size;
+        diff_buf[offset..offset + row_size].copy_from_slice(&row_buf);
+        total_count += count;
+        all_pixels.extend(pixels);
+    }
+
+    (diff_buf, total_count, all_pixels)
+}
+
+// ヒートマップ差分計算（積分画像→密度マップ→着色）
+// jobの進捗は3パス分の行数（キャンセル時は途中で空の結果を返す）
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn diff_heatmap_core(
+    a: PixelView, b: PixelView, width: u32, height: u32, metric: PixelMetric, ignore: Option<&[u8]>,
+    params: &DiffParams, job: Option<&Job>,
+) -> (Vec<u8>, u32, Vec<DiffPixel>) {
+    let w = width as usize;
+    let h = height as usize;
+    let cancelled = || job.is_some_and(Job::is_cancelled);
+    let advance = || {
+        if let Some(job) = job {
+            job.advance(1);
+        }
+    };
+
+    // Phase 1: diffMask作成（rayon並列, 除外領域は差分なし扱い）
+    let mut diff_mask = vec![0u8; w * h];
+    diff_mask.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        if cancelled() {
+            return;
+        }
+        let ignore_row = ignore.map(|m| &m[y * w..(y + 1) * w]);
+        diff_row(a, b, y, w, &metric, |x, _, _| {
+            if ignore_row.is_none_or(|m| m[x] == 0) {
+                row[x] = 1;
+            }
+        });
+        advance();
+    });
+    if cancelled() {
+        return (Vec::new(), 0, Vec::new());
+    }
+
+    // Phase 2: 積分画像（sequential - データ依存あり）
+    let iw = w + 1;
+    let ih = h + 1;
+    let mut integral = vec![0f32; iw * ih];
+    for y in 0..h {
+        if cancelled() {
+            return (Vec::new(), 0, Vec::new());
+        }
+        for x in 0..w {
+            let idx = (y + 1) * iw + (x + 1);
+            integral[idx] = diff_mask[y * w + x] as f32
+                + integral[idx - 1]
+                + integral[idx - iw]
+                - integral[idx - iw - 1];
+        }
+    }
+
+    // Phase 3: 密度マップ（rayon並列 - integralは読み取り専用）
+    // 画像より大きい半径は画像全体と同じ
+    let radius = params.density_radius.min(width.max(height)).max(1) as i32;
+    let density_and_max: Vec<(f32, f32)> = (0..h)
+        .into_par_iter()
+        .map(|y| {
+            if cancelled() {
+                return vec![(0.0, 0.0); w];
+            }
+            advance();
+            let mut row_max = 0f32;
+            let row: Vec<f32> = (0..w).map(|x| {
+                let x1 = (x as i32 - radius).max(0) as usize;
+                let y1 = (y as i32 - radius).max(0) as usize;
+                let x2 = ((x as i32 + radius) as usize).min(w - 1);
+                let y2 = ((y as i32 + radius) as usize).min(h - 1);
+                let area = ((x2 - x1 + 1) * (y2 - y1 + 1)) as f32;
+                let sum = integral[(y2 + 1) * iw + (x2 + 1)]
+                    - integral[y1 * iw + (x2 + 1)]
+                    - integral[(y2 + 1) * iw + x1]
+                    + integral[y1 * iw + x1];
+                let d = sum / area;
+                if d > row_max { row_max = d; }
+                d
+            }).collect();
+            // rowとrow_maxをタプルで返す（後でflatten）
+            row.into_iter().map(move |d| (d, row_max)).collect::<Vec<_>>()
+        })
+        .flatten()
+        .collect();
+
+    if cancelled() {
+        return (Vec::new(), 0, Vec::new());
+    }
+
+    // maxDensityを求める
+    let max_density = density_and_max.iter().map(|(_, m)| *m).fold(0f32, f32::max);
+
+    // Phase 4: ヒートマップ着色 + 高密度ピクセル収集（rayon並列）
+    let density_threshold = params.density_threshold;
+    let rows: Vec<(Vec<u8>, u32, Vec<DiffPixel>)> = (0..h)
+        .into_par_iter()
+        .map(|y| {
+            let row_size = w * 4;
+            let mut row_buf = vec![0u8; row_size];
+            let mut high_count = 0u32;
+            let mut high_pixels = Vec::new();
+            if cancelled() {
+                return (row_buf, high_count, high_pixels);
+            }
+            advance();
+
+            for x in 0..w {
+                let pixel_idx = y * w + x;
+                let di = x * 4;
+                let (density, _) = density_and_max[pixel_idx];
+                let normalized = if max_density > 0.0 { density / max_density } else { 0.0 };
+
+                if diff_mask[pixel_idx] == 1 && density > density_threshold {
+                    let (r, g, b) = if normalized < 0.3 {
+                        (0u8, (normalized / 0.3 * 200.0) as u8, 200u8)
+                    } else if normalized < 0.6 {
+                        let t = (normalized - 0.3) / 0.3;
+                        ((t * 255.0) as u8, (200.0 + t * 55.0) as u8, ((1.0 - t) * 200.0) as u8)
+                    } else {
+                        let t = (normalized - 0.6) / 0.4;
+                        high_count += 1;
+                        // 高密度ピクセルのみ差分の大きさを再計算
+                        let (pa, pb) = (a.rgba_at(x, y, w), b.rgba_at(x, y, w));
+                        let delta = metric.delta(&pa, &pb).unwrap_or(0.0);
+                        let kind = DiffKind::classify(&pa, &pb);
+                        high_pixels.push(DiffPixel { x: x as u32, y: y as u32, delta, kind });
+                        (255u8, ((1.0 - t) * 255.0) as u8, 0u8)
+                    };
+                    row_buf[di] = r;
+                    row_buf[di + 1] = g;
+                    row_buf[di + 2] = b;
+                    row_buf[di + 3] = 255;
+                } else if ignore.is_some_and(|m| m[pixel_idx] != 0) {
+                    // 除外領域
+                    row_buf[di..di + 3].copy_from_slice(&ignored_color(x, y));
+                    row_buf[di + 3] = 255;
+                } else {
+                    // 黒背景
+                    row_buf[di + 3] = 255;
+                }
+            }
+            (row_buf, high_count, high_pixels)
+        })
+        .collect();
+
+    let total_size = w * h * 4;
+    let mut heatmap_buf = vec![0u8; total_size];
+    let mut total_high = 0u32;
+    let mut all_high_pixels = Vec::new();
+
+    for (y, (row_buf, count, pixels)) in rows.into_iter().enumerate() {
+        let offset = y * w * 4;
+        heatmap_buf[offset..offset + w * 4].copy_from_slice(&row_buf);
+        total_high += count;
+        all_high_pixels.extend(pixels);
+    }
+
+    (heatmap_buf, total_high, all_high_pixels)
+}
+
+// SSIM差分の計算結果
+pub(crate) struct SsimOutput {
+    pub(crate) overlay: Vec<u8>,
+    pub(crate) diff_count: u32,
+    pub(crate) pixels: Vec<DiffPixel>,
+    pub(crate) mean_ssim: f64,
+    pub(crate) min_ssim: f32,
+}
+
+// 窓付きSSIMマップ計算（行ごとに窓内の和を求める, rayon行並列）
+// 輝度差だけでなく局所的なコントラスト・構造の変化を捉え、JPEG/リサンプリングノイズには鈍感
+// jobの進捗は行数（キャンセル時は残りの行を差分なしとして返す）
+pub(crate) fn diff_ssim_core(
+    a: &LumaPlane, b: &LumaPlane, radius: u32, ssim_threshold: f32, job: Option<&Job>,
+) -> SsimOutput {
+    // JPEGのリンギングなど画素単位のノイズで平坦部のSSIMが下がらないよう、3x3で均してから比べる
+    let (a, b) = (&a.box_blur3(), &b.box_blur3());
+    let w = a.width;
+    let h = a.height;
+    let r = radius as usize;
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    // 行ごとの結果もSsimOutputで返す（mean_ssimは行内の合計）
+    let rows: Vec<SsimOutput> = (0..h)
+        .into_par_iter()
+        .map(|y| {
+            if job.is_some_and(Job::is_cancelled) {
+                return SsimOutput {
+                    overlay: [0u8, 0, 0, 255].repeat(w),
+                    diff_count: 0,
+                    pixels: Vec::new(),
+                    mean_ssim: w as f64,
+                    min_ssim: 1.0,
+                };
+            }
+            let y1 = y.saturating_sub(r);
+            let y2 = (y + r).min(h - 1);
+
+            // 列ごとに窓の縦方向の和 (Σa, Σb, Σa², Σb², Σab)
+            let mut cols = vec![[0f64; 5]; w];
+            for yy in y1..=y2 {
+                let offset = yy * w;
+                for (x, col) in cols.iter_mut().enumerate() {
+                    let va = a.data[offset + x] as f64;
+                    let vb = b.data[offset + x] as f64;
+                    col[0] += va;
+                    col[1] += vb;
+                    col[2] += va * va;
+                    col[3] += vb * vb;
+                    col[4] += va * vb;
+                }
+            }
+
+            // 横方向は累積和で窓を切り出す
+            let mut prefix = vec![[0f64; 5]; w + 1];
+            for x in 0..w {
+                for k in 0..5 {
+                    prefix[x + 1][k] = prefix[x][k] + cols[x][k];
+                }
+            }
+
+            let mut row_buf = vec![0u8; w * 4];
+            let mut count = 0u32;
+            let mut pixels = Vec::new();
+            let mut ssim_sum = 0f64;
+            let mut ssim_min = 1f32;
+            for x in 0..w {
+                let x1 = x.saturating_sub(r);
+                let x2 = (x + r).min(w - 1);
+                let n = ((x2 - x1 + 1) * (y2 - y1 + 1)) as f64;
+                let s: [f64; 5] = std::array::from_fn(|k| prefix[x2 + 1][k] - prefix[x1][k]);
+                let mu_a = s[0] / n;
+                let mu_b = s[1] / n;
+                let var_a = (s[2] / n - mu_a * mu_a).max(0.0);
+                let var_b = (s[3] / n - mu_b * mu_b).max(0.0);
+                let cov = s[4] / n - mu_a * mu_b;
+                let ssim = (((2.0 * mu_a * mu_b + C1) * (2.0 * cov + C2))
+                    / ((mu_a * mu_a + mu_b * mu_b + C1) * (var_a + var_b + C2))) as f32;
+                ssim_sum += ssim as f64;
+                ssim_min = ssim_min.min(ssim);
+
+                let di = x * 4;
+                if ssim < ssim_threshold {
+                    // 非類似度(1 - SSIM)に応じて黄→赤
+                    let t = ((1.0 - ssim) / (1.0 - ssim_threshold).max(1e-6) - 1.0).clamp(0.0, 1.0);
+                    row_buf[di] = 255;
+                    row_buf[di + 1] = ((1.0 - t) * 220.0) as u8;
+                    row_buf[di + 2] = 0;
+                    count += 1;
+                    pixels.push(DiffPixel {
+                        x: x as u32, y: y as u32, delta: 1.0 - ssim, kind: DiffKind::Changed,
+                    });
+                }
+                // 黒背景（alpha=255）
+                row_buf[di + 3] = 255;
+            }
+            if let Some(job) = job {
+                job.advance(1);
+            }
+            SsimOutput {
+                overlay: row_buf,
+                diff_count: count,
+                pixels,
+                mean_ssim: ssim_sum,
+                min_ssim: ssim_min,
+            }
+        })
+        .collect();
+
+    let mut overlay = vec![0u8; w * h * 4];
+    let mut diff_count = 0u32;
+    let mut all_pixels = Vec::new();
+    let mut ssim_sum = 0f64;
+    let mut min_ssim = 1f32;
+    for (y, row) in rows.into_iter().enumerate() {
+        let offset = y * w * 4;
+        overlay[offset..offset + w * 4].copy_from_slice(&row.overlay);
+        diff_count += row.diff_count;
+        all_pixels.extend(row.pixels);
+        ssim_sum += row.mean_ssim;
+        min_ssim = min_ssim.min(row.min_ssim);
+    }
+
+    SsimOutput {
+        overlay,
+        diff_count,
+        pixels: all_pixels,
+        mean_ssim: if w * h > 0 { ssim_sum / (w * h) as f64 } else { 1.0 },
+        min_ssim,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 240;
+
+    // 左半分に文字ブロック風の黒い矩形を並べたページ（右半分は白）
+    fn page() -> Vec<u8> {
+        let mut buf = vec![255u8; SIZE * SIZE];
+        for row in 0..10 {
+            for col in 0..8 {
+                let (x, y) = (10 + col * 13, 10 + row * 22);
+                for yy in y..y + 14 {
+                    buf[yy * SIZE + x..yy * SIZE + x + 9].fill(20);
+                }
+            }
+        }
+        buf
+    }
+
+    fn ssim_markers(a: &[u8], b: &[u8]) -> (SsimOutput, Vec<DiffMarker>) {
+        let luma_a = LumaPlane::from_pixels(a, SIZE as u32, SIZE as u32, 1);
+        let luma_b = LumaPlane::from_pixels(b, SIZE as u32, SIZE as u32, 1);
+        let ssim = diff_ssim_core(&luma_a, &luma_b, 3, 0.9, None);
+        let markers = cluster_markers(&ssim.pixels, &resolve_diff_options(None, DiffMode::Ssim).unwrap());
+        (ssim, markers)
+    }
+
+    #[test]
+    fn ssim_of_identical_pages_is_one() {
+        let a = page();
+        let (ssim, markers) = ssim_markers(&a, &a);
+        assert_eq!(ssim.mean_ssim, 1.0);
+        assert_eq!(ssim.min_ssim, 1.0);
+        assert_eq!(ssim.diff_count, 0);
+        assert!(markers.is_empty());
+    }
+
+    #[test]
+    fn ssim_flags_low_contrast_edit() {
+        let a = page();
+        // 白地に薄いグレー（差は20階調）の書き足し
+        let mut b = a.clone();
+        for y in 150..190 {
+            b[y * SIZE + 160..y * SIZE + 200].fill(235);
+        }
+        let (ssim, markers) = ssim_markers(&a, &b);
+        assert!(ssim.diff_count > 0);
+        assert_eq!(markers.len(), 1);
+        let m = &markers[0];
+        assert!((m.x - 180.0).abs() < 10.0 && (m.y - 170.0).abs() < 10.0, "marker at ({}, {})", m.x, m.y);
+
+        // 1画素幅の細い線の書き足し（平滑化しても拾う）
+        let mut b = a.clone();
+        for x in 150..190 {
+            b[60 * SIZE + x] = 160;
+        }
+        let (_, markers) = ssim_markers(&a, &b);
+        assert_eq!(markers.len(), 1);
+    }
+
+    #[test]
+    fn ssim_ignores_jpeg_noise() {
+        let a = page();
+        let img = image::GrayImage::from_raw(SIZE as u32, SIZE as u32, a.clone()).unwrap();
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 85).encode_image(&img).unwrap();
+        let b = image::load_from_memory(&jpeg).unwrap().into_luma8().into_raw();
+        assert_ne!(a, b);
+
+        let (ssim, markers) = ssim_markers(&a, &b);
+        assert!(ssim.mean_ssim > 0.95, "mean SSIM {}", ssim.mean_ssim);
+        assert!(markers.is_empty(), "{} markers", markers.len());
+    }
+}
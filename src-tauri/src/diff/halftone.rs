@@ -0,0 +1,90 @@
+use crate::*;
+
+// ============== 網点（スクリーントーン）対策 ==============
+
+// 網点の線数(LPI)と画像解像度(dpi)。1周期 = dpi / lpi ピクセル
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+pub(crate) struct HalftoneOptions {
+    pub(crate) lpi: f64,
+    pub(crate) dpi: f64,
+}
+
+impl Default for HalftoneOptions {
+    fn default() -> Self {
+        // 漫画原稿の一般的なトーン（60線）と入稿解像度（600dpi）
+        Self { lpi: 60.0, dpi: 600.0 }
+    }
+}
+
+impl HalftoneOptions {
+    // 網点1周期分の箱フィルタ半径（幅 = 2r+1 ≒ 周期）
+    pub(crate) fn blur_radius(&self) -> usize {
+        let period = if self.lpi > 0.0 && self.dpi > 0.0 { self.dpi / self.lpi } else { 0.0 };
+        ((period - 1.0) / 2.0).round().max(1.0) as usize
+    }
+}
+
+// 箱フィルタを3回かけてガウスぼかし相当にする（網点の角度に依存せず点パターンを均す）
+pub(crate) const DESCREEN_PASSES: usize = 3;
+
+// 各行を水平方向に箱フィルタ（端は端の画素を延長）
+pub(crate) fn box_blur_rows(src: &[u8], width: usize, channels: usize, radius: usize) -> Vec<u8> {
+    let row_len = width * channels;
+    let window = (2 * radius + 1) as u32;
+    let mut out = vec![0u8; src.len()];
+    out.par_chunks_mut(row_len).zip(src.par_chunks(row_len)).for_each(|(dst, row)| {
+        let at = |x: isize, c: usize| row[(x.clamp(0, width as isize - 1) as usize) * channels + c] as u32;
+        for c in 0..channels {
+            let mut sum: u32 = (-(radius as isize)..=radius as isize).map(|x| at(x, c)).sum();
+            for x in 0..width {
+                dst[x * channels + c] = ((sum + window / 2) / window) as u8;
+                let xi = x as isize;
+                sum = sum + at(xi + radius as isize + 1, c) - at(xi - radius as isize, c);
+            }
+        }
+    });
+    out
+}
+
+pub(crate) fn transpose_pixels(src: &[u8], width: usize, height: usize, channels: usize) -> Vec<u8> {
+    let mut out = vec![0u8; src.len()];
+    out.par_chunks_mut(height * channels).enumerate().for_each(|(x, col)| {
+        for y in 0..height {
+            let si = (y * width + x) * channels;
+            col[y * channels..(y + 1) * channels].copy_from_slice(&src[si..si + channels]);
+        }
+    });
+    out
+}
+
+// 網点を均した比較用画像を作る（2値は階調に戻してからぼかす）
+pub(crate) fn descreen_plane(plane: &PixelPlane, width: u32, height: u32, opts: &HalftoneOptions) -> PixelPlane {
+    let (w, h) = (width as usize, height as usize);
+    let (mut buf, channels) = match plane {
+        PixelPlane::Rgba(v) => (v.clone(), 4),
+        PixelPlane::Gray(v) => (v.clone(), 1),
+        PixelPlane::Bilevel(bits) => (unpack_bilevel(bits, w, h), 1),
+    };
+    let radius = opts.blur_radius();
+    for _ in 0..DESCREEN_PASSES {
+        buf = box_blur_rows(&buf, w, channels, radius);
+    }
+    buf = transpose_pixels(&buf, w, h, channels);
+    for _ in 0..DESCREEN_PASSES {
+        buf = box_blur_rows(&buf, h, channels, radius);
+    }
+    buf = transpose_pixels(&buf, h, w, channels);
+    if channels == 4 { PixelPlane::Rgba(buf) } else { PixelPlane::Gray(buf) }
+}
+
+// 網点対策が指定されていれば両画像を並列に処理
+pub(crate) fn descreen_pair(
+    a: &PixelPlane, b: &PixelPlane, width: u32, height: u32, halftone: Option<&HalftoneOptions>,
+) -> Option<(PixelPlane, PixelPlane)> {
+    let opts = halftone?;
+    Some(rayon::join(
+        || descreen_plane(a, width, height, opts),
+        || descreen_plane(b, width, height, opts),
+    ))
+}
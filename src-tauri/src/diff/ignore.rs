@@ -0,0 +1,72 @@
+use crate::*;
+
+// ============== 除外領域（ノンブル・トンボなど） ==============
+
+// 差分から除外する領域（座標は比較画像=Aの座標系, heatmapはTIFFの座標系）
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct IgnoreRegions {
+    pub(crate) rects: Vec<CropBounds>,
+    pub(crate) polygons: Vec<Vec<Point>>,
+    pub(crate) mask_path: Option<String>, // マスク画像（輝度128以上の部分を除外, 比較サイズに引き伸ばす）
+}
+
+impl IgnoreRegions {
+    // 除外マスク（1 = 除外）を作る。何も指定がなければNone
+    pub(crate) fn rasterize(&self, width: u32, height: u32) -> Result<Option<Vec<u8>>, String> {
+        if self.rects.is_empty() && self.polygons.is_empty() && self.mask_path.is_none() {
+            return Ok(None);
+        }
+        let (w, h) = (width as usize, height as usize);
+        let mut mask = match &self.mask_path {
+            Some(path) => {
+                let img = image::open(path).map_err(|e| format!("Failed to open mask image: {}", e))?;
+                let img = if img.dimensions() != (width, height) {
+                    img.resize_exact(width, height, FilterType::Nearest)
+                } else {
+                    img
+                };
+                img.to_luma8().into_raw().into_iter().map(|v| (v >= 128) as u8).collect()
+            }
+            None => vec![0u8; w * h],
+        };
+
+        for r in &self.rects {
+            let (x2, y2) = (r.right.min(width) as usize, r.bottom.min(height) as usize);
+            for y in (r.top as usize)..y2 {
+                mask[y * w..(y + 1) * w][(r.left as usize).min(x2)..x2].fill(1);
+            }
+        }
+
+        // 多角形は行ごとに辺との交点を求めて塗る（偶奇規則, ピクセル中心で判定）
+        let polygons: Vec<&Vec<Point>> = self.polygons.iter().filter(|p| p.len() >= 3).collect();
+        if !polygons.is_empty() {
+            mask.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+                let py = y as f64 + 0.5;
+                for poly in &polygons {
+                    let mut xs: Vec<f64> = Vec::new();
+                    for (i, p1) in poly.iter().enumerate() {
+                        let p2 = &poly[(i + 1) % poly.len()];
+                        if (p1.y <= py) != (p2.y <= py) {
+                            xs.push(p1.x + (py - p1.y) / (p2.y - p1.y) * (p2.x - p1.x));
+                        }
+                    }
+                    xs.sort_by(|a, b| a.total_cmp(b));
+                    for span in xs.chunks_exact(2) {
+                        let x1 = (span[0] - 0.5).ceil().clamp(0.0, w as f64) as usize;
+                        let x2 = (span[1] - 0.5).ceil().clamp(0.0, w as f64) as usize;
+                        if x1 < x2 {
+                            row[x1..x2].fill(1);
+                        }
+                    }
+                }
+            });
+        }
+        Ok(Some(mask))
+    }
+}
+
+// 除外領域の表示色（斜線で差分なし・差分ありと区別する）
+pub(crate) fn ignored_color(x: usize, y: usize) -> [u8; 3] {
+    if ((x + y) / 6).is_multiple_of(2) { [72, 72, 96] } else { [36, 36, 48] }
+}
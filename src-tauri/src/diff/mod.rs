@@ -0,0 +1,558 @@
+use crate::*;
+
+mod color;
+mod plane;
+mod params;
+mod halftone;
+mod ignore;
+mod detect;
+mod cluster;
+mod probability;
+mod align;
+mod psd_layers;
+mod cmyk;
+
+pub(crate) use color::*;
+pub(crate) use plane::*;
+pub(crate) use params::*;
+pub(crate) use halftone::*;
+pub(crate) use ignore::*;
+pub(crate) use detect::*;
+pub(crate) use cluster::*;
+pub(crate) use probability::*;
+pub(crate) use align::*;
+pub(crate) use psd_layers::*;
+pub(crate) use cmyk::*;
+
+// ============== 差分計算 ==============
+
+// 矩形（right/bottomは含まない）
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub(crate) struct CropBounds {
+    pub(crate) left: u32,
+    pub(crate) top: u32,
+    pub(crate) right: u32,
+    pub(crate) bottom: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub(crate) struct Point {
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct DiffMarker {
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+    pub(crate) radius: f64,
+    pub(crate) count: u32,       // クラスタ内の差分ピクセル数
+    pub(crate) bbox: CropBounds, // 差分ピクセルを囲む最小の矩形（余白なし）
+    pub(crate) hull: Option<Vec<Point>>, // 差分ピクセルの凸包を簡略化した多角形（ピクセル境界の座標）。要求時のみ
+    // クラスタ内の差分の大きさ（ΔEモードではΔE, RGBモードでは最大チャネル差, SSIMでは1-SSIM）
+    pub(crate) max_delta: f64,
+    pub(crate) mean_delta: f64,
+    // 種類別のピクセル数
+    pub(crate) added: u32,
+    pub(crate) removed: u32,
+    pub(crate) changed: u32,
+    pub(crate) probability: f64, // 実際の修正である確率(%)。ProbabilityModelで算出
+}
+
+#[derive(Serialize)]
+pub(crate) struct DiffSimpleResult {
+    pub(crate) src_a: String,
+    pub(crate) src_b: String,
+    pub(crate) diff_src: String,
+    pub(crate) has_diff: bool,
+    pub(crate) diff_count: u32,
+    pub(crate) diff_probability: f64, // ページに修正がある確率(%)
+    pub(crate) markers: Vec<DiffMarker>,
+    pub(crate) image_width: u32,
+    pub(crate) image_height: u32,
+    pub(crate) registration: Option<Registration>, // 位置合わせ未使用時はnull
+}
+
+#[derive(Serialize)]
+pub(crate) struct DiffSsimResult {
+    pub(crate) src_a: String,
+    pub(crate) src_b: String,
+    pub(crate) diff_src: String,
+    pub(crate) has_diff: bool,
+    pub(crate) diff_count: u32,
+    pub(crate) mean_ssim: f64, // ページ全体の平均SSIM (1.0 = 完全一致)
+    pub(crate) min_ssim: f64,
+    pub(crate) diff_probability: f64,
+    pub(crate) markers: Vec<DiffMarker>,
+    pub(crate) image_width: u32,
+    pub(crate) image_height: u32,
+    pub(crate) registration: Option<Registration>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DiffHeatmapResult {
+    pub(crate) src_a: String,
+    pub(crate) src_b: String,
+    pub(crate) processed_a: String,
+    pub(crate) diff_src: String,
+    pub(crate) has_diff: bool,
+    pub(crate) diff_probability: f64,
+    pub(crate) high_density_count: u32,
+    pub(crate) markers: Vec<DiffMarker>,
+    pub(crate) image_width: u32,
+    pub(crate) image_height: u32,
+}
+
+// 拡張子でPSD/TIFF/その他を自動判定してデコード
+// layer_filterはPSDのみ有効（その他の形式では無視）
+pub(crate) fn decode_image_file(path: &str, layer_filter: Option<&LayerFilter>) -> Result<DynamicImage, String> {
+    let lower = path.to_lowercase();
+    if lower.ends_with(".psd") {
+        decode_psd_to_image(path, layer_filter)
+    } else {
+        image::open(path).map_err(|e| format!("Failed to open image {}: {}", path, e))
+    }
+}
+
+// PSDファイルをDynamicImageとしてデコード
+// layer_filter指定時は統合画像ではなく、選択したレイヤーのみを合成する
+// （レイヤー情報のないPSDでは選択できないので、統合画像で黙って代用せずエラーにする）
+pub(crate) fn decode_psd_to_image(path: &str, layer_filter: Option<&LayerFilter>) -> Result<DynamicImage, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read PSD: {}", e))?;
+    let psd = Psd::from_bytes(&bytes).map_err(|e| format!("Failed to parse PSD: {}", e))?;
+    let width = psd.width();
+    let height = psd.height();
+    let rgba = match layer_filter {
+        Some(_) if psd.layers().is_empty() => {
+            return Err(format!("PSD has no layer records, layer_filter cannot be applied: {}", path));
+        }
+        Some(filter) => composite_psd_layers(&psd, filter)?,
+        None => psd.rgba(),
+    };
+    let img_buf: ImageBuffer<Rgba<u8>, Vec<u8>> =
+        ImageBuffer::from_raw(width, height, rgba)
+            .ok_or_else(|| "Failed to create image buffer from PSD".to_string())?;
+    // グレースケール/2値のPSDはグレーで返す（差分計算の高速パスを使えるように）
+    // レイヤー合成でもグレーのレイヤーを白背景に重ねるだけなので色は付かない
+    let is_gray = matches!(psd.color_mode(), psd::ColorMode::Grayscale | psd::ColorMode::Bitmap);
+    if is_gray {
+        return Ok(DynamicImage::ImageLuma8(DynamicImage::ImageRgba8(img_buf).into_luma8()));
+    }
+    Ok(DynamicImage::ImageRgba8(img_buf))
+}
+
+// RGBA画像をbase64 data URLにエンコード
+pub(crate) fn encode_to_data_url(img: &DynamicImage) -> Result<String, String> {
+    let mut png_data = Cursor::new(Vec::new());
+    img.write_to(&mut png_data, image::ImageFormat::Png)
+        .map_err(|e| format!("PNG encode error: {}", e))?;
+    let base64_str = STANDARD.encode(png_data.get_ref());
+    Ok(format!("data:image/png;base64,{}", base64_str))
+}
+
+// 比較用画素データを画像に戻す
+pub(crate) fn plane_to_image(plane: &PixelPlane, width: u32, height: u32) -> Result<DynamicImage, String> {
+    let err = || "Failed to create image buffer".to_string();
+    Ok(match plane {
+        PixelPlane::Rgba(buf) => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, buf.clone()).ok_or_else(err)?),
+        PixelPlane::Gray(buf) => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, buf.clone()).ok_or_else(err)?),
+        PixelPlane::Bilevel(bits) => {
+            let gray = unpack_bilevel(bits, width as usize, height as usize);
+            DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, gray).ok_or_else(err)?)
+        }
+    })
+}
+
+// 比較用画素データをdata URLにエンコード（グレー/2値はグレースケールPNG）
+pub(crate) fn encode_plane_to_data_url(plane: &PixelPlane, width: u32, height: u32) -> Result<String, String> {
+    let gray = match plane {
+        PixelPlane::Rgba(buf) => return encode_rgba_to_data_url(buf, width, height),
+        PixelPlane::Gray(buf) => std::borrow::Cow::Borrowed(buf),
+        PixelPlane::Bilevel(bits) => {
+            std::borrow::Cow::Owned(unpack_bilevel(bits, width as usize, height as usize))
+        }
+    };
+    let img: ImageBuffer<image::Luma<u8>, &[u8]> =
+        ImageBuffer::from_raw(width, height, gray.as_slice())
+            .ok_or_else(|| "Failed to create image buffer".to_string())?;
+    let mut png_data = Cursor::new(Vec::new());
+    img.write_to(&mut png_data, image::ImageFormat::Png)
+        .map_err(|e| format!("PNG encode error: {}", e))?;
+    let base64_str = STANDARD.encode(png_data.get_ref());
+    Ok(format!("data:image/png;base64,{}", base64_str))
+}
+
+// RGBAバッファから直接data URLにエンコード（DynamicImage変換なし）
+pub(crate) fn encode_rgba_to_data_url(buf: &[u8], width: u32, height: u32) -> Result<String, String> {
+    let img: ImageBuffer<Rgba<u8>, &[u8]> =
+        ImageBuffer::from_raw(width, height, buf)
+            .ok_or_else(|| "Failed to create image buffer".to_string())?;
+    let mut png_data = Cursor::new(Vec::new());
+    img.write_to(&mut png_data, image::ImageFormat::Png)
+        .map_err(|e| format!("PNG encode error: {}", e))?;
+    let base64_str = STANDARD.encode(png_data.get_ref());
+    Ok(format!("data:image/png;base64,{}", base64_str))
+}
+
+// 同サイズに揃えた比較用画像ペア
+pub(crate) struct DiffPair {
+    pub(crate) img_a: DynamicImage,
+    pub(crate) a: PixelPlane,
+    pub(crate) b: PixelPlane, // 位置合わせ後
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) registration: Option<Registration>,
+}
+
+// 2ファイルをデコードして大きい方のサイズに揃え、必要なら位置合わせする
+// jobがあれば各段階の合間でキャンセルを確認する（デコード自体は途中で止められない）
+pub(crate) fn load_diff_pair(
+    path_a: &str, path_b: &str, align: Option<AlignMode>, layer_filter: Option<&LayerFilter>, job: Option<&Job>,
+) -> Result<DiffPair, String> {
+    let check = || job.map_or(Ok(()), Job::check);
+
+    // 2ファイル並列デコード（PSDは両方に同じレイヤーフィルタを適用, 片方がキャンセルされたらもう片方は読まない）
+    let (img_a, img_b) = rayon::join(
+        || check().and_then(|_| decode_image_file(path_a, layer_filter)),
+        || check().and_then(|_| decode_image_file(path_b, layer_filter)),
+    );
+    check()?;
+    let img_a = img_a?;
+    let img_b = img_b?;
+
+    let (wa, ha) = img_a.dimensions();
+    let (wb, hb) = img_b.dimensions();
+    let width = wa.max(wb);
+    let height = ha.max(hb);
+
+    // 必要ならリサイズ
+    let img_a = if wa != width || ha != height {
+        img_a.resize_exact(width, height, FilterType::Triangle)
+    } else {
+        img_a
+    };
+    let img_b = if wb != width || hb != height {
+        img_b.resize_exact(width, height, FilterType::Triangle)
+    } else {
+        img_b
+    };
+
+    // 両方グレースケールならRGBAに展開せずに比較する
+    let gray = is_gray_image(&img_a) && is_gray_image(&img_b);
+    let channels = if gray { 1 } else { 4 };
+    let raw_a = if gray { img_a.to_luma8().into_raw() } else { img_a.to_rgba8().into_raw() };
+    let raw_b = if gray { img_b.into_luma8().into_raw() } else { img_b.into_rgba8().into_raw() };
+    check()?;
+
+    // 位置合わせ（Bを検出したずれの分だけ戻してAに重ねる）
+    let (raw_b, registration) = match align.unwrap_or_default() {
+        AlignMode::None => (raw_b, None),
+        mode => {
+            let (params, registration) = estimate_registration(
+                &raw_a, &raw_b, width, height, channels, mode == AlignMode::Similarity,
+            );
+            let warped = if params.is_pure_translation() && params.dx == 0.0 && params.dy == 0.0 {
+                raw_b
+            } else {
+                warp_pixels(&raw_b, width, height, channels, &params)
+            };
+            (warped, Some(registration))
+        }
+    };
+    check()?;
+
+    // 2値画像は位置合わせ後にビットパック（補間で中間調が出た場合はグレーのまま）
+    let (a, b) = if gray {
+        PixelPlane::gray_pair(raw_a, raw_b, width, height)
+    } else {
+        (PixelPlane::Rgba(raw_a), PixelPlane::Rgba(raw_b))
+    };
+
+    Ok(DiffPair { img_a, a, b, width, height, registration })
+}
+
+// tiff-tiff / psd-psd 用の差分計算
+#[tauri::command]
+#[allow(clippy::too_many_arguments)] // フロントエンドから個別の引数で渡される
+pub(crate) async fn compute_diff_simple(
+    app: tauri::AppHandle,
+    path_a: String, path_b: String, threshold: u8, align: Option<AlignMode>,
+    color_metric: Option<ColorMetric>, delta_e: Option<f64>, layer_filter: Option<LayerFilter>,
+    halftone: Option<HalftoneOptions>, ignore_regions: Option<IgnoreRegions>, options: Option<DiffOptions>,
+    delivery: Option<ImageDelivery>, job_id: Option<String>,
+) -> Result<DiffSimpleResult, String> {
+    let job = Job::start(&app, job_id)?;
+    // デコード・差分計算はCPUを占有するので非同期ランタイムのスレッドでは行わない
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        diff_simple_job(
+            &state, &job, &path_a, &path_b, threshold, align, color_metric, delta_e, layer_filter.as_ref(),
+            halftone.as_ref(), ignore_regions.as_ref(), options.as_ref(), delivery,
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn diff_simple_job(
+    state: &AppState, job: &Job,
+    path_a: &str, path_b: &str, threshold: u8, align: Option<AlignMode>,
+    color_metric: Option<ColorMetric>, delta_e: Option<f64>, layer_filter: Option<&LayerFilter>,
+    halftone: Option<&HalftoneOptions>, ignore_regions: Option<&IgnoreRegions>, options: Option<&DiffOptions>,
+    delivery: Option<ImageDelivery>,
+) -> Result<DiffSimpleResult, String> {
+    job.phase(JobPhase::Decode, 1);
+    let pair = load_diff_pair(path_a, path_b, align, layer_filter, Some(job))?;
+    job.advance(1);
+    job.check()?;
+    let (width, height) = (pair.width, pair.height);
+    let ignore_mask = match ignore_regions {
+        Some(regions) => regions.rasterize(width, height)?,
+        None => None,
+    };
+
+    // 網点対策（表示用の画像はそのまま、差分計算だけぼかした画像で行う）
+    let descreened = descreen_pair(&pair.a, &pair.b, width, height, halftone);
+    let (view_a, view_b) = match &descreened {
+        Some((a, b)) => (a.view(), b.view()),
+        None => (pair.a.view(), pair.b.view()),
+    };
+
+    // 差分計算（color_metric指定時はthresholdの代わりにΔE閾値で判定）
+    let metric = PixelMetric::from_params(threshold, color_metric, delta_e);
+    job.phase(JobPhase::Diff, height as u64);
+    let (diff_buf, diff_count, diff_pixels) =
+        diff_simple_core(view_a, view_b, width, height, metric, ignore_mask.as_deref(), Some(job));
+    job.check()?;
+
+    // マーカークラスタリング
+    job.phase(JobPhase::Cluster, 1);
+    let params = resolve_diff_options(options, DiffMode::Simple)?;
+    let mut markers = cluster_markers(&diff_pixels, &params);
+    let diff_probability = score_markers(&mut markers, &params.probability_model, metric.delta_scale());
+    job.advance(1);
+    job.check()?;
+
+    // 3画像を並列エンコード（Bは位置合わせ後の画像）
+    job.phase(JobPhase::Encode, 3);
+    let [src_a, src_b, diff_src] = deliver_images(state, delivery, [
+        StoredImage::Image(pair.img_a),
+        StoredImage::Plane { plane: pair.b, width, height },
+        StoredImage::rgba(diff_buf, width, height),
+    ])?;
+    job.advance(3);
+    job.finish();
+
+    Ok(DiffSimpleResult {
+        src_a,
+        src_b,
+        diff_src,
+        has_diff: diff_count > 0,
+        diff_count,
+        diff_probability,
+        markers,
+        image_width: width,
+        image_height: height,
+        registration: pair.registration,
+    })
+}
+
+// 構造類似度(SSIM)による差分計算
+#[tauri::command]
+#[allow(clippy::too_many_arguments)] // フロントエンドから個別の引数で渡される
+pub(crate) async fn compute_diff_ssim(
+    app: tauri::AppHandle,
+    path_a: String, path_b: String, ssim_threshold: Option<f64>, window_radius: Option<u32>,
+    align: Option<AlignMode>, layer_filter: Option<LayerFilter>, halftone: Option<HalftoneOptions>,
+    options: Option<DiffOptions>, delivery: Option<ImageDelivery>, job_id: Option<String>,
+) -> Result<DiffSsimResult, String> {
+    let job = Job::start(&app, job_id)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        diff_ssim_job(
+            &state, &job, &path_a, &path_b, ssim_threshold, window_radius, align, layer_filter.as_ref(),
+            halftone.as_ref(), options.as_ref(), delivery,
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn diff_ssim_job(
+    state: &AppState, job: &Job,
+    path_a: &str, path_b: &str, ssim_threshold: Option<f64>, window_radius: Option<u32>,
+    align: Option<AlignMode>, layer_filter: Option<&LayerFilter>, halftone: Option<&HalftoneOptions>,
+    options: Option<&DiffOptions>, delivery: Option<ImageDelivery>,
+) -> Result<DiffSsimResult, String> {
+    job.phase(JobPhase::Decode, 1);
+    let pair = load_diff_pair(path_a, path_b, align, layer_filter, Some(job))?;
+    job.advance(1);
+    let (width, height) = (pair.width, pair.height);
+
+    // 網点対策（差分計算のみ）
+    let descreened = descreen_pair(&pair.a, &pair.b, width, height, halftone);
+    let (view_a, view_b) = match &descreened {
+        Some((a, b)) => (a.view(), b.view()),
+        None => (pair.a.view(), pair.b.view()),
+    };
+    job.check()?;
+
+    // SSIMマップ計算（デフォルト: 7x7窓, SSIM < 0.9 を差分とする）
+    let ssim_threshold = ssim_threshold.unwrap_or(0.9) as f32;
+    let radius = window_radius.unwrap_or(3).clamp(1, 16);
+    let luma_a = LumaPlane::from_view(view_a, width, height);
+    let luma_b = LumaPlane::from_view(view_b, width, height);
+    job.phase(JobPhase::Diff, height as u64);
+    let ssim = diff_ssim_core(&luma_a, &luma_b, radius, ssim_threshold, Some(job));
+    job.check()?;
+
+    // マーカークラスタリング（SSIMは窓単位で広がるので小さい孤立点は除外）
+    job.phase(JobPhase::Cluster, 1);
+    let params = resolve_diff_options(options, DiffMode::Ssim)?;
+    let mut markers = cluster_markers(&ssim.pixels, &params);
+    let diff_probability = score_markers(&mut markers, &params.probability_model, 1.0);
+    job.advance(1);
+    job.check()?;
+
+    // 3画像を並列エンコード
+    job.phase(JobPhase::Encode, 3);
+    let [src_a, src_b, diff_src] = deliver_images(state, delivery, [
+        StoredImage::Image(pair.img_a),
+        StoredImage::Plane { plane: pair.b, width, height },
+        StoredImage::rgba(ssim.overlay, width, height),
+    ])?;
+    job.advance(3);
+    job.finish();
+
+    Ok(DiffSsimResult {
+        src_a,
+        src_b,
+        diff_src,
+        has_diff: ssim.diff_count > 0,
+        diff_count: ssim.diff_count,
+        mean_ssim: ((ssim.mean_ssim * 10000.0).round() / 10000.0),
+        min_ssim: ((ssim.min_ssim as f64 * 10000.0).round() / 10000.0),
+        diff_probability,
+        markers,
+        image_width: width,
+        image_height: height,
+        registration: pair.registration,
+    })
+}
+
+// psd-tiff 用のヒートマップ差分計算
+#[tauri::command]
+#[allow(clippy::too_many_arguments)] // フロントエンドから個別の引数で渡される
+pub(crate) async fn compute_diff_heatmap(
+    app: tauri::AppHandle,
+    psd_path: String, tiff_path: String, crop_bounds: CropBounds, threshold: u8,
+    color_metric: Option<ColorMetric>, delta_e: Option<f64>, layer_filter: Option<LayerFilter>,
+    halftone: Option<HalftoneOptions>, ignore_regions: Option<IgnoreRegions>, options: Option<DiffOptions>,
+    delivery: Option<ImageDelivery>, job_id: Option<String>,
+) -> Result<DiffHeatmapResult, String> {
+    let job = Job::start(&app, job_id)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        diff_heatmap_job(
+            &state, &job, &psd_path, &tiff_path, crop_bounds, threshold, color_metric, delta_e,
+            layer_filter.as_ref(), halftone.as_ref(), ignore_regions.as_ref(), options.as_ref(), delivery,
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn diff_heatmap_job(
+    state: &AppState, job: &Job,
+    psd_path: &str, tiff_path: &str, crop_bounds: CropBounds, threshold: u8,
+    color_metric: Option<ColorMetric>, delta_e: Option<f64>, layer_filter: Option<&LayerFilter>,
+    halftone: Option<&HalftoneOptions>, ignore_regions: Option<&IgnoreRegions>, options: Option<&DiffOptions>,
+    delivery: Option<ImageDelivery>,
+) -> Result<DiffHeatmapResult, String> {
+    // 並列デコード（片方がキャンセルされたらもう片方は読まない）
+    job.phase(JobPhase::Decode, 2);
+    let (psd_result, tiff_result) = rayon::join(
+        || {
+            job.check()?;
+            let result = decode_psd_to_image(psd_path, layer_filter);
+            job.advance(1);
+            result
+        },
+        || {
+            job.check()?;
+            let result = image::open(tiff_path).map_err(|e| format!("Failed to open TIFF: {}", e));
+            job.advance(1);
+            result
+        },
+    );
+    job.check()?;
+    let psd_img = psd_result?;
+    let tiff_img = tiff_result?;
+
+    let (tiff_w, tiff_h) = tiff_img.dimensions();
+    let ignore_mask = match ignore_regions {
+        Some(regions) => regions.rasterize(tiff_w, tiff_h)?,
+        None => None,
+    };
+
+    // PSDをクロップ
+    let crop_w = crop_bounds.right - crop_bounds.left;
+    let crop_h = crop_bounds.bottom - crop_bounds.top;
+    let cropped = psd_img.crop_imm(crop_bounds.left, crop_bounds.top, crop_w, crop_h);
+
+    // TIFFサイズにリサイズ（Nearest = imageSmoothingEnabled=false 相当）
+    let processed_psd = cropped.resize_exact(tiff_w, tiff_h, FilterType::Nearest);
+    job.check()?;
+
+    // モノクロ同士ならグレー/2値のまま比較
+    let (plane_a, plane_b) = PixelPlane::pair_from_images(&processed_psd, &tiff_img);
+
+    // 網点対策（再ラスタライズで網点がずれてもトーン部分を差分にしない）
+    let (plane_a, plane_b) = descreen_pair(&plane_a, &plane_b, tiff_w, tiff_h, halftone)
+        .unwrap_or((plane_a, plane_b));
+
+    // ヒートマップ差分計算（color_metric指定時はΔE閾値で判定）
+    let params = resolve_diff_options(options, DiffMode::Heatmap)?;
+    let metric = PixelMetric::from_params(threshold, color_metric, delta_e);
+    job.phase(JobPhase::Diff, tiff_h as u64 * 3);
+    let (heatmap_buf, high_density_count, high_pixels) = diff_heatmap_core(
+        plane_a.view(), plane_b.view(), tiff_w, tiff_h, metric, ignore_mask.as_deref(), &params, Some(job),
+    );
+    job.check()?;
+
+    // マーカークラスタリング（標準: gridSize=250, minCluster=20, minRadius=80）
+    job.phase(JobPhase::Cluster, 1);
+    let mut markers = cluster_markers(&high_pixels, &params);
+
+    // diffProbability計算（マーカーごとの確率からページの確率を求める）
+    let diff_probability = score_markers(&mut markers, &params.probability_model, metric.delta_scale());
+    job.advance(1);
+    job.check()?;
+
+    // 4画像を並列エンコード
+    job.phase(JobPhase::Encode, 4);
+    let [src_a, src_b, processed_a, diff_src] = deliver_images(state, delivery, [
+        StoredImage::Image(psd_img),
+        StoredImage::Image(tiff_img),
+        StoredImage::Image(processed_psd),
+        StoredImage::rgba(heatmap_buf, tiff_w, tiff_h),
+    ])?;
+    job.advance(4);
+    job.finish();
+
+    Ok(DiffHeatmapResult {
+        src_a,
+        src_b,
+        processed_a,
+        diff_src,
+        has_diff: high_density_count > 0,
+        diff_probability,
+        high_density_count,
+        markers,
+        image_width: tiff_w,
+        image_height: tiff_h,
+    })
+}
@@ -0,0 +1,222 @@
+use crate::*;
+
+// ============== 差分パラメータ ==============
+
+// 解像度別のプリセット（各コマンドの標準値を解像度比で拡縮する）
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DiffPreset {
+    Standard, // 600dpi前後（従来の値）
+    HighRes,  // 1200dpi前後（見開き・高解像度スキャン）
+    LowRes,   // 300dpi以下（Web用・縮小画像）
+}
+
+impl DiffPreset {
+    pub(crate) fn scale(self) -> f64 {
+        match self {
+            DiffPreset::Standard => 1.0,
+            DiffPreset::HighRes => 2.0,
+            DiffPreset::LowRes => 0.5,
+        }
+    }
+}
+
+// 標準値の元になる比較方法
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DiffMode {
+    Simple,
+    Ssim,
+    Heatmap,
+}
+
+// フロントエンドから渡すチューニング値（未指定の項目はプリセットの値）
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct DiffOptions {
+    pub(crate) preset: Option<DiffPreset>,
+    pub(crate) grid_size: Option<u32>,         // クラスタリングのグリッド幅(px)
+    pub(crate) min_cluster: Option<u32>,       // マーカーにする最小ピクセル数
+    pub(crate) min_radius: Option<f64>,        // マーカーの最小半径(px)
+    pub(crate) marker_padding: Option<f64>,    // マーカー半径に足す余白(px)
+    pub(crate) connectivity: Option<u32>,      // 同じクラスタとみなすセル距離
+    pub(crate) density_radius: Option<u32>,    // ヒートマップの密度計算の半径(px)
+    pub(crate) density_threshold: Option<f32>, // ヒートマップに載せる最小密度(0-1)
+    pub(crate) probability_model: Option<ProbabilityModel>, // 指定するとどの比較方法でもこの係数を使う
+    pub(crate) probability_models: Option<ProbabilityModels>, // calibrate_diff_probabilityで求めた比較方法ごとの係数
+    #[serde(alias = "with_contours")]
+    pub(crate) with_hull: Option<bool>,        // マーカーに差分ピクセルの凸包を付ける
+}
+
+// 解決済みのパラメータ
+#[derive(Clone, Copy)]
+pub(crate) struct DiffParams {
+    pub(crate) grid_size: u32,
+    pub(crate) min_cluster: u32,
+    pub(crate) min_radius: f64,
+    pub(crate) marker_padding: f64,
+    pub(crate) connectivity: u32,
+    pub(crate) density_radius: u32,
+    pub(crate) density_threshold: f32,
+    pub(crate) probability_model: ProbabilityModel,
+    pub(crate) with_hull: bool,
+}
+
+impl DiffParams {
+    // 各コマンドの従来値
+    pub(crate) fn defaults(mode: DiffMode) -> Self {
+        let (grid_size, min_cluster, min_radius, marker_padding) = match mode {
+            DiffMode::Simple => (200, 1, 300.0, 100.0),
+            DiffMode::Ssim => (200, 20, 300.0, 100.0),
+            DiffMode::Heatmap => (250, 20, 80.0, 60.0),
+        };
+        Self {
+            grid_size,
+            min_cluster,
+            min_radius,
+            marker_padding,
+            connectivity: 1,
+            density_radius: 15,
+            density_threshold: 0.05,
+            probability_model: ProbabilityModel::default_for(mode),
+            with_hull: false,
+        }
+    }
+
+    // 長さは解像度比、最小ピクセル数は面積比で拡縮
+    pub(crate) fn scaled(self, scale: f64) -> Self {
+        let len = |v: u32| ((v as f64 * scale).round() as u32).max(1);
+        Self {
+            grid_size: len(self.grid_size),
+            min_cluster: ((self.min_cluster as f64 * scale * scale).round() as u32).max(1),
+            min_radius: self.min_radius * scale,
+            marker_padding: self.marker_padding * scale,
+            density_radius: len(self.density_radius),
+            ..self
+        }
+    }
+}
+
+// フロントエンドから極端な値が来ても処理が終わるように制限する
+pub(crate) const MAX_CONNECTIVITY: u32 = 8; // 近傍セルの探索は (2r+1)^2
+pub(crate) const MAX_DENSITY_RADIUS: u32 = 1024;
+// マーカーの半径・余白（px）と最小ピクセル数の上限（これを超える値は入力ミスとみなす）
+pub(crate) const MAX_MARKER_LENGTH: f64 = 100_000.0;
+pub(crate) const MAX_MIN_CLUSTER: u32 = 100_000_000;
+
+// 長さの指定（NaN・負・極端に大きい値はエラー）
+fn valid_length(name: &str, value: f64) -> Result<f64, String> {
+    if !value.is_finite() || !(0.0..=MAX_MARKER_LENGTH).contains(&value) {
+        return Err(format!("Invalid {}: {} (expected 0 to {})", name, value, MAX_MARKER_LENGTH));
+    }
+    Ok(value)
+}
+
+impl DiffOptions {
+    pub(crate) fn resolve(&self, mode: DiffMode) -> Result<DiffParams, String> {
+        let base = DiffParams::defaults(mode).scaled(self.preset.unwrap_or(DiffPreset::Standard).scale());
+        let min_cluster = self.min_cluster.unwrap_or(base.min_cluster);
+        if min_cluster > MAX_MIN_CLUSTER {
+            return Err(format!("Invalid min_cluster: {} (expected at most {})", min_cluster, MAX_MIN_CLUSTER));
+        }
+        let density_threshold = self.density_threshold.unwrap_or(base.density_threshold);
+        if !density_threshold.is_finite() || !(0.0..=1.0).contains(&density_threshold) {
+            return Err(format!("Invalid density_threshold: {} (expected 0 to 1)", density_threshold));
+        }
+        Ok(DiffParams {
+            grid_size: self.grid_size.unwrap_or(base.grid_size).max(1),
+            min_cluster,
+            min_radius: valid_length("min_radius", self.min_radius.unwrap_or(base.min_radius))?,
+            marker_padding: valid_length("marker_padding", self.marker_padding.unwrap_or(base.marker_padding))?,
+            connectivity: self.connectivity.unwrap_or(base.connectivity).min(MAX_CONNECTIVITY),
+            density_radius: self.density_radius.unwrap_or(base.density_radius).clamp(1, MAX_DENSITY_RADIUS),
+            density_threshold,
+            probability_model: self
+                .probability_model
+                .or_else(|| self.probability_models.and_then(|m| m.get(mode)))
+                .unwrap_or(base.probability_model),
+            with_hull: self.with_hull.unwrap_or(base.with_hull),
+        })
+    }
+}
+
+// 未指定ならコマンドの標準値
+pub(crate) fn resolve_diff_options(options: Option<&DiffOptions>, mode: DiffMode) -> Result<DiffParams, String> {
+    options.map_or_else(|| Ok(DiffParams::defaults(mode)), |o| o.resolve(mode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(preset: DiffPreset, mode: DiffMode) -> DiffParams {
+        DiffOptions { preset: Some(preset), ..Default::default() }.resolve(mode).unwrap()
+    }
+
+    #[test]
+    fn standard_preset_keeps_previous_values() {
+        for mode in [DiffMode::Simple, DiffMode::Ssim, DiffMode::Heatmap] {
+            let explicit = resolve(DiffPreset::Standard, mode);
+            let defaults = resolve_diff_options(None, mode).unwrap();
+            assert_eq!(
+                (explicit.grid_size, explicit.min_cluster, explicit.min_radius, explicit.marker_padding),
+                (defaults.grid_size, defaults.min_cluster, defaults.min_radius, defaults.marker_padding),
+            );
+        }
+        // 各コマンドの従来値（単純比較・SSIM: 余白100/最小半径300, ヒートマップ: 余白60/最小半径80）
+        let simple = resolve(DiffPreset::Standard, DiffMode::Simple);
+        assert_eq!((simple.grid_size, simple.min_cluster, simple.min_radius, simple.marker_padding), (200, 1, 300.0, 100.0));
+        let ssim = resolve(DiffPreset::Standard, DiffMode::Ssim);
+        assert_eq!((ssim.grid_size, ssim.min_cluster, ssim.min_radius, ssim.marker_padding), (200, 20, 300.0, 100.0));
+        let heatmap = resolve(DiffPreset::Standard, DiffMode::Heatmap);
+        assert_eq!(
+            (heatmap.grid_size, heatmap.min_cluster, heatmap.min_radius, heatmap.marker_padding),
+            (250, 20, 80.0, 60.0),
+        );
+        assert_eq!((heatmap.connectivity, heatmap.density_radius, heatmap.density_threshold), (1, 15, 0.05));
+    }
+
+    #[test]
+    fn resolution_presets_scale_lengths_and_areas() {
+        let high = resolve(DiffPreset::HighRes, DiffMode::Heatmap);
+        assert_eq!((high.grid_size, high.min_cluster, high.min_radius, high.marker_padding), (500, 80, 160.0, 120.0));
+        assert_eq!(high.density_radius, 30);
+        let low = resolve(DiffPreset::LowRes, DiffMode::Simple);
+        assert_eq!((low.grid_size, low.min_cluster, low.min_radius, low.marker_padding), (100, 1, 150.0, 50.0));
+    }
+
+    #[test]
+    fn explicit_values_override_preset_and_are_limited() {
+        let options = DiffOptions {
+            preset: Some(DiffPreset::HighRes),
+            min_radius: Some(10.0),
+            connectivity: Some(100),
+            density_radius: Some(0),
+            ..Default::default()
+        };
+        let params = options.resolve(DiffMode::Heatmap).unwrap();
+        assert_eq!(params.min_radius, 10.0);
+        assert_eq!(params.marker_padding, 120.0);
+        assert_eq!((params.connectivity, params.density_radius), (MAX_CONNECTIVITY, 1));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let invalid = [
+            DiffOptions { min_radius: Some(f64::NAN), ..Default::default() },
+            DiffOptions { min_radius: Some(-1.0), ..Default::default() },
+            DiffOptions { min_radius: Some(f64::INFINITY), ..Default::default() },
+            DiffOptions { marker_padding: Some(-0.5), ..Default::default() },
+            DiffOptions { marker_padding: Some(1e12), ..Default::default() },
+            DiffOptions { density_threshold: Some(f32::NAN), ..Default::default() },
+            DiffOptions { density_threshold: Some(-0.1), ..Default::default() },
+            DiffOptions { density_threshold: Some(1.5), ..Default::default() },
+            DiffOptions { min_cluster: Some(u32::MAX), ..Default::default() },
+        ];
+        for options in &invalid {
+            assert!(options.resolve(DiffMode::Heatmap).is_err());
+        }
+        let zero = DiffOptions { min_radius: Some(0.0), marker_padding: Some(0.0), ..Default::default() };
+        assert!(zero.resolve(DiffMode::Simple).is_ok());
+    }
+}
@@ -0,0 +1,225 @@
+use crate::*;
+
+// ============== 画素バッファ（RGBA / グレースケール / 2値） ==============
+
+// 比較用の画素データ。モノクロ原稿はRGBAに展開せずに保持してメモリと時間を節約する
+pub(crate) enum PixelPlane {
+    Rgba(Vec<u8>),
+    Gray(Vec<u8>),    // 8bitグレースケール
+    Bilevel(Vec<u8>), // 1bit（行ごとにパック, 先頭ピクセルがMSB, 1 = 黒）
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum PixelView<'a> {
+    Rgba(&'a [u8]),
+    Gray(&'a [u8]),
+    Bilevel(&'a [u8]),
+}
+
+impl PixelPlane {
+    pub(crate) fn view(&self) -> PixelView<'_> {
+        match self {
+            PixelPlane::Rgba(v) => PixelView::Rgba(v),
+            PixelPlane::Gray(v) => PixelView::Gray(v),
+            PixelPlane::Bilevel(v) => PixelView::Bilevel(v),
+        }
+    }
+
+    // 両方ともグレー画像ならグレー（さらに両方2値ならビットパック）、それ以外はRGBA
+    pub(crate) fn pair_from_images(a: &DynamicImage, b: &DynamicImage) -> (PixelPlane, PixelPlane) {
+        if is_gray_image(a) && is_gray_image(b) {
+            let (w, h) = a.dimensions();
+            PixelPlane::gray_pair(a.to_luma8().into_raw(), b.to_luma8().into_raw(), w, h)
+        } else {
+            (PixelPlane::Rgba(a.to_rgba8().into_raw()), PixelPlane::Rgba(b.to_rgba8().into_raw()))
+        }
+    }
+
+    pub(crate) fn gray_pair(a: Vec<u8>, b: Vec<u8>, width: u32, height: u32) -> (PixelPlane, PixelPlane) {
+        let (w, h) = (width as usize, height as usize);
+        match (pack_bilevel(&a, w, h), pack_bilevel(&b, w, h)) {
+            (Some(pa), Some(pb)) => (PixelPlane::Bilevel(pa), PixelPlane::Bilevel(pb)),
+            _ => (PixelPlane::Gray(a), PixelPlane::Gray(b)),
+        }
+    }
+}
+
+impl PixelView<'_> {
+    // 1ピクセルをRGBAで取得（to_rgba8と同じ展開）
+    pub(crate) fn rgba_at(&self, x: usize, y: usize, width: usize) -> [u8; 4] {
+        match self {
+            PixelView::Rgba(v) => {
+                let i = (y * width + x) * 4;
+                [v[i], v[i + 1], v[i + 2], v[i + 3]]
+            }
+            PixelView::Gray(v) => {
+                let g = v[y * width + x];
+                [g, g, g, 255]
+            }
+            PixelView::Bilevel(v) => {
+                let bit = v[y * bilevel_stride(width) + x / 8] & (0x80 >> (x % 8));
+                let g = if bit != 0 { 0 } else { 255 };
+                [g, g, g, 255]
+            }
+        }
+    }
+}
+
+pub(crate) fn is_gray_image(img: &DynamicImage) -> bool {
+    matches!(
+        img.color(),
+        image::ColorType::L8 | image::ColorType::L16 | image::ColorType::La8 | image::ColorType::La16
+    )
+}
+
+pub(crate) fn bilevel_stride(width: usize) -> usize {
+    width.div_ceil(8)
+}
+
+// 全ピクセルが0か255のときだけ1bitにパック
+pub(crate) fn pack_bilevel(gray: &[u8], width: usize, height: usize) -> Option<Vec<u8>> {
+    if gray.par_iter().any(|&v| v != 0 && v != 255) {
+        return None;
+    }
+    let stride = bilevel_stride(width);
+    let mut bits = vec![0u8; stride * height];
+    bits.par_chunks_mut(stride).enumerate().for_each(|(y, row)| {
+        for (x, &v) in gray[y * width..(y + 1) * width].iter().enumerate() {
+            if v == 0 {
+                row[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+    });
+    Some(bits)
+}
+
+pub(crate) fn unpack_bilevel(bits: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let stride = bilevel_stride(width);
+    let mut gray = vec![255u8; width * height];
+    gray.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+        for (x, v) in row.iter_mut().enumerate() {
+            if bits[y * stride + x / 8] & (0x80 >> (x % 8)) != 0 {
+                *v = 0;
+            }
+        }
+    });
+    gray
+}
+
+// 1行分を比較し、差分のあるピクセルごとに f(x, 差分の大きさ, 種類) を呼ぶ
+pub(crate) fn diff_row(
+    a: PixelView, b: PixelView, y: usize, width: usize, metric: &PixelMetric,
+    mut f: impl FnMut(usize, f32, DiffKind),
+) {
+    match (a, b) {
+        (PixelView::Rgba(a), PixelView::Rgba(b)) => {
+            let offset = y * width * 4;
+            for x in 0..width {
+                let i = offset + x * 4;
+                if let Some(delta) = metric.delta(&a[i..i + 4], &b[i..i + 4]) {
+                    f(x, delta, DiffKind::classify(&a[i..i + 4], &b[i..i + 4]));
+                }
+            }
+        }
+        (PixelView::Gray(a), PixelView::Gray(b)) => {
+            let row_a = &a[y * width..(y + 1) * width];
+            let row_b = &b[y * width..(y + 1) * width];
+            for (x, (&va, &vb)) in row_a.iter().zip(row_b).enumerate() {
+                if va == vb {
+                    continue;
+                }
+                if let Some(delta) = metric.delta(&[va, va, va, 255], &[vb, vb, vb, 255]) {
+                    f(x, delta, DiffKind::from_gray(va, vb));
+                }
+            }
+        }
+        (PixelView::Bilevel(a), PixelView::Bilevel(b)) => {
+            // 2値の差分は常に白⇔黒なので差分の大きさは1回だけ計算
+            let Some(delta) = metric.delta(&[0, 0, 0, 255], &[255, 255, 255, 255]) else { return };
+            let stride = bilevel_stride(width);
+            let row_a = &a[y * stride..(y + 1) * stride];
+            let row_b = &b[y * stride..(y + 1) * stride];
+            for (bi, (&ba, &bb)) in row_a.iter().zip(row_b).enumerate() {
+                let xor = ba ^ bb;
+                if xor == 0 {
+                    continue; // 8ピクセル分まとめてスキップ
+                }
+                for bit in 0..8 {
+                    let x = bi * 8 + bit;
+                    let mask = 0x80 >> bit;
+                    if xor & mask != 0 && x < width {
+                        // Bが黒（ビットが立っている）なら描き足し
+                        let kind = if bb & mask != 0 { DiffKind::Added } else { DiffKind::Removed };
+                        f(x, delta, kind);
+                    }
+                }
+            }
+        }
+        // 形式が混在する場合（通常は発生しない）はRGBAに展開して比較
+        (a, b) => {
+            for x in 0..width {
+                let (pa, pb) = (a.rgba_at(x, y, width), b.rgba_at(x, y, width));
+                if let Some(delta) = metric.delta(&pa, &pb) {
+                    f(x, delta, DiffKind::classify(&pa, &pb));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 幅が8の倍数でない白黒の市松模様風パターン
+    fn bilevel_page(width: usize, height: usize, seed: usize) -> Vec<u8> {
+        (0..width * height).map(|i| if (i * 7 + i / width * seed) % 5 < 2 { 0 } else { 255 }).collect()
+    }
+
+    #[test]
+    fn bilevel_packing_round_trips() {
+        for (width, height) in [(1, 1), (7, 3), (8, 2), (13, 5), (64, 4)] {
+            let gray = bilevel_page(width, height, 3);
+            let bits = pack_bilevel(&gray, width, height).unwrap();
+            assert_eq!(bits.len(), width.div_ceil(8) * height);
+            assert_eq!(unpack_bilevel(&bits, width, height), gray);
+        }
+        // 中間調が1画素でもあればパックしない
+        let mut gray = bilevel_page(13, 5, 3);
+        gray[17] = 128;
+        assert!(pack_bilevel(&gray, 13, 5).is_none());
+    }
+
+    #[test]
+    fn bilevel_gray_and_rgba_give_identical_markers() {
+        let (width, height) = (61, 47);
+        let a = vec![255u8; width * height];
+        let mut b = a.clone();
+        for (x0, y0) in [(3, 4), (40, 30)] {
+            for y in y0..y0 + 6 {
+                b[y * width + x0..y * width + x0 + 9].fill(0);
+            }
+        }
+        let (w, h) = (width as u32, height as u32);
+        let (bits_a, bits_b) = match PixelPlane::gray_pair(a.clone(), b.clone(), w, h) {
+            (PixelPlane::Bilevel(pa), PixelPlane::Bilevel(pb)) => (pa, pb),
+            _ => panic!("expected bilevel planes"),
+        };
+        let rgba = |g: &[u8]| g.iter().flat_map(|&v| [v, v, v, 255]).collect::<Vec<u8>>();
+        let (rgba_a, rgba_b) = (rgba(&a), rgba(&b));
+
+        let params = DiffParams { min_radius: 1.0, marker_padding: 0.0, ..DiffParams::defaults(DiffMode::Simple) };
+        let metric = PixelMetric::from_params(10, None, None);
+        let run = |va: PixelView, vb: PixelView| {
+            let (buf, count, pixels) = diff_simple_core(va, vb, w, h, metric, None, None);
+            let markers = cluster_markers(&pixels, &params);
+            (buf, count, serde_json::to_string(&markers).unwrap())
+        };
+        let bilevel = run(PixelView::Bilevel(&bits_a), PixelView::Bilevel(&bits_b));
+        let gray = run(PixelView::Gray(&a), PixelView::Gray(&b));
+        let color = run(PixelView::Rgba(&rgba_a), PixelView::Rgba(&rgba_b));
+        assert_eq!(bilevel.1, 2 * 6 * 9);
+        assert_eq!(bilevel, gray);
+        assert_eq!(bilevel, color);
+    }
+}
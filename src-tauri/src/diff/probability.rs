@@ -0,0 +1,430 @@
+use crate::*;
+
+// ============== 差分確率 ==============
+//
+// diff_probability / DiffMarker.probability の尺度:
+//   0-100(%)。「実際の修正（描き足し・消し・写植変更）である確率」の推定値。
+//   マーカーごとに大きさ・強度・密度からロジスティック回帰で確率を求め、
+//   ページの確率は最も確率の高いマーカーの確率とする（noisy-ORは薄いノイズが多いだけで100%に近づくため使わない）。
+//   マーカーの特徴量の分布は比較方法ごとに違うので、係数も DiffMode ごとに持つ。
+//   目安: 50%以上 = 要確認, 90%以上 = ほぼ確実に修正あり, 10%未満 = ノイズの可能性が高い。
+//   係数は既知の「差分あり/なし」ペアを calibrate_diff_probability に通して調整できる。
+
+// マーカーの特徴量に掛けるロジスティック回帰の係数
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub(crate) struct ProbabilityModel {
+    pub(crate) bias: f64,
+    pub(crate) size: f64,      // ln(1 + ピクセル数)
+    pub(crate) intensity: f64, // 平均の差分の大きさ / delta_scale (0-1)
+    pub(crate) fill: f64,      // ピクセル数 / 外接矩形の面積 (0-1)
+}
+
+impl ProbabilityModel {
+    // 比較方法ごとの初期値。実データから学習した値ではなく手で決めたもの
+    // テストのフィクスチャに特徴量を代入して確認しながら、まばらな再スキャンノイズ ≒ 10%前後、
+    // 数百pxの描き足し・削除・中間調の修正 ≒ 90%以上になるよう調整した
+    // 単純比較のsizeは、アイコンをJPEG(q92)で保存し直しただけの画像（約2800pxの薄い差分）が
+    // 50%未満になるよう0.7から下げている。実際の原稿に合わせるには calibrate_diff_probability で求め直す
+    pub(crate) fn default_for(mode: DiffMode) -> Self {
+        match mode {
+            DiffMode::Simple => Self { bias: -6.0, size: 0.55, intensity: 8.0, fill: 2.0 },
+            // SSIMは窓単位で広がるのでピクセル数が多めに出る。強度は 1-SSIM
+            DiffMode::Ssim => Self { bias: -7.0, size: 0.8, intensity: 6.0, fill: 2.0 },
+            // 高密度の領域だけがマーカーになるので、残ったものは修正の可能性が高い
+            DiffMode::Heatmap => Self { bias: -5.0, size: 0.9, intensity: 3.0, fill: 1.5 },
+        }
+    }
+
+    pub(crate) fn features(marker: &DiffMarker, delta_scale: f64) -> [f64; 3] {
+        let b = &marker.bbox;
+        let area = ((b.right - b.left) as f64 * (b.bottom - b.top) as f64).max(1.0);
+        [
+            (1.0 + marker.count as f64).ln(),
+            (marker.mean_delta / delta_scale).clamp(0.0, 1.0),
+            (marker.count as f64 / area).min(1.0),
+        ]
+    }
+
+    // 0-1の確率
+    pub(crate) fn predict(&self, f: &[f64; 3]) -> f64 {
+        let z = self.bias + self.size * f[0] + self.intensity * f[1] + self.fill * f[2];
+        1.0 / (1.0 + (-z).exp())
+    }
+}
+
+// 比較方法ごとの係数（未指定の比較方法は初期値）
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(default)]
+pub(crate) struct ProbabilityModels {
+    pub(crate) simple: Option<ProbabilityModel>,
+    pub(crate) ssim: Option<ProbabilityModel>,
+    pub(crate) heatmap: Option<ProbabilityModel>,
+}
+
+impl ProbabilityModels {
+    pub(crate) fn get(&self, mode: DiffMode) -> Option<ProbabilityModel> {
+        match mode {
+            DiffMode::Simple => self.simple,
+            DiffMode::Ssim => self.ssim,
+            DiffMode::Heatmap => self.heatmap,
+        }
+    }
+}
+
+// ページの確率（最大のマーカー確率, 0-1）
+pub(crate) fn page_probability(marker_probs: impl IntoIterator<Item = f64>) -> f64 {
+    marker_probs.into_iter().fold(0.0, f64::max)
+}
+
+// 各マーカーに確率(%)を設定し、ページの確率(%)を返す
+pub(crate) fn score_markers(markers: &mut [DiffMarker], model: &ProbabilityModel, delta_scale: f64) -> f64 {
+    let mut probs = Vec::with_capacity(markers.len());
+    for m in markers.iter_mut() {
+        let p = model.predict(&ProbabilityModel::features(m, delta_scale));
+        m.probability = (p * 1000.0).round() / 10.0;
+        probs.push(p);
+    }
+    (page_probability(probs) * 1000.0).round() / 10.0
+}
+
+// キャリブレーション用の既知ペア（マニフェストJSONの1要素）
+#[derive(Deserialize, Clone)]
+pub(crate) struct CalibrationSample {
+    pub(crate) path_a: String, // マニフェストからの相対パスも可
+    pub(crate) path_b: String,
+    pub(crate) expected_diff: bool, // true = 修正あり（known-bad）, false = 修正なし（known-good）
+}
+
+#[derive(Serialize)]
+pub(crate) struct CalibrationSampleResult {
+    pub(crate) path_a: String,
+    pub(crate) path_b: String,
+    pub(crate) expected_diff: bool,
+    pub(crate) probability: f64, // 調整後のモデルでのページ確率(%)
+    pub(crate) marker_count: usize,
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct CalibrationReport {
+    pub(crate) mode: DiffMode, // DiffOptions.probability_models のどこに保存するか
+    pub(crate) model: ProbabilityModel,
+    pub(crate) accuracy: f64, // 50%を境界にした正解率(0-1)
+    pub(crate) log_loss: f64,
+    pub(crate) samples: Vec<CalibrationSampleResult>,
+}
+
+// ページ単位のラベルでlog lossを勾配降下で最小化
+// ページの確率は最大のマーカー確率なので、勾配はそのマーカーにだけ流れる
+pub(crate) fn fit_probability_model(pages: &[(Vec<[f64; 3]>, bool)], init: ProbabilityModel) -> ProbabilityModel {
+    let mut w = [init.bias, init.size, init.intensity, init.fill];
+    let learning_rate = 0.05;
+    let l2 = 1e-3; // 係数の発散防止
+    for _ in 0..3000 {
+        let mut grad = [0f64; 4];
+        for (features, expected) in pages {
+            let model = ProbabilityModel { bias: w[0], size: w[1], intensity: w[2], fill: w[3] };
+            let top = features
+                .iter()
+                .map(|f| (model.predict(f), f))
+                .max_by(|a, b| a.0.total_cmp(&b.0));
+            // マーカーがないページは係数で確率を変えられない
+            let Some((p, f)) = top else { continue };
+            let y = if *expected { 1.0 } else { 0.0 };
+            // ∂L/∂z = p - y
+            let dz = p - y;
+            grad[0] += dz;
+            for k in 0..3 {
+                grad[k + 1] += dz * f[k];
+            }
+        }
+        let n = pages.len().max(1) as f64;
+        for k in 0..4 {
+            w[k] -= learning_rate * (grad[k] / n + l2 * w[k]);
+        }
+    }
+    ProbabilityModel { bias: w[0], size: w[1], intensity: w[2], fill: w[3] }
+}
+
+// 比較方法ごとのマーカーと強度の正規化幅（各コマンドの標準の設定で差分を取る）
+pub(crate) fn diff_markers_for_mode(
+    mode: DiffMode, a: PixelView, b: PixelView, width: u32, height: u32, metric: PixelMetric, params: &DiffParams,
+) -> (Vec<DiffMarker>, f64) {
+    match mode {
+        DiffMode::Simple => {
+            let (_, _, pixels) = diff_simple_core(a, b, width, height, metric, None, None);
+            (cluster_markers(&pixels, params), metric.delta_scale())
+        }
+        DiffMode::Ssim => {
+            let luma_a = LumaPlane::from_view(a, width, height);
+            let luma_b = LumaPlane::from_view(b, width, height);
+            let ssim = diff_ssim_core(&luma_a, &luma_b, 3, 0.9, None);
+            (cluster_markers(&ssim.pixels, params), 1.0)
+        }
+        DiffMode::Heatmap => {
+            let (_, _, pixels) = diff_heatmap_core(a, b, width, height, metric, None, params, None);
+            (cluster_markers(&pixels, params), metric.delta_scale())
+        }
+    }
+}
+
+// 既知ペアのマニフェストで確率モデルを評価・調整する（回帰確認にも使う）
+// modeごとに別の係数を求める（結果は DiffOptions.probability_models の該当モードに保存する）
+// fit = false なら現在のモデルで評価のみ
+#[tauri::command]
+pub(crate) fn calibrate_diff_probability(
+    manifest_path: String, mode: Option<DiffMode>, threshold: u8, color_metric: Option<ColorMetric>,
+    delta_e: Option<f64>, options: Option<DiffOptions>, fit: Option<bool>,
+) -> Result<CalibrationReport, String> {
+    let text = fs::read_to_string(&manifest_path)
+        .map_err(|e| format!("Failed to read manifest: {}", e))?;
+    let samples: Vec<CalibrationSample> = serde_json::from_str(&text)
+        .map_err(|e| format!("Invalid manifest: {}", e))?;
+    let base_dir = PathBuf::from(&manifest_path).parent().map(|p| p.to_path_buf()).unwrap_or_default();
+    let resolve = |p: &str| base_dir.join(p).to_string_lossy().to_string();
+
+    let mode = mode.unwrap_or(DiffMode::Simple);
+    let params = resolve_diff_options(options.as_ref(), mode)?;
+    let metric = PixelMetric::from_params(threshold, color_metric, delta_e);
+
+    // 各ペアのマーカー特徴量を並列に抽出
+    let extracted: Vec<Result<Vec<[f64; 3]>, String>> = samples
+        .par_iter()
+        .map(|s| {
+            let pair = load_diff_pair(&resolve(&s.path_a), &resolve(&s.path_b), None, None, None)?;
+            let (markers, delta_scale) = diff_markers_for_mode(
+                mode, pair.a.view(), pair.b.view(), pair.width, pair.height, metric, &params,
+            );
+            Ok(markers.iter().map(|m| ProbabilityModel::features(m, delta_scale)).collect())
+        })
+        .collect();
+
+    let pages: Vec<(Vec<[f64; 3]>, bool)> = samples
+        .iter()
+        .zip(&extracted)
+        .filter_map(|(s, r)| r.as_ref().ok().map(|f| (f.clone(), s.expected_diff)))
+        .collect();
+    let model = if fit.unwrap_or(true) && !pages.is_empty() {
+        fit_probability_model(&pages, params.probability_model)
+    } else {
+        params.probability_model
+    };
+
+    let mut correct = 0usize;
+    let mut log_loss = 0.0;
+    let results: Vec<CalibrationSampleResult> = samples
+        .iter()
+        .zip(extracted)
+        .map(|(s, r)| match r {
+            Ok(features) => {
+                let p = page_probability(features.iter().map(|f| model.predict(f)));
+                if (p >= 0.5) == s.expected_diff {
+                    correct += 1;
+                }
+                let pc = p.clamp(1e-6, 1.0 - 1e-6);
+                log_loss -= if s.expected_diff { pc.ln() } else { (1.0 - pc).ln() };
+                CalibrationSampleResult {
+                    path_a: s.path_a.clone(),
+                    path_b: s.path_b.clone(),
+                    expected_diff: s.expected_diff,
+                    probability: (p * 1000.0).round() / 10.0,
+                    marker_count: features.len(),
+                    error: None,
+                }
+            }
+            Err(e) => CalibrationSampleResult {
+                path_a: s.path_a.clone(),
+                path_b: s.path_b.clone(),
+                expected_diff: s.expected_diff,
+                probability: 0.0,
+                marker_count: 0,
+                error: Some(e),
+            },
+        })
+        .collect();
+
+    let n = pages.len().max(1) as f64;
+    Ok(CalibrationReport {
+        mode,
+        model,
+        accuracy: correct as f64 / n,
+        log_loss: log_loss / n,
+        samples: results,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: u32 = 400;
+
+    // 白地に文字列を模した黒い横棒を並べたページ
+    fn page() -> Vec<u8> {
+        let mut buf = [255u8; 4].repeat((SIZE * SIZE) as usize);
+        for line in 0..8 {
+            let top = 30 + line * 45;
+            fill_rect(&mut buf, 40, top, 320, 12, 0);
+        }
+        buf
+    }
+
+    fn fill_rect(buf: &mut [u8], left: u32, top: u32, width: u32, height: u32, value: u8) {
+        for y in top..top + height {
+            for x in left..left + width {
+                let i = ((y * SIZE + x) * 4) as usize;
+                buf[i..i + 3].copy_from_slice(&[value; 3]);
+            }
+        }
+    }
+
+    // 再スキャン・再圧縮を模した薄いノイズ（まばらな孤立点）
+    fn add_noise(buf: &mut [u8], seed: u64) {
+        let mut state = seed;
+        for _ in 0..300 {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let x = ((state >> 33) % SIZE as u64) as u32;
+            let y = ((state >> 13) % SIZE as u64) as u32;
+            let i = ((y * SIZE + x) * 4) as usize;
+            buf[i] = buf[i].saturating_sub(12).max(if buf[i] == 0 { 12 } else { 0 });
+        }
+    }
+
+    // (ラベル, 画像A, 画像B, 修正あり)
+    fn fixtures() -> Vec<(&'static str, Vec<u8>, Vec<u8>, bool)> {
+        let base = page();
+        let mut noisy = base.clone();
+        add_noise(&mut noisy, 1);
+        let mut noisy2 = base.clone();
+        add_noise(&mut noisy2, 2);
+
+        let mut added = base.clone();
+        fill_rect(&mut added, 150, 250, 60, 60, 0); // 描き足し
+        let mut erased = noisy.clone();
+        fill_rect(&mut erased, 40, 120, 200, 12, 255); // 写植の削除
+        let mut retouched = base.clone();
+        fill_rect(&mut retouched, 300, 330, 40, 40, 60); // 中間調の修正
+
+        vec![
+            ("identical", base.clone(), base.clone(), false),
+            ("rescan noise", base.clone(), noisy.clone(), false),
+            ("rescan noise 2", noisy, noisy2, false),
+            ("added block", base.clone(), added, true),
+            ("erased line", base.clone(), erased, true),
+            ("retouched", base, retouched, true),
+        ]
+    }
+
+    fn fixture_probability(mode: DiffMode, a: &[u8], b: &[u8]) -> f64 {
+        let threshold = match mode {
+            DiffMode::Heatmap => 70,
+            _ => 5,
+        };
+        let metric = PixelMetric::from_params(threshold, None, None);
+        let params = DiffParams::defaults(mode);
+        let (mut markers, delta_scale) =
+            diff_markers_for_mode(mode, PixelView::Rgba(a), PixelView::Rgba(b), SIZE, SIZE, metric, &params);
+        score_markers(&mut markers, &params.probability_model, delta_scale)
+    }
+
+    #[test]
+    fn default_models_rank_fixtures() {
+        for mode in [DiffMode::Simple, DiffMode::Ssim, DiffMode::Heatmap] {
+            for (label, a, b, expected) in fixtures() {
+                let p = fixture_probability(mode, &a, &b);
+                assert_eq!(p >= 50.0, expected, "{} ({:?}): {}%", label, serde_json::to_string(&mode), p);
+            }
+        }
+    }
+
+    #[test]
+    fn page_probability_does_not_saturate() {
+        // 薄いノイズがいくら多くても修正ありにはならない
+        assert_eq!(page_probability(vec![0.1; 200]), 0.1);
+        assert_eq!(page_probability(vec![0.1, 0.95, 0.2]), 0.95);
+        assert_eq!(page_probability(Vec::new()), 0.0);
+    }
+
+    #[test]
+    fn fitted_model_keeps_fixture_order() {
+        let mode = DiffMode::Simple;
+        let metric = PixelMetric::from_params(5, None, None);
+        let params = DiffParams::defaults(mode);
+        let pages: Vec<(Vec<[f64; 3]>, bool)> = fixtures()
+            .into_iter()
+            .map(|(_, a, b, expected)| {
+                let (markers, delta_scale) = diff_markers_for_mode(
+                    mode, PixelView::Rgba(&a), PixelView::Rgba(&b), SIZE, SIZE, metric, &params,
+                );
+                (markers.iter().map(|m| ProbabilityModel::features(m, delta_scale)).collect(), expected)
+            })
+            .collect();
+        let model = fit_probability_model(&pages, params.probability_model);
+        for (features, expected) in &pages {
+            let p = page_probability(features.iter().map(|f| model.predict(f)));
+            assert_eq!(p >= 0.5, *expected, "{}", p);
+        }
+    }
+
+    // アプリのアイコンを白背景に合成した原稿と、その再エンコード（修正なし）・描き足し（修正あり）
+    fn write_icon_samples(dir: &std::path::Path) {
+        let icon = image::open(concat!(env!("CARGO_MANIFEST_DIR"), "/icons/128x128.png")).unwrap().to_rgba8();
+        let mut page = image::RgbImage::from_pixel(icon.width(), icon.height(), image::Rgb([255, 255, 255]));
+        for (x, y, p) in icon.enumerate_pixels() {
+            let a = p[3] as u32;
+            let blend = |c: u8| ((c as u32 * a + 255 * (255 - a)) / 255) as u8;
+            page.put_pixel(x, y, image::Rgb([blend(p[0]), blend(p[1]), blend(p[2])]));
+        }
+        fs::create_dir_all(dir).unwrap();
+        page.save(dir.join("original.png")).unwrap();
+
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 92).encode_image(&page).unwrap();
+        fs::write(dir.join("reencoded.jpg"), jpeg).unwrap();
+
+        let mut edited = page.clone();
+        for y in 20..32 {
+            for x in 90..102 {
+                edited.put_pixel(x, y, image::Rgb([0, 0, 0]));
+            }
+        }
+        edited.save(dir.join("edited.png")).unwrap();
+
+        let manifest = serde_json::json!([
+            { "path_a": "original.png", "path_b": "original.png", "expected_diff": false },
+            { "path_a": "original.png", "path_b": "reencoded.jpg", "expected_diff": false },
+            { "path_a": "original.png", "path_b": "edited.png", "expected_diff": true },
+        ]);
+        fs::write(dir.join("manifest.json"), manifest.to_string()).unwrap();
+    }
+
+    #[test]
+    fn default_models_separate_known_good_and_bad_files() {
+        let dir = std::env::temp_dir().join(format!("kenban-test-calibration-{}", std::process::id()));
+        write_icon_samples(&dir);
+        let manifest = dir.join("manifest.json").to_string_lossy().into_owned();
+        let reports: Vec<_> = [(DiffMode::Simple, 5), (DiffMode::Ssim, 5), (DiffMode::Heatmap, 70)]
+            .into_iter()
+            .map(|(mode, threshold)| {
+                calibrate_diff_probability(manifest.clone(), Some(mode), threshold, None, None, None, Some(false))
+            })
+            .collect();
+        let _ = fs::remove_dir_all(&dir);
+        for report in reports {
+            let report = report.unwrap();
+            let mode = serde_json::to_string(&report.mode).unwrap();
+            for sample in &report.samples {
+                assert!(sample.error.is_none(), "{}: {:?}", mode, sample.error);
+                assert_eq!(
+                    sample.probability >= 50.0,
+                    sample.expected_diff,
+                    "{} {}: {}%",
+                    mode,
+                    sample.path_b,
+                    sample.probability
+                );
+            }
+            assert_eq!(report.accuracy, 1.0, "{}", mode);
+        }
+    }
+}
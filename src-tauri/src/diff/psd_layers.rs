@@ -0,0 +1,727 @@
+use crate::*;
+
+// ============== PSDレイヤー単位の差分 ==============
+
+// PSDレイヤー情報
+#[derive(Serialize, Clone)]
+pub(crate) struct PsdLayerInfo {
+    pub(crate) index: usize,       // psd.layers()上の位置（0 = 最上層）
+    pub(crate) name: String,
+    pub(crate) path: String,       // "グループ/サブグループ/レイヤー名"
+    pub(crate) visible: bool,
+    pub(crate) opacity: u8,
+    pub(crate) left: i32,
+    pub(crate) top: i32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) blend_mode: String,
+    pub(crate) is_clipping_mask: bool,
+}
+
+// グループIDから階層パスを組み立てる（外側のグループが先頭）
+pub(crate) fn psd_group_path(psd: &Psd, group_id: Option<u32>) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut current = group_id;
+    // 壊れたファイルで親子関係が循環しても止まるよう深さを制限
+    while let Some(id) = current {
+        if names.len() >= 64 {
+            break;
+        }
+        match psd.groups().get(&id) {
+            Some(group) => {
+                names.push(group.name().to_string());
+                current = group.parent_id();
+            }
+            None => break,
+        }
+    }
+    names.reverse();
+    names
+}
+
+pub(crate) fn collect_psd_layers(psd: &Psd) -> Vec<PsdLayerInfo> {
+    psd.layers()
+        .iter()
+        .enumerate()
+        .map(|(index, layer)| {
+            let mut path = psd_group_path(psd, layer.parent_id());
+            path.push(layer.name().to_string());
+            PsdLayerInfo {
+                index,
+                name: layer.name().to_string(),
+                path: path.join("/"),
+                // psdクレートはフラグのbit1をそのまま返すが、実際のPhotoshopでは「非表示」ビット
+                visible: !layer.visible(),
+                opacity: layer.opacity(),
+                left: layer.layer_left(),
+                top: layer.layer_top(),
+                width: layer.width() as u32,
+                height: layer.height() as u32,
+                blend_mode: format!("{:?}", layer.blend_mode()),
+                // 同様にpsdクレートは「クリッピングベース（値0）」をtrueで返すので反転する
+                is_clipping_mask: !layer.is_clipping_mask(),
+            }
+        })
+        .collect()
+}
+
+// PSDファイルを読み込んでパース
+pub(crate) fn read_psd(path: &str) -> Result<Psd, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read PSD: {}", e))?;
+    Psd::from_bytes(&bytes).map_err(|e| format!("Failed to parse PSD: {}", e))
+}
+
+// レイヤー1枚を白背景に合成（PSDキャンバスサイズのRGBA、不透明度込み）
+pub(crate) fn render_psd_layer_on_white(layer: &psd::PsdLayer) -> Result<Vec<u8>, String> {
+    // psdクレートはチャネル欠落やキャンバス外のレイヤーでpanicすることがあるため捕捉する
+    let mut rgba = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| layer.rgba()))
+        .map_err(|_| format!("Failed to render layer '{}'", layer.name()))?;
+    let opacity = layer.opacity() as u32;
+    rgba.par_chunks_mut(4).for_each(|p| {
+        let alpha = p[3] as u32 * opacity / 255;
+        for v in &mut p[..3] {
+            *v = ((*v as u32 * alpha + 255 * (255 - alpha)) / 255) as u8;
+        }
+        p[3] = 255;
+    });
+    Ok(rgba)
+}
+
+// 合成時のレイヤー選択（パターンは * と ? のワイルドカード、レイヤー名・フルパス・親グループのパスに一致）
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct LayerFilter {
+    pub(crate) include: Vec<String>, // 空なら全レイヤー
+    pub(crate) exclude: Vec<String>, // includeより優先
+    pub(crate) include_hidden: bool, // 非表示レイヤー・非表示グループ内も合成する
+}
+
+impl LayerFilter {
+    pub(crate) fn matches_any(patterns: &[String], info: &PsdLayerInfo) -> bool {
+        // "セリフ" のようなグループ名/パスは配下の全レイヤーに一致させる
+        let mut prefixes = Vec::new();
+        let mut current = String::new();
+        for part in info.path.split('/') {
+            if !current.is_empty() {
+                current.push('/');
+            }
+            current.push_str(part);
+            prefixes.push(current.clone());
+        }
+        patterns.iter().any(|p| {
+            wildcard_match(p, &info.name) || prefixes.iter().any(|prefix| wildcard_match(p, prefix))
+        })
+    }
+
+    pub(crate) fn accepts(&self, info: &PsdLayerInfo, effectively_visible: bool) -> bool {
+        if !self.include_hidden && !effectively_visible {
+            return false;
+        }
+        if !self.include.is_empty() && !Self::matches_any(&self.include, info) {
+            return false;
+        }
+        !Self::matches_any(&self.exclude, info)
+    }
+}
+
+// * (任意の文字列) と ? (任意の1文字) のみのワイルドカード一致
+pub(crate) fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            // 直前の * に1文字多く吸わせてやり直す
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+// レイヤー自身と親グループがすべて表示されているか
+// psdクレートはグループの属性を終端レコード（</Layer group>）から読むため、そちらの非表示フラグで判定する
+pub(crate) fn psd_layer_effectively_visible(psd: &Psd, info: &PsdLayerInfo, layer: &psd::PsdLayer) -> bool {
+    if !info.visible {
+        return false;
+    }
+    let mut current = layer.parent_id();
+    let mut depth = 0;
+    while let Some(id) = current {
+        let Some(group) = psd.groups().get(&id) else { break };
+        // レイヤーと同じくbit1は実際には非表示フラグ
+        if group.visible() {
+            return false;
+        }
+        depth += 1;
+        if depth >= 64 {
+            break;
+        }
+        current = group.parent_id();
+    }
+    true
+}
+
+// フィルタを通ったレイヤーを下から順に白背景へ通常合成（描画モードは無視）
+// psd.layers()は上のレイヤーから並んでいるので逆順に重ねる
+// クリッピングされたレイヤーは直下のベースの不透明部分にのみ描画し、ベースが合成されない場合は省く
+pub(crate) fn composite_psd_layers(psd: &Psd, filter: &LayerFilter) -> Result<Vec<u8>, String> {
+    let infos = collect_psd_layers(psd);
+    let mut canvas = vec![255u8; (psd.width() as usize) * (psd.height() as usize) * 4];
+    let mut base_alpha: Option<Vec<u8>> = None; // 直近のクリッピングベースのアルファ（合成しなかった場合はNone）
+    for (info, layer) in infos.iter().zip(psd.layers().iter()).rev() {
+        let accepted = filter.accepts(info, psd_layer_effectively_visible(psd, info, layer));
+        if !info.is_clipping_mask && !accepted {
+            base_alpha = None;
+        }
+        if !accepted || (info.is_clipping_mask && base_alpha.is_none()) {
+            continue;
+        }
+        let rgba = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| layer.rgba()))
+            .map_err(|_| format!("Failed to render layer '{}'", info.path))?;
+        if !info.is_clipping_mask {
+            base_alpha = Some(rgba.par_chunks(4).map(|p| p[3]).collect());
+        }
+        let clip = if info.is_clipping_mask { base_alpha.as_deref() } else { None };
+        let opacity = layer.opacity() as u32;
+        canvas.par_chunks_mut(4).zip(rgba.par_chunks(4)).enumerate().for_each(|(i, (dst, src))| {
+            let mut alpha = src[3] as u32 * opacity / 255;
+            if let Some(clip) = clip {
+                alpha = alpha * clip[i] as u32 / 255;
+            }
+            if alpha == 0 {
+                return;
+            }
+            for c in 0..3 {
+                dst[c] = ((src[c] as u32 * alpha + dst[c] as u32 * (255 - alpha)) / 255) as u8;
+            }
+        });
+    }
+    Ok(canvas)
+}
+
+// レイヤー単位の比較結果の種類
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LayerDiffStatus {
+    Changed,
+    Unchanged,
+    OnlyA, // Aにのみ存在（削除されたレイヤー）
+    OnlyB, // Bにのみ存在（追加されたレイヤー）
+    Error, // レイヤーの展開に失敗（比較不能）
+}
+
+#[derive(Serialize)]
+pub(crate) struct LayerDiffEntry {
+    pub(crate) path: String,
+    pub(crate) name: String,
+    pub(crate) status: LayerDiffStatus,
+    pub(crate) index_a: Option<usize>,
+    pub(crate) index_b: Option<usize>,
+    pub(crate) properties_changed: bool, // 表示/不透明度/描画モードの変更
+    pub(crate) diff_count: u32,
+    pub(crate) markers: Vec<DiffMarker>,
+    pub(crate) diff_src: Option<String>, // include_images指定時の変更レイヤーのみ
+    pub(crate) error: Option<String>,    // レイヤーの展開に失敗した場合
+}
+
+#[derive(Serialize)]
+pub(crate) struct PsdLayerDiffResult {
+    pub(crate) layers: Vec<LayerDiffEntry>,
+    pub(crate) changed_layers: Vec<String>, // 変更・追加・削除のあったレイヤーパス（比較できなかったレイヤーも含む）
+    pub(crate) errored_layers: Vec<String>, // 展開に失敗して比較できなかったレイヤーパス
+    pub(crate) image_width: u32,
+    pub(crate) image_height: u32,
+}
+
+// 2つのPSDのレイヤーを対応付け（パス一致 → 名前一致の順、同名は出現順）
+pub(crate) fn match_psd_layers(
+    layers_a: &[PsdLayerInfo], layers_b: &[PsdLayerInfo],
+) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut used_b = vec![false; layers_b.len()];
+    let mut matched_a: Vec<Option<usize>> = vec![None; layers_a.len()];
+
+    for (ia, la) in layers_a.iter().enumerate() {
+        if let Some(jb) = (0..layers_b.len()).find(|&jb| !used_b[jb] && layers_b[jb].path == la.path) {
+            used_b[jb] = true;
+            matched_a[ia] = Some(jb);
+        }
+    }
+    for (ia, la) in layers_a.iter().enumerate() {
+        if matched_a[ia].is_some() {
+            continue;
+        }
+        if let Some(jb) = (0..layers_b.len()).find(|&jb| !used_b[jb] && layers_b[jb].name == la.name) {
+            used_b[jb] = true;
+            matched_a[ia] = Some(jb);
+        }
+    }
+
+    let mut pairs: Vec<(Option<usize>, Option<usize>)> = matched_a
+        .into_iter()
+        .enumerate()
+        .map(|(ia, jb)| (Some(ia), jb))
+        .collect();
+    pairs.extend((0..layers_b.len()).filter(|&jb| !used_b[jb]).map(|jb| (None, Some(jb))));
+    pairs
+}
+
+// PSDのレイヤー一覧を取得
+#[tauri::command]
+pub(crate) fn list_psd_layers(path: String) -> Result<Vec<PsdLayerInfo>, String> {
+    let psd = read_psd(&path)?;
+    Ok(collect_psd_layers(&psd))
+}
+
+// psd-psd のレイヤー単位差分（対応するレイヤー同士を個別に比較）
+#[tauri::command]
+#[allow(clippy::too_many_arguments)] // フロントエンドから個別の引数で渡される
+pub(crate) fn compute_psd_layer_diff(
+    state: State<'_, AppState>,
+    path_a: String, path_b: String, threshold: u8,
+    color_metric: Option<ColorMetric>, delta_e: Option<f64>, include_images: Option<bool>,
+    options: Option<DiffOptions>, delivery: Option<ImageDelivery>,
+) -> Result<PsdLayerDiffResult, String> {
+    diff_psd_layers(
+        &state, &path_a, &path_b, threshold, color_metric, delta_e, include_images, options.as_ref(), delivery,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn diff_psd_layers(
+    state: &AppState,
+    path_a: &str, path_b: &str, threshold: u8,
+    color_metric: Option<ColorMetric>, delta_e: Option<f64>, include_images: Option<bool>,
+    options: Option<&DiffOptions>, delivery: Option<ImageDelivery>,
+) -> Result<PsdLayerDiffResult, String> {
+    let params = resolve_diff_options(options, DiffMode::Simple)?;
+    let (psd_a, psd_b) = rayon::join(|| read_psd(path_a), || read_psd(path_b));
+    let psd_a = psd_a?;
+    let psd_b = psd_b?;
+
+    let width = psd_a.width();
+    let height = psd_a.height();
+    let layers_a = collect_psd_layers(&psd_a);
+    let layers_b = collect_psd_layers(&psd_b);
+    let metric = PixelMetric::from_params(threshold, color_metric, delta_e);
+    let include_images = include_images.unwrap_or(false);
+
+    // Bのキャンバスサイズが異なる場合はAに揃える
+    let render_b = |idx: usize| -> Result<Vec<u8>, String> {
+        let rgba = render_psd_layer_on_white(&psd_b.layers()[idx])?;
+        if psd_b.width() == width && psd_b.height() == height {
+            return Ok(rgba);
+        }
+        let img: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_raw(psd_b.width(), psd_b.height(), rgba)
+            .ok_or_else(|| "Failed to create image buffer from PSD layer".to_string())?;
+        Ok(image::imageops::resize(&img, width, height, FilterType::Triangle).into_raw())
+    };
+
+    // レイヤーごとにキャンバス全体のバッファを使うため、メモリ節約のため逐次処理（各差分は内部で並列）
+    let mut entries = Vec::new();
+    for (ia, ib) in match_psd_layers(&layers_a, &layers_b) {
+        let info = ia.map(|i| &layers_a[i]).or(ib.map(|i| &layers_b[i])).unwrap();
+        let mut entry = LayerDiffEntry {
+            path: info.path.clone(),
+            name: info.name.clone(),
+            status: LayerDiffStatus::Unchanged,
+            index_a: ia,
+            index_b: ib,
+            properties_changed: false,
+            diff_count: 0,
+            markers: Vec::new(),
+            diff_src: None,
+            error: None,
+        };
+
+        match (ia, ib) {
+            (Some(ia), Some(ib)) => {
+                let (la, lb) = (&layers_a[ia], &layers_b[ib]);
+                entry.properties_changed = la.visible != lb.visible
+                    || la.opacity != lb.opacity
+                    || la.blend_mode != lb.blend_mode;
+
+                let (rgba_a, rgba_b) = rayon::join(
+                    || render_psd_layer_on_white(&psd_a.layers()[ia]),
+                    || render_b(ib),
+                );
+                let (rgba_a, rgba_b) = match (rgba_a, rgba_b) {
+                    (Ok(a), Ok(b)) => (a, b),
+                    (Err(e), _) | (_, Err(e)) => {
+                        // 1レイヤーの失敗で全体を止めない
+                        entry.status = LayerDiffStatus::Error;
+                        entry.error = Some(e);
+                        entries.push(entry);
+                        continue;
+                    }
+                };
+                let (diff_buf, diff_count, diff_pixels) =
+                    diff_simple_core(
+                        PixelView::Rgba(&rgba_a), PixelView::Rgba(&rgba_b), width, height, metric, None, None,
+                    );
+
+                entry.diff_count = diff_count;
+                entry.markers = cluster_markers(&diff_pixels, &params);
+                score_markers(&mut entry.markers, &params.probability_model, metric.delta_scale());
+                if diff_count > 0 || entry.properties_changed {
+                    entry.status = LayerDiffStatus::Changed;
+                }
+                if include_images && diff_count > 0 {
+                    let [diff_src] = deliver_images(state, delivery, [StoredImage::rgba(diff_buf, width, height)])?;
+                    entry.diff_src = Some(diff_src);
+                }
+            }
+            (Some(_), None) => entry.status = LayerDiffStatus::OnlyA,
+            (None, Some(_)) => entry.status = LayerDiffStatus::OnlyB,
+            (None, None) => continue,
+        }
+        entries.push(entry);
+    }
+
+    // 比較できなかったレイヤーは変更なしと断定できないため変更扱いにも含める
+    let changed_layers = entries
+        .iter()
+        .filter(|e| e.status != LayerDiffStatus::Unchanged)
+        .map(|e| e.path.clone())
+        .collect();
+    let errored_layers = entries
+        .iter()
+        .filter(|e| e.status == LayerDiffStatus::Error)
+        .map(|e| e.path.clone())
+        .collect();
+
+    Ok(PsdLayerDiffResult {
+        layers: entries,
+        changed_layers,
+        errored_layers,
+        image_width: width,
+        image_height: height,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // テスト用PSDの構成要素（上のレイヤーから順に並べる）
+    pub(crate) enum TestRecord {
+        Layer { name: &'static str, hidden: bool, clipped: bool, rect: (i32, i32, i32, i32), color: [u8; 4] },
+        GroupStart { name: &'static str, hidden: bool },
+        GroupEnd,
+    }
+
+    impl TestRecord {
+        // rectは (left, top, right, bottom)、右下は含まない
+        pub(crate) fn layer(name: &'static str, rect: (i32, i32, i32, i32), color: [u8; 4]) -> Self {
+            TestRecord::Layer { name, hidden: false, clipped: false, rect, color }
+        }
+    }
+
+    fn push_u16(buf: &mut Vec<u8>, v: u16) {
+        buf.extend_from_slice(&v.to_be_bytes());
+    }
+
+    fn push_u32(buf: &mut Vec<u8>, v: u32) {
+        buf.extend_from_slice(&v.to_be_bytes());
+    }
+
+    // 非圧縮・8bitの最小限のPSDを組み立てる（統合画像は白、channelsは1=グレースケール/3=RGB）
+    pub(crate) fn build_test_psd(width: u32, height: u32, channels: u16, records: &[TestRecord]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(b"8BPS");
+        push_u16(&mut buf, 1);
+        buf.extend_from_slice(&[0; 6]);
+        push_u16(&mut buf, channels);
+        push_u32(&mut buf, height);
+        push_u32(&mut buf, width);
+        push_u16(&mut buf, 8);
+        push_u16(&mut buf, if channels == 1 { 1 } else { 3 });
+        push_u32(&mut buf, 0); // カラーモードデータ
+        push_u32(&mut buf, 0); // 画像リソース
+
+        // レイヤー情報（ファイル上は下のレイヤーから並ぶ）
+        let mut info = Vec::new();
+        let mut channel_data = Vec::new();
+        info.extend_from_slice(&(records.len() as i16).to_be_bytes());
+        // 終端レコードにも開始レコードと同じ非表示フラグを立てる（psdクレートはグループの属性を終端から読む）
+        let mut open = Vec::new();
+        let end_hidden: Vec<bool> = records
+            .iter()
+            .map(|record| match record {
+                TestRecord::GroupStart { hidden, .. } => {
+                    open.push(*hidden);
+                    false
+                }
+                TestRecord::GroupEnd => open.pop().unwrap_or(false),
+                TestRecord::Layer { .. } => false,
+            })
+            .collect();
+        for (record, end_hidden) in records.iter().zip(end_hidden).rev() {
+            let (name, flags, clipping, rect, divider, color) = match record {
+                TestRecord::Layer { name, hidden, clipped, rect, color } => {
+                    (*name, if *hidden { 2 } else { 0 }, *clipped as u8, *rect, None, Some(*color))
+                }
+                TestRecord::GroupStart { name, hidden } => {
+                    (*name, if *hidden { 2 } else { 0 }, 0, (0, 0, 0, 0), Some(1), None)
+                }
+                TestRecord::GroupEnd => ("</Layer group>", if end_hidden { 2 } else { 0 }, 0, (0, 0, 0, 0), Some(3), None),
+            };
+            let (left, top, right, bottom) = rect;
+            for v in [top, left, bottom, right] {
+                info.extend_from_slice(&v.to_be_bytes());
+            }
+            let pixels = ((right - left) * (bottom - top)).max(0) as usize;
+            // グレースケールのレイヤーはグレー(0)と透明度(-1)のみ
+            let layer_channels: Vec<(i16, u8)> = match color {
+                Some(color) if channels == 1 => vec![(0, color[0]), (-1, color[3])],
+                Some(color) => vec![(0, color[0]), (1, color[1]), (2, color[2]), (-1, color[3])],
+                None => Vec::new(),
+            };
+            match color {
+                Some(_) => {
+                    push_u16(&mut info, layer_channels.len() as u16);
+                    for (id, value) in layer_channels {
+                        info.extend_from_slice(&id.to_be_bytes());
+                        push_u32(&mut info, pixels as u32 + 2);
+                        push_u16(&mut channel_data, 0);
+                        channel_data.resize(channel_data.len() + pixels, value);
+                    }
+                }
+                None => push_u16(&mut info, 0),
+            }
+            info.extend_from_slice(b"8BIMnorm");
+            info.extend_from_slice(&[255, clipping, flags, 0]);
+
+            let mut extra = Vec::new();
+            push_u32(&mut extra, 0); // レイヤーマスク
+            push_u32(&mut extra, 0); // ブレンド範囲
+            extra.push(name.len() as u8);
+            extra.extend_from_slice(name.as_bytes());
+            while extra.len() % 4 != 0 {
+                extra.push(0);
+            }
+            if let Some(divider) = divider {
+                extra.extend_from_slice(b"8BIMlsct");
+                push_u32(&mut extra, 4);
+                push_u32(&mut extra, divider);
+            }
+            push_u32(&mut info, extra.len() as u32);
+            info.extend_from_slice(&extra);
+        }
+        info.extend_from_slice(&channel_data);
+        if info.len() % 2 != 0 {
+            info.push(0);
+        }
+        if records.is_empty() {
+            push_u32(&mut buf, 0); // レイヤーなし（統合画像のみ）
+        } else {
+            push_u32(&mut buf, info.len() as u32 + 8);
+            push_u32(&mut buf, info.len() as u32);
+            buf.extend_from_slice(&info);
+            push_u32(&mut buf, 0); // グローバルレイヤーマスク
+        }
+
+        push_u16(&mut buf, 0);
+        buf.resize(buf.len() + (width * height) as usize * channels as usize, 255);
+        buf
+    }
+
+    pub(crate) fn write_test_psd(label: &str, bytes: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("kenban-test-{}-{}.psd", label, std::process::id()));
+        fs::write(&path, bytes).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn hidden_flag_is_inverted_for_layers_and_groups() {
+        let bytes = build_test_psd(4, 4, 3, &[
+            TestRecord::Layer { name: "非表示", hidden: true, clipped: false, rect: (0, 0, 2, 2), color: [255, 0, 0, 255] },
+            TestRecord::GroupStart { name: "閉じたグループ", hidden: true },
+            TestRecord::layer("中", (0, 0, 4, 4), [0, 255, 0, 255]),
+            TestRecord::GroupEnd,
+            TestRecord::layer("背景", (0, 0, 4, 4), [0, 0, 255, 255]),
+        ]);
+        let psd = Psd::from_bytes(&bytes).unwrap();
+        let infos = collect_psd_layers(&psd);
+        let summary: Vec<(&str, bool)> = infos.iter().map(|i| (i.path.as_str(), i.visible)).collect();
+        assert_eq!(summary, [("非表示", false), ("閉じたグループ/中", true), ("背景", true)]);
+
+        let visible: Vec<bool> = infos
+            .iter()
+            .zip(psd.layers().iter())
+            .map(|(info, layer)| psd_layer_effectively_visible(&psd, info, layer))
+            .collect();
+        assert_eq!(visible, [false, false, true]);
+    }
+
+    #[test]
+    fn layer_diff_lists_changed_added_and_errored_layers() {
+        // "はみ出し" はキャンバス外へ出るためpsdクレートの展開がpanicする
+        let path_a = write_test_psd("layers-a", &build_test_psd(8, 8, 3, &[
+            TestRecord::layer("はみ出し", (6, 6, 10, 10), [0, 0, 0, 255]),
+            TestRecord::layer("文字", (0, 0, 4, 4), [0, 0, 0, 255]),
+            TestRecord::layer("背景", (0, 0, 8, 8), [255, 255, 255, 255]),
+        ]));
+        let path_b = write_test_psd("layers-b", &build_test_psd(8, 8, 3, &[
+            TestRecord::layer("追加", (6, 0, 8, 2), [255, 0, 0, 255]),
+            TestRecord::layer("はみ出し", (6, 6, 10, 10), [0, 0, 0, 255]),
+            TestRecord::layer("文字", (2, 2, 6, 6), [0, 0, 0, 255]),
+            TestRecord::layer("背景", (0, 0, 8, 8), [255, 255, 255, 255]),
+        ]));
+        let state = AppState::for_tests();
+        let options = DiffOptions { min_radius: Some(1.0), marker_padding: Some(0.0), ..Default::default() };
+        let result =
+            diff_psd_layers(&state, &path_a, &path_b, 10, None, None, None, Some(&options), None).unwrap();
+        let _ = fs::remove_file(&path_a);
+        let _ = fs::remove_file(&path_b);
+
+        let statuses: Vec<(&str, LayerDiffStatus)> = result.layers.iter().map(|e| (e.path.as_str(), e.status)).collect();
+        assert_eq!(statuses, [
+            ("はみ出し", LayerDiffStatus::Error),
+            ("文字", LayerDiffStatus::Changed),
+            ("背景", LayerDiffStatus::Unchanged),
+            ("追加", LayerDiffStatus::OnlyB),
+        ]);
+        assert_eq!(result.changed_layers, ["はみ出し", "文字", "追加"]);
+        assert_eq!(result.errored_layers, ["はみ出し"]);
+        // 指定したmin_radiusがマーカーに反映される
+        let moved = &result.layers[1];
+        assert!(moved.error.is_none() && result.layers[0].error.is_some());
+        assert!(!moved.markers.is_empty() && moved.markers.iter().all(|m| m.radius < 300.0));
+    }
+
+    #[test]
+    fn layer_diff_rejects_invalid_options() {
+        let state = AppState::for_tests();
+        let options = DiffOptions { marker_padding: Some(f64::NAN), ..Default::default() };
+        let err = diff_psd_layers(&state, "missing-a.psd", "missing-b.psd", 10, None, None, None, Some(&options), None);
+        assert!(err.err().unwrap().contains("marker_padding"));
+    }
+
+    fn info(path: &str) -> PsdLayerInfo {
+        PsdLayerInfo {
+            index: 0,
+            name: path.rsplit('/').next().unwrap().to_string(),
+            path: path.to_string(),
+            visible: true,
+            opacity: 255,
+            left: 0,
+            top: 0,
+            width: 1,
+            height: 1,
+            blend_mode: "Normal".to_string(),
+            is_clipping_mask: false,
+        }
+    }
+
+    fn patterns(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn wildcard_matches_star_and_question_mark() {
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("*", "セリフ"));
+        assert!(wildcard_match("セリフ*", "セリフ 2"));
+        assert!(wildcard_match("*写植*", "本文写植レイヤー"));
+        assert!(wildcard_match("p?", "p1"));
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(!wildcard_match("p?", "p"));
+        assert!(!wildcard_match("p?", "p12"));
+        assert!(!wildcard_match("a*c", "abcd"));
+        assert!(!wildcard_match("", "a"));
+        assert!(!wildcard_match("背景", "背景2"));
+    }
+
+    #[test]
+    fn exclude_takes_precedence_over_include() {
+        let filter = LayerFilter {
+            include: patterns(&["セリフ"]),
+            exclude: patterns(&["*仮*"]),
+            include_hidden: false,
+        };
+        // グループ名は配下の全レイヤーに一致する
+        assert!(filter.accepts(&info("セリフ/吹き出し1"), true));
+        assert!(!filter.accepts(&info("セリフ/仮テキスト"), true));
+        assert!(!filter.accepts(&info("背景"), true));
+        // 非表示レイヤーはinclude_hiddenがなければ除外
+        assert!(!filter.accepts(&info("セリフ/吹き出し1"), false));
+        let with_hidden = LayerFilter { include_hidden: true, ..filter.clone() };
+        assert!(with_hidden.accepts(&info("セリフ/吹き出し1"), false));
+
+        // includeが空なら除外パターン以外すべて
+        let exclude_only = LayerFilter { exclude: patterns(&["背景"]), ..Default::default() };
+        assert!(exclude_only.accepts(&info("セリフ/吹き出し1"), true));
+        assert!(!exclude_only.accepts(&info("背景"), true));
+        // 親グループのパスにもワイルドカードが効く
+        let nested = LayerFilter { include: patterns(&["作画/*"]), ..Default::default() };
+        assert!(nested.accepts(&info("作画/線画/顔"), true));
+        assert!(!nested.accepts(&info("作画"), true));
+    }
+
+    fn pixel(canvas: &[u8], width: u32, x: u32, y: u32) -> [u8; 3] {
+        let i = ((y * width + x) * 4) as usize;
+        [canvas[i], canvas[i + 1], canvas[i + 2]]
+    }
+
+    #[test]
+    fn clipped_layers_follow_their_base() {
+        let bytes = build_test_psd(4, 4, 3, &[
+            TestRecord::Layer { name: "トーン", hidden: false, clipped: true, rect: (0, 0, 4, 4), color: [0, 0, 255, 255] },
+            TestRecord::layer("ベタ", (0, 0, 2, 2), [255, 0, 0, 255]),
+        ]);
+        let psd = Psd::from_bytes(&bytes).unwrap();
+        let clipping: Vec<bool> = collect_psd_layers(&psd).iter().map(|i| i.is_clipping_mask).collect();
+        assert_eq!(clipping, [true, false]);
+
+        let all = composite_psd_layers(&psd, &LayerFilter::default()).unwrap();
+        assert_eq!(pixel(&all, 4, 0, 0), [0, 0, 255]);
+        assert_eq!(pixel(&all, 4, 3, 3), [255, 255, 255]);
+
+        // ベースを除外するとクリッピングされたレイヤーも描画しない
+        let without_base = LayerFilter { exclude: patterns(&["ベタ"]), ..Default::default() };
+        let composite = composite_psd_layers(&psd, &without_base).unwrap();
+        assert!(composite.iter().all(|&v| v == 255));
+
+        let base_only = LayerFilter { exclude: patterns(&["トーン"]), ..Default::default() };
+        let composite = composite_psd_layers(&psd, &base_only).unwrap();
+        assert_eq!(pixel(&composite, 4, 1, 1), [255, 0, 0]);
+        assert_eq!(pixel(&composite, 4, 2, 2), [255, 255, 255]);
+    }
+
+    #[test]
+    fn layer_filter_on_flattened_psd_is_an_error() {
+        let path = write_test_psd("flat", &build_test_psd(4, 4, 3, &[]));
+        let filter = LayerFilter { include: patterns(&["セリフ"]), ..Default::default() };
+        let filtered = decode_psd_to_image(&path, Some(&filter));
+        let plain = decode_psd_to_image(&path, None);
+        let _ = fs::remove_file(&path);
+        assert!(filtered.err().unwrap().contains("no layer"));
+        assert_eq!(plain.unwrap().dimensions(), (4, 4));
+    }
+
+    #[test]
+    fn filtered_grayscale_psd_stays_gray() {
+        let path = write_test_psd("gray", &build_test_psd(6, 4, 1, &[
+            TestRecord::layer("セリフ", (0, 0, 3, 2), [0, 0, 0, 255]),
+            TestRecord::layer("背景", (0, 0, 6, 4), [255, 255, 255, 255]),
+        ]));
+        let filter = LayerFilter { include: patterns(&["セリフ"]), ..Default::default() };
+        let filtered = decode_psd_to_image(&path, Some(&filter));
+        let _ = fs::remove_file(&path);
+        let img = filtered.unwrap();
+        assert!(is_gray_image(&img));
+        let luma = img.to_luma8();
+        assert_eq!((luma.get_pixel(1, 1)[0], luma.get_pixel(4, 3)[0]), (0, 255));
+    }
+}
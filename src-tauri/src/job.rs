@@ -0,0 +1,132 @@
+use crate::*;
+
+// ============== ジョブ（進捗通知・キャンセル） ==============
+pub(crate) const JOB_PROGRESS_EVENT: &str = "job-progress";
+// キャンセル時のエラーメッセージ（フロントエンドはこの文字列で判定する）
+pub(crate) const JOB_CANCELLED: &str = "Job cancelled";
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum JobPhase {
+    Decode,
+    Diff,
+    Cluster,
+    Encode,
+    Done,
+}
+
+#[derive(Serialize, Clone)]
+pub(crate) struct JobProgressEvent {
+    pub(crate) job_id: String,
+    pub(crate) phase: JobPhase,
+    pub(crate) done: u64,
+    pub(crate) total: u64,
+}
+
+// 実行中の処理（job_id未指定の場合は通知もキャンセルもしない）
+pub(crate) struct Job {
+    pub(crate) id: Option<String>,
+    pub(crate) app: tauri::AppHandle,
+    pub(crate) cancelled: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    pub(crate) phase: Mutex<JobPhase>,
+    pub(crate) total: std::sync::atomic::AtomicU64,
+    pub(crate) done: std::sync::atomic::AtomicU64,
+    pub(crate) emitted_permille: std::sync::atomic::AtomicU64, // 通知済みの進捗（1%ごとに通知）
+}
+
+impl Job {
+    pub(crate) fn start(app: &tauri::AppHandle, id: Option<String>) -> Result<Self, String> {
+        let cancelled = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        if let Some(id) = &id {
+            let state = app.state::<AppState>();
+            let mut jobs = state.jobs.lock().map_err(|e| e.to_string())?;
+            jobs.insert(id.clone(), cancelled.clone());
+        }
+        Ok(Self {
+            id,
+            app: app.clone(),
+            cancelled,
+            phase: Mutex::new(JobPhase::Decode),
+            total: 0.into(),
+            done: 0.into(),
+            emitted_permille: 0.into(),
+        })
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub(crate) fn check(&self) -> Result<(), String> {
+        if self.is_cancelled() {
+            return Err(JOB_CANCELLED.to_string());
+        }
+        Ok(())
+    }
+
+    // 次の段階へ（totalは行数や画像数）
+    pub(crate) fn phase(&self, phase: JobPhase, total: u64) {
+        use std::sync::atomic::Ordering;
+        if let Ok(mut p) = self.phase.lock() {
+            *p = phase;
+        }
+        self.total.store(total, Ordering::Relaxed);
+        self.done.store(0, Ordering::Relaxed);
+        self.emitted_permille.store(0, Ordering::Relaxed);
+        self.emit(phase, 0, total);
+    }
+
+    // rayonの各スレッドから呼ばれる
+    pub(crate) fn advance(&self, n: u64) {
+        use std::sync::atomic::Ordering;
+        if self.id.is_none() {
+            return;
+        }
+        let total = self.total.load(Ordering::Relaxed).max(1);
+        let done = (self.done.fetch_add(n, Ordering::Relaxed) + n).min(total);
+        let permille = done * 1000 / total;
+        let prev = self.emitted_permille.load(Ordering::Relaxed);
+        if (permille >= prev + 10 || done == total)
+            && permille > prev
+            && self.emitted_permille.compare_exchange(prev, permille, Ordering::Relaxed, Ordering::Relaxed).is_ok()
+        {
+            let phase = self.phase.lock().map(|p| *p).unwrap_or(JobPhase::Decode);
+            self.emit(phase, done, total);
+        }
+    }
+
+    pub(crate) fn emit(&self, phase: JobPhase, done: u64, total: u64) {
+        if let Some(id) = &self.id {
+            let _ = self.app.emit(JOB_PROGRESS_EVENT, JobProgressEvent { job_id: id.clone(), phase, done, total });
+        }
+    }
+
+    pub(crate) fn finish(&self) {
+        self.phase(JobPhase::Done, 0);
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        let Some(id) = &self.id else { return };
+        let state = self.app.state::<AppState>();
+        let Ok(mut jobs) = state.jobs.lock() else { return };
+        // 同じIDで新しいジョブが始まっていたら残す
+        if jobs.get(id).is_some_and(|flag| std::sync::Arc::ptr_eq(flag, &self.cancelled)) {
+            jobs.remove(id);
+        }
+    }
+}
+
+// 実行中のジョブを中断（各処理は行単位で打ち切り、JOB_CANCELLEDエラーを返す）
+#[tauri::command]
+pub(crate) fn cancel_job(state: State<'_, AppState>, job_id: String) -> Result<bool, String> {
+    let jobs = state.jobs.lock().map_err(|e| e.to_string())?;
+    Ok(match jobs.get(&job_id) {
+        Some(flag) => {
+            flag.store(true, std::sync::atomic::Ordering::Relaxed);
+            true
+        }
+        None => false,
+    })
+}
@@ -0,0 +1,108 @@
+use crate::*;
+
+// ============== CSVレポート ==============
+
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// 1ペア1行。markersは "left:top:right:bottom:ピクセル数:確率" を ; 区切り
+pub(crate) fn report_to_csv(report: &DiffReport) -> String {
+    let mut out = String::from(
+        "index,name_a,name_b,status,exceeded,image_width,image_height,diff_count,diff_probability,\
+         marker_count,markers,diff_image,error\n",
+    );
+    for p in &report.pairs {
+        let status = serde_json::to_value(p.status)
+            .ok()
+            .and_then(|v| v.as_str().map(|s| s.to_string()))
+            .unwrap_or_default();
+        let markers = p
+            .markers
+            .iter()
+            .map(|m| {
+                let b = &m.bbox;
+                format!("{}:{}:{}:{}:{}:{}", b.left, b.top, b.right, b.bottom, m.count, m.probability)
+            })
+            .collect::<Vec<_>>()
+            .join(";");
+        let fields = [
+            (p.index + 1).to_string(),
+            p.name_a.clone().unwrap_or_default(),
+            p.name_b.clone().unwrap_or_default(),
+            status,
+            p.exceeded.to_string(),
+            p.image_width.to_string(),
+            p.image_height.to_string(),
+            p.diff_count.to_string(),
+            p.diff_probability.to_string(),
+            p.markers.len().to_string(),
+            markers,
+            p.diff_image.clone().unwrap_or_default(),
+            p.error.clone().unwrap_or_default(),
+        ];
+        out.push_str(&fields.iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(","));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 4180の引用を戻して1行をフィールドに分ける（フィールド内の改行も扱う）
+    fn parse_csv(text: &str) -> Vec<Vec<String>> {
+        let mut rows = Vec::new();
+        let (mut row, mut field) = (Vec::new(), String::new());
+        let mut chars = text.chars().peekable();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            match (quoted, c) {
+                (true, '"') if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                (true, '"') => quoted = false,
+                (true, c) => field.push(c),
+                (false, '"') => quoted = true,
+                (false, ',') => row.push(std::mem::take(&mut field)),
+                (false, '\n') => {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+                (false, c) => field.push(c),
+            }
+        }
+        rows
+    }
+
+    #[test]
+    fn quotes_only_fields_that_need_it() {
+        assert_eq!(csv_field("p001.psd"), "p001.psd");
+        assert_eq!(csv_field("第1話 原稿.psd"), "第1話 原稿.psd");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("line1\nline2"), "\"line1\nline2\"");
+        assert_eq!(csv_field("cr\r"), "\"cr\r\"");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn report_round_trips_through_a_csv_parser() {
+        let report = crate::report::tests::sample_report();
+        let rows = parse_csv(&report_to_csv(&report));
+        assert_eq!(rows.len(), 4);
+        assert!(rows.iter().all(|r| r.len() == rows[0].len()));
+        assert_eq!(rows[0][1], "name_a");
+        assert_eq!(rows[1][..4], ["1", "第1話,p001.psd", "\"修正\"版\np001.tif", "diff"]);
+        assert_eq!(rows[1][10], "10:20:20:30:42:87.5");
+        assert_eq!(rows[1][11], "差分/p001 <A&B>.png");
+        assert_eq!(rows[2][2..4], ["", "missing_b"]);
+        assert_eq!(rows[3][12], "Failed to open 'p003.tif'\r\n\u{1b}[31mbroken\u{0}");
+    }
+}
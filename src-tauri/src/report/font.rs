@@ -0,0 +1,527 @@
+use crate::*;
+
+// ---- 日本語フォント（TrueTypeのサブセットをCIDフォントとして埋め込む） ----
+
+// 見つからなければHelvetica（ASCII以外は ? になる）
+pub(crate) fn pdf_font_candidates() -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+
+    // Windows
+    let windir = std::env::var("WINDIR").unwrap_or_else(|_| "C:\\Windows".to_string());
+    for name in ["YuGothM.ttc", "meiryo.ttc", "msgothic.ttc"] {
+        candidates.push(PathBuf::from(&windir).join("Fonts").join(name));
+    }
+
+    // macOS
+    candidates.push(PathBuf::from("/System/Library/Fonts/ヒラギノ角ゴシック W3.ttc"));
+    candidates.push(PathBuf::from("/System/Library/Fonts/Supplemental/Arial Unicode.ttf"));
+    candidates.push(PathBuf::from("/Library/Fonts/Arial Unicode.ttf"));
+
+    // Linux
+    for path in [
+        "/usr/share/fonts/opentype/ipaexfont-gothic/ipaexg.ttf",
+        "/usr/share/fonts/truetype/fonts-japanese-gothic.ttf",
+        "/usr/share/fonts/truetype/takao-gothic/TakaoPGothic.ttf",
+        "/usr/share/fonts/truetype/vlgothic/VL-Gothic-Regular.ttf",
+        "/usr/share/fonts/truetype/droid/DroidSansFallbackFull.ttf",
+    ] {
+        candidates.push(PathBuf::from(path));
+    }
+    candidates
+}
+
+pub(crate) fn be16(data: &[u8], pos: usize) -> u16 {
+    data.get(pos..pos + 2).map_or(0, |b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+pub(crate) fn be32(data: &[u8], pos: usize) -> u32 {
+    data.get(pos..pos + 4).map_or(0, |b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// sfntのチェックサム（4バイト単位の和）
+pub(crate) fn sfnt_checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, c| {
+        let mut word = [0u8; 4];
+        word[..c.len()].copy_from_slice(c);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+// TrueTypeアウトライン（glyf）のフォントのみ対応（CFFのOpenTypeは使えない）
+pub(crate) struct PdfFont {
+    pub(crate) name: String,
+    pub(crate) data: Vec<u8>,
+    pub(crate) tables: HashMap<[u8; 4], (usize, usize)>, // (ファイル先頭からの位置, 長さ)
+    pub(crate) units_per_em: f64,
+    pub(crate) num_glyphs: u16,
+    pub(crate) num_h_metrics: u16,
+    pub(crate) long_loca: bool,
+    pub(crate) cmap: (usize, u16), // (サブテーブルの位置, format 4 / 12)
+    pub(crate) used: BTreeMap<u16, char>, // 使ったグリフ → 文字（ToUnicode用）
+}
+
+impl PdfFont {
+    // 指定されたフォント（読めなければエラー）、なければシステムの日本語フォントを順に試す
+    pub(crate) fn find(path: Option<&PathBuf>) -> Result<Option<Self>, String> {
+        if let Some(path) = path {
+            return Self::load(path).map(Some);
+        }
+        Ok(pdf_font_candidates().iter().filter(|p| p.exists()).find_map(|p| Self::load(p).ok()))
+    }
+
+    pub(crate) fn load(path: &PathBuf) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("Failed to read font {}: {}", path.display(), e))?;
+        let invalid = || format!("Unsupported font (TrueType outlines required): {}", path.display());
+
+        // TTC（フォントコレクション）は先頭のフォントを使う
+        let base = if data.starts_with(b"ttcf") { be32(&data, 12) as usize } else { 0 };
+        if be32(&data, base) != 0x0001_0000 && data.get(base..base + 4) != Some(b"true") {
+            return Err(invalid());
+        }
+        let mut tables = HashMap::new();
+        for i in 0..be16(&data, base + 4) as usize {
+            let rec = base + 12 + i * 16;
+            let tag: [u8; 4] = data.get(rec..rec + 4).ok_or_else(invalid)?.try_into().unwrap();
+            let (offset, length) = (be32(&data, rec + 8) as usize, be32(&data, rec + 12) as usize);
+            if offset + length > data.len() {
+                return Err(invalid());
+            }
+            tables.insert(tag, (offset, length));
+        }
+        // 固定長の部分を読むので最低限の長さを確認（壊れたフォントで範囲外を読まないように）
+        let required = [
+            (b"head", 54),
+            (b"hhea", 36),
+            (b"maxp", 6),
+            (b"cmap", 4),
+            (b"hmtx", 0),
+            (b"loca", 0),
+            (b"glyf", 0),
+        ];
+        for (tag, min_len) in required {
+            if tables.get(tag).is_none_or(|&(_, length)| length < min_len) {
+                return Err(invalid());
+            }
+        }
+
+        let head = tables[b"head"].0;
+        let hhea = tables[b"hhea"].0;
+        let (cmap, cmap_len) = tables[b"cmap"];
+        let num_glyphs = be16(&data, tables[b"maxp"].0 + 4);
+        let num_h_metrics = be16(&data, hhea + 34).max(1);
+        let long_loca = be16(&data, head + 50) == 1;
+        // loca はグリフ数+1個, hmtx は numberOfHMetrics 個の (advance, lsb)
+        let loca_len = (num_glyphs as usize + 1) * if long_loca { 4 } else { 2 };
+        if tables[b"loca"].1 < loca_len || tables[b"hmtx"].1 < num_h_metrics as usize * 4 {
+            return Err(invalid());
+        }
+        if cmap_len < 4 + be16(&data, cmap + 2) as usize * 8 {
+            return Err(invalid());
+        }
+        // Unicodeのサブテーブル（全角外の文字も引ける format 12 を優先）
+        let mut subtable = None;
+        for i in 0..be16(&data, cmap + 2) as usize {
+            let rec = cmap + 4 + i * 8;
+            let (platform, encoding) = (be16(&data, rec), be16(&data, rec + 2));
+            let offset = cmap + be32(&data, rec + 4) as usize;
+            let format = be16(&data, offset);
+            // サブテーブル全体がcmapに収まっているものだけ使う（format 4 は16bit長, 12 は32bit長）
+            let length = if format == 12 { be32(&data, offset + 4) as usize } else { be16(&data, offset + 2) as usize };
+            if offset + length > cmap + cmap_len {
+                continue;
+            }
+            let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+            if unicode && (format == 12 || (format == 4 && subtable.is_none())) {
+                subtable = Some((offset, format));
+            }
+        }
+
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("Font");
+        let name: String = stem.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-').collect();
+        Ok(Self {
+            name: format!("KBNSUB+{}", if name.is_empty() { "Font" } else { &name }),
+            units_per_em: be16(&data, head + 18).max(1) as f64,
+            num_glyphs,
+            num_h_metrics,
+            long_loca,
+            cmap: subtable.ok_or_else(invalid)?,
+            tables,
+            data,
+            used: BTreeMap::new(),
+        })
+    }
+
+    pub(crate) fn table(&self, tag: &[u8; 4]) -> &[u8] {
+        self.tables.get(tag).map_or(&[], |&(offset, length)| &self.data[offset..offset + length])
+    }
+
+    pub(crate) fn glyph_id(&self, c: char) -> u16 {
+        let (pos, format) = self.cmap;
+        let d = &self.data;
+        let code = c as u32;
+        if format == 12 {
+            for i in 0..be32(d, pos + 12) as usize {
+                let group = pos + 16 + i * 12;
+                let (start, end) = (be32(d, group), be32(d, group + 4));
+                if (start..=end).contains(&code) {
+                    return (be32(d, group + 8) + code - start) as u16;
+                }
+            }
+            return 0;
+        }
+        if code > 0xFFFF {
+            return 0;
+        }
+        let seg_x2 = be16(d, pos + 6) as usize;
+        for i in (0..seg_x2).step_by(2) {
+            let end = be16(d, pos + 14 + i) as u32;
+            if code > end {
+                continue;
+            }
+            let start = be16(d, pos + 16 + seg_x2 + i) as u32;
+            if code < start {
+                return 0;
+            }
+            let delta = be16(d, pos + 16 + seg_x2 * 2 + i);
+            let range_pos = pos + 16 + seg_x2 * 3 + i;
+            let range_offset = be16(d, range_pos) as usize;
+            if range_offset == 0 {
+                return (code as u16).wrapping_add(delta);
+            }
+            let g = be16(d, range_pos + range_offset + (code - start) as usize * 2);
+            return if g == 0 { 0 } else { g.wrapping_add(delta) };
+        }
+        0
+    }
+
+    pub(crate) fn advance(&self, gid: u16) -> u16 {
+        let index = gid.min(self.num_h_metrics - 1) as usize;
+        be16(self.table(b"hmtx"), index * 4)
+    }
+
+    // glyf内の範囲
+    pub(crate) fn glyph_range(&self, gid: u16) -> (usize, usize) {
+        let loca = self.table(b"loca");
+        let g = gid as usize;
+        if self.long_loca {
+            (be32(loca, g * 4) as usize, be32(loca, g * 4 + 4) as usize)
+        } else {
+            (be16(loca, g * 2) as usize * 2, be16(loca, g * 2 + 2) as usize * 2)
+        }
+    }
+
+    // 文字列をグリフ番号（Identity-H の2バイトコード）の16進文字列にする
+    pub(crate) fn encode(&mut self, text: &str) -> String {
+        let mut hex = String::from("<");
+        for c in text.chars() {
+            let mut c = c;
+            let mut gid = self.glyph_id(c);
+            if gid == 0 || gid >= self.num_glyphs {
+                // フォントにない文字
+                c = '?';
+                gid = self.glyph_id(c);
+            }
+            self.used.entry(gid).or_insert(c);
+            hex.push_str(&format!("{:04X}", gid));
+        }
+        hex.push('>');
+        hex
+    }
+
+    // 使ったグリフ（と複合グリフの部品）だけを残したフォント
+    // グリフ番号は変えないので CIDToGIDMap は Identity のまま使える
+    pub(crate) fn subset(&self) -> Vec<u8> {
+        let glyf = self.table(b"glyf");
+        let mut keep: BTreeSet<u16> = self.used.keys().copied().collect();
+        keep.insert(0);
+        let mut stack: Vec<u16> = keep.iter().copied().collect();
+        while let Some(gid) = stack.pop() {
+            let (start, end) = self.glyph_range(gid);
+            if end <= start || end > glyf.len() || (be16(glyf, start) as i16) >= 0 {
+                continue;
+            }
+            // 複合グリフの部品
+            let mut pos = start + 10;
+            loop {
+                let flags = be16(glyf, pos);
+                let component = be16(glyf, pos + 2);
+                if component < self.num_glyphs && keep.insert(component) {
+                    stack.push(component);
+                }
+                pos += 4 + if flags & 0x0001 != 0 { 4 } else { 2 };
+                pos += if flags & 0x0008 != 0 {
+                    2
+                } else if flags & 0x0040 != 0 {
+                    4
+                } else if flags & 0x0080 != 0 {
+                    8
+                } else {
+                    0
+                };
+                if flags & 0x0020 == 0 || pos >= end {
+                    break;
+                }
+            }
+        }
+
+        let mut new_glyf = Vec::new();
+        let mut new_loca = Vec::with_capacity((self.num_glyphs as usize + 1) * 4);
+        for gid in 0..self.num_glyphs {
+            new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+            let (start, end) = self.glyph_range(gid);
+            if keep.contains(&gid) && start < end && end <= glyf.len() {
+                new_glyf.extend_from_slice(&glyf[start..end]);
+                new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+            }
+        }
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+
+        let mut head = self.table(b"head").to_vec();
+        head[8..12].fill(0); // checkSumAdjustment（最後に計算）
+        head[50..52].copy_from_slice(&1u16.to_be_bytes()); // long loca
+
+        // タグ順
+        let mut tables: Vec<(&[u8; 4], Vec<u8>)> = vec![
+            (b"cvt ", self.table(b"cvt ").to_vec()),
+            (b"fpgm", self.table(b"fpgm").to_vec()),
+            (b"glyf", new_glyf),
+            (b"head", head),
+            (b"hhea", self.table(b"hhea").to_vec()),
+            (b"hmtx", self.table(b"hmtx").to_vec()),
+            (b"loca", new_loca),
+            (b"maxp", self.table(b"maxp").to_vec()),
+            (b"prep", self.table(b"prep").to_vec()),
+        ];
+        tables.retain(|(_, data)| !data.is_empty());
+
+        let n = tables.len() as u16;
+        let entry_selector = 15 - n.leading_zeros() as u16;
+        let search_range = (1u16 << entry_selector) * 16;
+        let mut out = Vec::new();
+        out.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+        for v in [n, search_range, entry_selector, n * 16 - search_range] {
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        let mut offset = 12 + tables.len() * 16;
+        let mut head_offset = 0;
+        for (tag, data) in &tables {
+            if *tag == b"head" {
+                head_offset = offset;
+            }
+            out.extend_from_slice(*tag);
+            out.extend_from_slice(&sfnt_checksum(data).to_be_bytes());
+            out.extend_from_slice(&(offset as u32).to_be_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            offset += data.len().next_multiple_of(4);
+        }
+        for (_, data) in &tables {
+            out.extend_from_slice(data);
+            out.resize(out.len().next_multiple_of(4), 0);
+        }
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(sfnt_checksum(&out));
+        out[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+        out
+    }
+
+    // Type0（Identity-H）+ CIDFontType2 として書き出す（font_idは予約済みの番号）
+    pub(crate) fn embed(&self, writer: &mut PdfWriter, font_id: usize) {
+        let scale = |v: i16| (v as f64 * 1000.0 / self.units_per_em).round() as i64;
+        let head = self.table(b"head");
+        let hhea = self.table(b"hhea");
+        let bbox: Vec<i64> = (0..4).map(|i| scale(be16(head, 36 + i * 2) as i16)).collect();
+        let (ascent, descent) = (scale(be16(hhea, 4) as i16), scale(be16(hhea, 6) as i16));
+
+        let subset = self.subset();
+        let file_id = writer.add_stream(&format!("/Length1 {}", subset.len()), &subset);
+        let descriptor_id = writer.add(format!(
+            "<< /Type /FontDescriptor /FontName /{} /Flags 4 /FontBBox [{} {} {} {}] /ItalicAngle 0 \
+             /Ascent {} /Descent {} /CapHeight {} /StemV 80 /FontFile2 {} 0 R >>",
+            self.name, bbox[0], bbox[1], bbox[2], bbox[3], ascent, descent, ascent, file_id,
+        ));
+        let widths: String = self
+            .used
+            .keys()
+            .map(|&gid| format!("{} [{}] ", gid, scale(self.advance(gid) as i16).max(0)))
+            .collect();
+        let cid_font_id = writer.add(format!(
+            "<< /Type /Font /Subtype /CIDFontType2 /BaseFont /{} \
+             /CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >> \
+             /FontDescriptor {} 0 R /DW 1000 /W [{}] /CIDToGIDMap /Identity >>",
+            self.name, descriptor_id, widths,
+        ));
+
+        // コピー・検索用のグリフ → Unicode 対応
+        let mut cmap = String::from(
+            "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+             /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+             /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+             1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+        );
+        let entries: Vec<(&u16, &char)> = self.used.iter().collect();
+        for chunk in entries.chunks(100) {
+            cmap.push_str(&format!("{} beginbfchar\n", chunk.len()));
+            for (gid, c) in chunk {
+                let utf16: String = c.encode_utf16(&mut [0; 2]).iter().map(|u| format!("{:04X}", u)).collect();
+                cmap.push_str(&format!("<{:04X}> <{}>\n", gid, utf16));
+            }
+            cmap.push_str("endbfchar\n");
+        }
+        cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend");
+        let to_unicode_id = writer.add_stream("", cmap.as_bytes());
+
+        writer.set(
+            font_id,
+            format!(
+                "<< /Type /Font /Subtype /Type0 /BaseFont /{} /Encoding /Identity-H \
+                 /DescendantFonts [{} 0 R] /ToUnicode {} 0 R >>",
+                self.name, cid_font_id, to_unicode_id,
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 'A'..'D' → グリフ1..4（'C' は グリフ5 を部品にする複合グリフ）
+    fn simple_glyph(seed: u8) -> Vec<u8> {
+        let mut g = vec![0, 1, 0, 0, 0, 0, 0, 10, 0, 10];
+        g.extend_from_slice(&[seed; 6]);
+        g
+    }
+
+    fn test_font() -> Vec<u8> {
+        let mut composite = vec![0xFF, 0xFF, 0, 0, 0, 0, 0, 10, 0, 10];
+        composite.extend_from_slice(&[0x00, 0x00, 0x00, 0x05, 0x00, 0x00]); // flags, glyphIndex=5, dx/dy
+        let glyphs = [simple_glyph(0), simple_glyph(1), simple_glyph(2), composite, simple_glyph(4), simple_glyph(5)];
+        let mut glyf = Vec::new();
+        let mut loca = Vec::new();
+        for g in &glyphs {
+            loca.extend_from_slice(&((glyf.len() / 2) as u16).to_be_bytes());
+            glyf.extend_from_slice(g);
+        }
+        loca.extend_from_slice(&((glyf.len() / 2) as u16).to_be_bytes());
+
+        let mut head = vec![0u8; 54];
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+        let mut hhea = vec![0u8; 36];
+        hhea[34..36].copy_from_slice(&6u16.to_be_bytes());
+        let mut maxp = vec![0, 0, 0x50, 0];
+        maxp.extend_from_slice(&6u16.to_be_bytes());
+        let hmtx: Vec<u8> = (0..6u16).flat_map(|i| [(500 + i * 10).to_be_bytes(), [0, 0]].concat()).collect();
+
+        // format 4: 0x41..0x44 → +0xFFC0（= -0x40）, 終端 0xFFFF
+        let mut sub = Vec::new();
+        for v in [4u16, 32, 0, 4, 4, 1, 0, 0x44, 0xFFFF, 0, 0x41, 0xFFFF, 0xFFC0, 1, 0, 0] {
+            sub.extend_from_slice(&v.to_be_bytes());
+        }
+        let mut cmap = vec![0, 0, 0, 1, 0, 3, 0, 1, 0, 0, 0, 12];
+        cmap.extend_from_slice(&sub);
+
+        let tables: [(&[u8; 4], Vec<u8>); 7] = [
+            (b"cmap", cmap),
+            (b"glyf", glyf),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", hmtx),
+            (b"loca", loca),
+            (b"maxp", maxp),
+        ];
+        let mut out = vec![0, 1, 0, 0, 0, 7, 0, 64, 0, 2, 0, 48];
+        let mut offset = 12 + tables.len() * 16;
+        for (tag, data) in &tables {
+            out.extend_from_slice(*tag);
+            out.extend_from_slice(&sfnt_checksum(data).to_be_bytes());
+            out.extend_from_slice(&(offset as u32).to_be_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            offset += data.len().next_multiple_of(4);
+        }
+        for (_, data) in &tables {
+            out.extend_from_slice(data);
+            out.resize(out.len().next_multiple_of(4), 0);
+        }
+        out
+    }
+
+    fn load_bytes(name: &str, data: &[u8]) -> Result<PdfFont, String> {
+        let path = std::env::temp_dir().join(format!("kenban-test-{}-{}.ttf", std::process::id(), name));
+        fs::write(&path, data).unwrap();
+        let font = PdfFont::load(&path);
+        let _ = fs::remove_file(&path);
+        font
+    }
+
+    // sfntのテーブル (位置, 長さ)
+    fn table_of(font: &[u8], tag: &[u8; 4]) -> (usize, usize) {
+        (0..be16(font, 4) as usize)
+            .map(|i| 12 + i * 16)
+            .find(|&rec| &font[rec..rec + 4] == tag)
+            .map(|rec| (be32(font, rec + 8) as usize, be32(font, rec + 12) as usize))
+            .unwrap()
+    }
+
+    #[test]
+    fn cmap_maps_characters_to_glyphs() {
+        let font = load_bytes("cmap", &test_font()).unwrap();
+        assert_eq!(font.cmap.1, 4);
+        assert_eq!(font.glyph_id('A'), 1);
+        assert_eq!(font.glyph_id('D'), 4);
+        assert_eq!(font.glyph_id('E'), 0);
+        assert_eq!(font.glyph_id('あ'), 0);
+        assert_eq!(font.advance(3), 530);
+    }
+
+    #[test]
+    fn subset_keeps_used_glyphs_and_components() {
+        let original = test_font();
+        let mut font = load_bytes("subset", &original).unwrap();
+        assert_eq!(font.encode("AC"), "<00010003>");
+        let subset = font.subset();
+
+        // テーブルごとのチェックサムとファイル全体の checkSumAdjustment
+        for i in 0..be16(&subset, 4) as usize {
+            let rec = 12 + i * 16;
+            let (offset, length) = (be32(&subset, rec + 8) as usize, be32(&subset, rec + 12) as usize);
+            let mut data = subset[offset..offset + length].to_vec();
+            if &subset[rec..rec + 4] == b"head" {
+                data[8..12].fill(0);
+            }
+            assert_eq!(be32(&subset, rec + 4), sfnt_checksum(&data));
+        }
+        assert_eq!(sfnt_checksum(&subset), 0xB1B0_AFBA);
+
+        // long loca に変わり, 使ったグリフ（.notdef, A, C と部品の5）だけ中身が残る
+        let (head, _) = table_of(&subset, b"head");
+        assert_eq!(be16(&subset, head + 50), 1);
+        let (loca, loca_len) = table_of(&subset, b"loca");
+        let (glyf, _) = table_of(&subset, b"glyf");
+        assert_eq!(loca_len, 7 * 4);
+        let src_glyf = &original[table_of(&original, b"glyf").0..];
+        for gid in 0..6 {
+            let (start, end) = (be32(&subset, loca + gid * 4) as usize, be32(&subset, loca + gid * 4 + 4) as usize);
+            let (src_start, src_end) = font.glyph_range(gid as u16);
+            if [0, 1, 3, 5].contains(&gid) {
+                assert_eq!(subset[glyf + start..glyf + start + (src_end - src_start)], src_glyf[src_start..src_end]);
+                assert_eq!(start % 4, 0);
+            } else {
+                assert_eq!(start, end, "glyph {} should be empty", gid);
+            }
+        }
+        assert!(!subset.windows(4).any(|w| w == b"cmap"));
+    }
+
+    #[test]
+    fn truncated_tables_are_rejected() {
+        let font = test_font();
+        for tag in [b"head", b"hhea", b"maxp", b"loca", b"hmtx", b"cmap"] {
+            // テーブルの長さだけ短く書き換える
+            let (_, length) = table_of(&font, tag);
+            let rec = (0..7).map(|i| 12 + i * 16).find(|&rec| &font[rec..rec + 4] == tag).unwrap();
+            let mut broken = font.clone();
+            broken[rec + 12..rec + 16].copy_from_slice(&((length / 2) as u32).to_be_bytes());
+            assert!(load_bytes("truncated", &broken).is_err(), "{}", String::from_utf8_lossy(tag));
+        }
+        assert!(load_bytes("short", &font[..40]).is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use crate::*;
+
+// ============== HTMLレポート ==============
+
+// レポートの縮小画像の長辺(px)
+pub(crate) const REPORT_THUMB_SIZE: u32 = 1600;
+
+// 差分オーバーレイを縮小（細い線が消えないよう、ブロック内に差分があればその色を残す）
+// 背景には薄くしたB画像を敷く
+pub(crate) fn downscale_diff_overlay(diff: &BatchDiff, tw: u32, th: u32, base: &DynamicImage) -> image::RgbImage {
+    let (w, h) = (diff.width as usize, diff.height as usize);
+    let mut out = base.to_luma8();
+    for v in out.iter_mut() {
+        *v = 160 + (*v as u16 * 95 / 255) as u8; // 背景は明るいグレーに寄せる
+    }
+    let mut rgb = DynamicImage::ImageLuma8(out).to_rgb8();
+    rgb.par_chunks_mut(tw as usize * 3).enumerate().for_each(|(ty, row)| {
+        let y1 = ty * h / th as usize;
+        let y2 = ((ty + 1) * h / th as usize).max(y1 + 1).min(h);
+        for tx in 0..tw as usize {
+            let x1 = tx * w / tw as usize;
+            let x2 = ((tx + 1) * w / tw as usize).max(x1 + 1).min(w);
+            'block: for y in y1..y2 {
+                for x in x1..x2 {
+                    let i = (y * w + x) * 4;
+                    if diff.diff_buf[i..i + 3] != [0, 0, 0] {
+                        row[tx * 3..tx * 3 + 3].copy_from_slice(&diff.diff_buf[i..i + 3]);
+                        break 'block;
+                    }
+                }
+            }
+        }
+    });
+    rgb
+}
+
+pub(crate) fn write_pair_thumbnails(dir: &std::path::Path, index: usize, diff: &BatchDiff) -> Result<PairThumbnails, String> {
+    let scale = (REPORT_THUMB_SIZE as f64 / diff.width.max(diff.height) as f64).min(1.0);
+    let tw = ((diff.width as f64 * scale).round() as u32).max(1);
+    let th = ((diff.height as f64 * scale).round() as u32).max(1);
+    let thumbs = PairThumbnails {
+        a: format!("images/{:03}_a.jpg", index + 1),
+        b: format!("images/{:03}_b.jpg", index + 1),
+        diff: format!("images/{:03}_diff.png", index + 1),
+    };
+
+    let (thumb_a, thumb_b) = rayon::join(
+        || diff.preview_a.resize_exact(tw, th, FilterType::Triangle),
+        || diff.preview_b.resize_exact(tw, th, FilterType::Triangle),
+    );
+    let overlay = downscale_diff_overlay(diff, tw, th, &thumb_b);
+    let save_err = |e: image::ImageError| format!("Failed to write thumbnail: {}", e);
+    thumb_a.to_rgb8().save(dir.join(&thumbs.a)).map_err(save_err)?;
+    thumb_b.to_rgb8().save(dir.join(&thumbs.b)).map_err(save_err)?;
+    overlay.save(dir.join(&thumbs.diff)).map_err(save_err)?;
+    Ok(thumbs)
+}
+
+pub(crate) const HTML_REPORT_STYLE: &str = "\
+body{font-family:sans-serif;margin:0;background:#1e1e1e;color:#ddd}
+header,section{padding:16px 24px}
+a{color:#6cb6ff}
+table{border-collapse:collapse;width:100%}
+th,td{border-bottom:1px solid #333;padding:4px 8px;text-align:left}
+tr.exceeded td{color:#ff8080}
+section{border-top:2px solid #333}
+nav{float:right}
+.grid{display:grid;grid-template-columns:repeat(3,1fr);gap:8px}
+.frame{position:relative}
+.frame img{width:100%;display:block;background:#fff}
+.frame .caption{font-size:12px;color:#aaa}
+.box{position:absolute;border:2px solid #ff4040;box-sizing:border-box}
+.box.low{border-color:#ffb000;border-style:dashed}
+.stats{font-size:13px;color:#aaa}
+";
+
+// ページ移動（j/k, ←/→）
+pub(crate) const HTML_REPORT_SCRIPT: &str = "\
+pub(crate) const pages=[...document.querySelectorAll('section')];let cur=-1;
+document.addEventListener('keydown',e=>{
+if(!pages.length)return;
+if(e.key==='j'||e.key==='ArrowRight')cur=Math.min(cur+1,pages.length-1);
+else if(e.key==='k'||e.key==='ArrowLeft')cur=Math.max(cur-1,0);else return;
+pages[cur].scrollIntoView();});
+document.querySelectorAll('[data-time]').forEach(el=>{el.textContent=new Date(el.dataset.time*1000).toLocaleString();});
+";
+
+pub(crate) fn batch_status_label(p: &BatchPairResult) -> &'static str {
+    match p.status {
+        BatchStatus::Same => "一致",
+        BatchStatus::Diff if p.exceeded => "要確認",
+        BatchStatus::Diff => "軽微な差分",
+        BatchStatus::MissingA => "Aなし",
+        BatchStatus::MissingB => "Bなし",
+        BatchStatus::Error => "エラー",
+    }
+}
+
+// マーカーの矩形を画像上に重ねるdiv（位置は画像に対する%）
+pub(crate) fn html_marker_boxes(p: &BatchPairResult, fail_probability: f64) -> String {
+    let (w, h) = (p.image_width.max(1) as f64, p.image_height.max(1) as f64);
+    p.markers
+        .iter()
+        .map(|m| {
+            let b = &m.bbox;
+            format!(
+                "<div class=\"box{}\" style=\"left:{:.3}%;top:{:.3}%;width:{:.3}%;height:{:.3}%\" \
+                 title=\"{} px / {:.1}%\"></div>",
+                if m.probability >= fail_probability { "" } else { " low" },
+                b.left as f64 / w * 100.0,
+                b.top as f64 / h * 100.0,
+                (b.right - b.left) as f64 / w * 100.0,
+                (b.bottom - b.top) as f64 / h * 100.0,
+                m.count,
+                m.probability,
+            )
+        })
+        .collect()
+}
+
+// レポートフォルダに index.html を書き出す（画像は write_pair_thumbnails で書き出し済み）
+pub(crate) fn write_html_report(dir: &std::path::Path, report: &DiffReport) -> Result<PathBuf, String> {
+    let pairs = &report.pairs;
+    let exceeded = pairs.iter().filter(|p| p.exceeded).count();
+    let mut html = String::new();
+    html.push_str(&format!(
+        "<!DOCTYPE html>\n<html lang=\"ja\"><head><meta charset=\"utf-8\"><title>検版レポート</title>\
+         <style>{}</style></head><body>\n<header><h1>検版レポート</h1>\
+         <p class=\"stats\">作成: <span data-time=\"{}\"></span> / A: {} / B: {} / 方式: {}</p>\
+         <p>{}ページ中 要確認 {}ページ（確率 {:.0}% 以上・ページ欠け）</p>\n",
+        HTML_REPORT_STYLE,
+        report.generated_at,
+        xml_escape(report.folder_a.as_deref().unwrap_or("-")),
+        xml_escape(report.folder_b.as_deref().unwrap_or("-")),
+        xml_escape(&report.mode),
+        pairs.len(),
+        exceeded,
+        report.fail_probability,
+    ));
+
+    // 一覧表
+    html.push_str("<table><tr><th>#</th><th>A</th><th>B</th><th>判定</th><th>差分px</th><th>マーカー</th><th>確率</th></tr>\n");
+    for p in pairs {
+        html.push_str(&format!(
+            "<tr{}><td><a href=\"#p{:03}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td></tr>\n",
+            if p.exceeded { " class=\"exceeded\"" } else { "" },
+            p.index + 1,
+            p.index + 1,
+            xml_escape(p.name_a.as_deref().unwrap_or("-")),
+            xml_escape(p.name_b.as_deref().unwrap_or("-")),
+            batch_status_label(p),
+            p.diff_count,
+            p.markers.len(),
+            p.diff_probability,
+        ));
+    }
+    html.push_str("</table></header>\n");
+
+    // ページごとの詳細
+    for (i, p) in pairs.iter().enumerate() {
+        let nav_link = |target: Option<&BatchPairResult>, label: &str| match target {
+            Some(t) => format!("<a href=\"#p{:03}\">{}</a>", t.index + 1, label),
+            None => label.to_string(),
+        };
+        html.push_str(&format!(
+            "<section id=\"p{:03}\"><nav>{} | <a href=\"#\">一覧</a> | {}</nav>\
+             <h2>{} {}</h2><p class=\"stats\">{}x{} / 差分 {}px / マーカー {} / 確率 {:.1}%{}</p>\n",
+            p.index + 1,
+            nav_link(i.checked_sub(1).and_then(|j| pairs.get(j)), "← 前"),
+            nav_link(pairs.get(i + 1), "次 →"),
+            batch_status_label(p),
+            xml_escape(&pair_label(p)),
+            p.image_width,
+            p.image_height,
+            p.diff_count,
+            p.markers.len(),
+            p.diff_probability,
+            p.error.as_ref().map(|e| format!(" / {}", xml_escape(e))).unwrap_or_default(),
+        ));
+        if let Some(t) = &p.thumbnails {
+            let boxes = html_marker_boxes(p, report.fail_probability);
+            html.push_str("<div class=\"grid\">");
+            for (src, caption) in [(&t.a, "A"), (&t.b, "B"), (&t.diff, "差分")] {
+                html.push_str(&format!(
+                    "<div><div class=\"caption\">{}</div><div class=\"frame\"><img src=\"{}\" loading=\"lazy\">{}</div></div>",
+                    caption,
+                    xml_escape(src),
+                    boxes,
+                ));
+            }
+            html.push_str("</div>\n");
+        }
+        html.push_str("</section>\n");
+    }
+    html.push_str(&format!("<script>{}</script>\n</body></html>\n", HTML_REPORT_SCRIPT));
+
+    let path = dir.join("index.html");
+    fs::write(&path, html).map_err(|e| format!("Failed to write report: {}", e))?;
+    Ok(path)
+}
+
+#[derive(Serialize)]
+pub(crate) struct HtmlReportResult {
+    pub(crate) index_path: String,
+    pub(crate) pairs: usize,
+    pub(crate) exceeded: usize,
+}
+
+// フォルダ一括比較を行い、オフラインで閲覧できるHTMLレポートを書き出す
+#[tauri::command]
+pub(crate) async fn export_html_report(config: BatchConfig, out_dir: String) -> Result<HtmlReportResult, String> {
+    let cfg = BatchConfig { html_dir: Some(PathBuf::from(&out_dir)), ..config };
+    let pairs = run_batch(&cfg)?;
+    let report = DiffReport {
+        generated_at: unix_time_secs(),
+        folder_a: Some(cfg.folder_a.clone()),
+        folder_b: Some(cfg.folder_b.clone()),
+        mode: batch_mode_name(cfg.mode).to_string(),
+        fail_probability: cfg.fail_probability,
+        pairs,
+    };
+    let index_path = write_html_report(std::path::Path::new(&out_dir), &report)?;
+    Ok(HtmlReportResult {
+        index_path: index_path.to_string_lossy().to_string(),
+        pairs: report.pairs.len(),
+        exceeded: report.pairs.iter().filter(|p| p.exceeded).count(),
+    })
+}
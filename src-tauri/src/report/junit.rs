@@ -0,0 +1,122 @@
+use crate::*;
+
+// ============== JUnit XMLレポート ==============
+
+pub(crate) fn xml_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // 属性値の改行・タブはパース時に空白へ正規化されるので文字参照にする
+            '\n' | '\r' | '\t' => out.push_str(&format!("&#{};", c as u32)),
+            // XML 1.0で使えない制御文字（エラーメッセージに混ざることがある）は置き換える
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => out.push('\u{fffd}'),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// 1ペア = 1テストケース。閾値超え・ページ欠けはfailure、読み込みエラーはerror
+pub(crate) fn report_to_junit(report: &DiffReport) -> String {
+    let failures = report.pairs.iter().filter(|p| p.exceeded).count();
+    let errors = report.pairs.iter().filter(|p| p.status == BatchStatus::Error).count();
+    let suite = format!("kenban.{}", report.mode);
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!(
+        "<testsuites tests=\"{n}\" failures=\"{failures}\" errors=\"{errors}\">\n\
+         <testsuite name=\"{name}\" tests=\"{n}\" failures=\"{failures}\" errors=\"{errors}\">\n",
+        n = report.pairs.len(),
+        name = xml_escape(&suite),
+    ));
+    for p in &report.pairs {
+        out.push_str(&format!(
+            "  <testcase classname=\"{}\" name=\"{}\">\n",
+            xml_escape(&suite),
+            xml_escape(&pair_label(p)),
+        ));
+        let summary = format!(
+            "pixels={} markers={} probability={:.1}% size={}x{}",
+            p.diff_count, p.markers.len(), p.diff_probability, p.image_width, p.image_height,
+        );
+        match p.status {
+            BatchStatus::Error => out.push_str(&format!(
+                "    <error message=\"{}\"/>\n",
+                xml_escape(p.error.as_deref().unwrap_or("error")),
+            )),
+            BatchStatus::MissingA | BatchStatus::MissingB => out.push_str(&format!(
+                "    <failure message=\"missing page in {}\"/>\n",
+                if p.status == BatchStatus::MissingA { "A" } else { "B" },
+            )),
+            _ if p.exceeded => {
+                let details = p
+                    .markers
+                    .iter()
+                    .map(|m| {
+                        let b = &m.bbox;
+                        format!(
+                            "({}, {})-({}, {}) pixels={} probability={:.1}%",
+                            b.left, b.top, b.right, b.bottom, m.count, m.probability,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                out.push_str(&format!(
+                    "    <failure message=\"{}\">{}</failure>\n",
+                    xml_escape(&summary),
+                    xml_escape(&details),
+                ));
+            }
+            _ => out.push_str(&format!("    <system-out>{}</system-out>\n", xml_escape(&summary))),
+        }
+        out.push_str("  </testcase>\n");
+    }
+    out.push_str("</testsuite>\n</testsuites>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_markup_and_control_characters() {
+        assert_eq!(xml_escape("第1話 p001.psd"), "第1話 p001.psd");
+        assert_eq!(xml_escape("<a href=\"x\">&'</a>"), "&lt;a href=&quot;x&quot;&gt;&amp;&apos;&lt;/a&gt;");
+        assert_eq!(xml_escape("a\r\nb\tc"), "a&#13;&#10;b&#9;c");
+        assert_eq!(xml_escape("\u{1b}[31m\u{0}"), "\u{fffd}[31m\u{fffd}");
+        // 既に実体参照の形をしていても二重にエスケープする
+        assert_eq!(xml_escape("&amp;"), "&amp;amp;");
+    }
+
+    #[test]
+    fn report_is_well_formed_xml() {
+        let xml = report_to_junit(&crate::report::tests::sample_report());
+        assert!(xml.contains("<testsuites tests=\"3\" failures=\"2\" errors=\"1\">"));
+        assert!(xml.contains("name=\"001 第1話,p001.psd &lt;-&gt; &quot;修正&quot;版&#10;p001.tif\""));
+        assert!(xml.contains("<failure message=\"missing page in B\"/>"));
+        assert!(xml.contains("<error message=\"Failed to open &apos;p003.tif&apos;&#13;&#10;\u{fffd}[31mbroken\u{fffd}\"/>"));
+
+        // 属性値・テキストの外に生の < & " や制御文字が残っていない
+        let mut depth = 0;
+        let mut in_attr = false;
+        for c in xml.chars() {
+            assert!(c == '\n' || !c.is_control(), "control character {:?}", c);
+            match c {
+                '"' if depth > 0 => in_attr = !in_attr,
+                '<' => {
+                    assert!(!in_attr && depth == 0);
+                    depth += 1;
+                }
+                '>' if !in_attr => depth -= 1,
+                _ => {}
+            }
+        }
+        assert_eq!(depth, 0);
+        assert_eq!(xml.matches("<testcase ").count(), xml.matches("</testcase>").count());
+    }
+}
@@ -0,0 +1,133 @@
+use crate::*;
+
+mod csv;
+mod junit;
+mod html;
+mod pdf;
+mod font;
+
+pub(crate) use csv::*;
+pub(crate) use junit::*;
+pub(crate) use html::*;
+pub(crate) use pdf::*;
+pub(crate) use font::*;
+
+// ============== レポート出力（JSON / CSV / JUnit XML） ==============
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ReportFormat {
+    Json,
+    Csv,
+    Junit,
+}
+
+// 一括比較の結果（CLIの出力、またはフロントエンドから渡される）
+#[derive(Serialize, Deserialize)]
+pub(crate) struct DiffReport {
+    #[serde(default)]
+    pub(crate) generated_at: u64, // UNIX時刻(秒)
+    #[serde(default)]
+    pub(crate) folder_a: Option<String>,
+    #[serde(default)]
+    pub(crate) folder_b: Option<String>,
+    pub(crate) mode: String, // "simple" / "heatmap" など
+    pub(crate) fail_probability: f64,
+    pub(crate) pairs: Vec<BatchPairResult>,
+}
+
+pub(crate) fn unix_time_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// 拡張子から形式を判定（.xmlはJUnit）
+pub(crate) fn report_format_for_path(path: &str) -> Result<ReportFormat, String> {
+    let ext = PathBuf::from(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "json" => Ok(ReportFormat::Json),
+        "csv" => Ok(ReportFormat::Csv),
+        "xml" => Ok(ReportFormat::Junit),
+        _ => Err(format!("Unknown report format: {} (use .json, .csv or .xml)", path)),
+    }
+}
+
+pub(crate) fn pair_label(p: &BatchPairResult) -> String {
+    format!(
+        "{:03} {} <-> {}",
+        p.index + 1,
+        p.name_a.as_deref().unwrap_or("-"),
+        p.name_b.as_deref().unwrap_or("-"),
+    )
+}
+
+pub(crate) fn report_to_json(report: &DiffReport) -> Result<String, String> {
+    serde_json::to_string_pretty(report).map_err(|e| format!("JSON encode error: {}", e))
+}
+
+pub(crate) fn write_report_file(path: &std::path::Path, format: ReportFormat, report: &DiffReport) -> Result<(), String> {
+    let text = match format {
+        ReportFormat::Json => report_to_json(report)?,
+        ReportFormat::Csv => report_to_csv(report),
+        ReportFormat::Junit => report_to_junit(report),
+    };
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create report folder: {}", e))?;
+    }
+    fs::write(path, text).map_err(|e| format!("Failed to write report: {}", e))
+}
+
+// フロントエンドの比較結果をレポートとして保存（format省略時は拡張子で判定）
+#[tauri::command]
+pub(crate) fn write_diff_report(path: String, report: DiffReport, format: Option<ReportFormat>) -> Result<(), String> {
+    let format = match format {
+        Some(f) => f,
+        None => report_format_for_path(&path)?,
+    };
+    write_report_file(std::path::Path::new(&path), format, &report)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // CSV・XMLで特別扱いが必要な文字を含むレポート
+    pub(crate) fn sample_report() -> DiffReport {
+        serde_json::from_value(serde_json::json!({
+            "mode": "simple",
+            "fail_probability": 50.0,
+            "pairs": [
+                {
+                    "index": 0, "name_a": "第1話,p001.psd", "name_b": "\"修正\"版\np001.tif",
+                    "status": "diff", "exceeded": true, "image_width": 800, "image_height": 600,
+                    "diff_count": 42, "diff_probability": 87.5,
+                    "markers": [{
+                        "x": 15.0, "y": 25.0, "radius": 300.0, "count": 42,
+                        "bbox": { "left": 10, "top": 20, "right": 20, "bottom": 30 },
+                        "max_delta": 120.0, "mean_delta": 80.0, "added": 40, "removed": 2, "changed": 0,
+                        "probability": 87.5
+                    }],
+                    "diff_image": "差分/p001 <A&B>.png", "error": null
+                },
+                {
+                    "index": 1, "name_a": "p002.psd", "name_b": null,
+                    "status": "missing_b", "exceeded": true, "image_width": 0, "image_height": 0,
+                    "diff_count": 0, "diff_probability": 0.0, "markers": [], "diff_image": null, "error": null
+                },
+                {
+                    "index": 2, "name_a": "p003.psd", "name_b": "p003.tif",
+                    "status": "error", "exceeded": false, "image_width": 0, "image_height": 0,
+                    "diff_count": 0, "diff_probability": 0.0, "markers": [], "diff_image": null,
+                    "error": "Failed to open 'p003.tif'\r\n\u{1b}[31mbroken\u{0}"
+                }
+            ]
+        }))
+        .unwrap()
+    }
+}
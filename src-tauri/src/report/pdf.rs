@@ -0,0 +1,508 @@
+use crate::*;
+
+// ============== PDFレポート ==============
+
+// 最小限のPDF書き出し（画像はJPEGをそのまま埋め込み, 文字は日本語フォントのサブセット。なければHelvetica）
+pub(crate) struct PdfWriter {
+    pub(crate) objects: Vec<Option<Vec<u8>>>,
+}
+
+impl PdfWriter {
+    pub(crate) fn new() -> Self {
+        Self { objects: Vec::new() }
+    }
+
+    // オブジェクト番号を先に確保（相互参照する辞書用）
+    pub(crate) fn reserve(&mut self) -> usize {
+        self.objects.push(None);
+        self.objects.len()
+    }
+
+    pub(crate) fn set(&mut self, id: usize, body: impl Into<Vec<u8>>) {
+        self.objects[id - 1] = Some(body.into());
+    }
+
+    pub(crate) fn add(&mut self, body: impl Into<Vec<u8>>) -> usize {
+        let id = self.reserve();
+        self.set(id, body);
+        id
+    }
+
+    pub(crate) fn add_stream(&mut self, dict: &str, data: &[u8]) -> usize {
+        let mut body = format!("<< {} /Length {} >>\nstream\n", dict, data.len()).into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\nendstream");
+        self.add(body)
+    }
+
+    pub(crate) fn add_jpeg(&mut self, img: &DynamicImage) -> Result<(usize, u32, u32), String> {
+        let rgb = img.to_rgb8();
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 85)
+            .encode_image(&rgb)
+            .map_err(|e| format!("JPEG encode error: {}", e))?;
+        let (w, h) = rgb.dimensions();
+        let id = self.add_stream(
+            &format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB \
+                 /BitsPerComponent 8 /Filter /DCTDecode",
+                w, h,
+            ),
+            &jpeg,
+        );
+        Ok((id, w, h))
+    }
+
+    pub(crate) fn finish(self, root: usize) -> Vec<u8> {
+        let mut out = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+        let mut offsets = Vec::with_capacity(self.objects.len());
+        for (i, obj) in self.objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            out.extend_from_slice(obj.as_deref().unwrap_or(b"null"));
+            out.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = out.len();
+        out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1).as_bytes());
+        for offset in offsets {
+            out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        out.extend_from_slice(
+            format!(
+                "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+                self.objects.len() + 1, root, xref,
+            )
+            .as_bytes(),
+        );
+        out
+    }
+}
+
+// A4横（pt）
+pub(crate) const PDF_PAGE_W: f64 = 842.0;
+pub(crate) const PDF_PAGE_H: f64 = 595.0;
+pub(crate) const PDF_MARGIN: f64 = 30.0;
+// 1ペアあたりの拡大図の上限（大量のノイズで肥大化しないように）
+pub(crate) const PDF_MAX_CROPS_PER_PAIR: usize = 24;
+pub(crate) const PDF_SUMMARY_ROWS: usize = 36;
+
+// 1ページ分の描画命令（座標は左上原点で受け取りPDF座標に変換）
+// 文字は使うグリフを集めてからフォントを埋め込むので、ページを組むときに書き出す
+pub(crate) struct PdfPage {
+    pub(crate) content: String,
+    pub(crate) images: Vec<usize>,
+    pub(crate) texts: Vec<(f64, f64, f64, String)>, // (x, y, size, 文字列)
+}
+
+impl PdfPage {
+    pub(crate) fn new() -> Self {
+        Self { content: String::new(), images: Vec::new(), texts: Vec::new() }
+    }
+
+    pub(crate) fn text(&mut self, x: f64, y: f64, size: f64, text: &str) {
+        self.texts.push((x, y, size, text.to_string()));
+    }
+
+    pub(crate) fn image(&mut self, id: usize, x: f64, y: f64, w: f64, h: f64) {
+        self.images.push(id);
+        self.content.push_str(&format!(
+            "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im{} Do Q\n",
+            w, h, x, PDF_PAGE_H - y - h, id,
+        ));
+    }
+
+    pub(crate) fn rect(&mut self, x: f64, y: f64, w: f64, h: f64, rgb: [f64; 3]) {
+        self.content.push_str(&format!(
+            "q {} {} {} RG 1.5 w {:.2} {:.2} {:.2} {:.2} re S Q\n",
+            rgb[0], rgb[1], rgb[2], x, PDF_PAGE_H - y - h, w, h,
+        ));
+    }
+}
+
+// 画像を枠内に収める（位置とサイズを返す）
+pub(crate) fn fit_in_box(iw: u32, ih: u32, x: f64, y: f64, bw: f64, bh: f64) -> (f64, f64, f64, f64) {
+    let scale = (bw / iw as f64).min(bh / ih as f64);
+    let (w, h) = (iw as f64 * scale, ih as f64 * scale);
+    (x + (bw - w) / 2.0, y + (bh - h) / 2.0, w, h)
+}
+
+// PDFに埋め込む前に縮小（長辺max_size以下）
+pub(crate) fn shrink_for_pdf(img: &DynamicImage, max_size: u32) -> DynamicImage {
+    let (w, h) = img.dimensions();
+    if w.max(h) <= max_size {
+        return img.clone();
+    }
+    img.resize(max_size, max_size, FilterType::Triangle)
+}
+
+// UNIX時刻 → "YYYY-MM-DD HH:MM UTC"
+pub(crate) fn format_unix_time(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // civil_from_days（グレゴリオ暦）
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", y, m, d, rem / 3600, rem % 3600 / 60)
+}
+
+// PDFのテキスト文字列（UTF-16BE, しおり用）
+pub(crate) fn pdf_utf16_string(text: &str) -> String {
+    let mut hex = String::from("<FEFF");
+    for unit in text.encode_utf16() {
+        hex.push_str(&format!("{:04X}", unit));
+    }
+    hex.push('>');
+    hex
+}
+
+// 検版PDFの組み立て（一括比較の各ペアを順に追加し、最後に表紙の一覧を付ける）
+pub(crate) struct ProofPdf {
+    pub(crate) writer: PdfWriter,
+    pub(crate) pages_id: usize,
+    pub(crate) font_id: usize,
+    pub(crate) font: Option<PdfFont>, // Noneなら Helvetica
+    pub(crate) pages: Vec<usize>,
+    pub(crate) bookmarks: Vec<(String, usize)>,        // (しおりの名前, ペアの先頭ページのオブジェクト番号)
+    pub(crate) first_page_of_pair: HashMap<usize, usize>, // ペアのindex → pagesでの位置
+}
+
+impl ProofPdf {
+    // 指定したフォントが読めなければエラー（未指定で見つからないときはHelvetica）
+    pub(crate) fn new(font_path: Option<&PathBuf>) -> Result<Self, String> {
+        let font = PdfFont::find(font_path)?;
+        let mut writer = PdfWriter::new();
+        let pages_id = writer.reserve();
+        // 日本語フォントは使ったグリフが決まってから finish で書き出す
+        let font_id = if font.is_some() {
+            writer.reserve()
+        } else {
+            writer.add("<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>")
+        };
+        Ok(Self {
+            writer,
+            pages_id,
+            font_id,
+            font,
+            pages: Vec::new(),
+            bookmarks: Vec::new(),
+            first_page_of_pair: HashMap::new(),
+        })
+    }
+
+    pub(crate) fn build_page(&mut self, page: PdfPage) -> usize {
+        let mut content = page.content;
+        for (x, y, size, text) in &page.texts {
+            let encoded = match &mut self.font {
+                Some(font) => font.encode(text),
+                // Helveticaで表示できない文字は ? に置き換える（ファイル名はしおりに正しく入れる）
+                None => {
+                    let escaped: String = text
+                        .chars()
+                        .map(|c| match c {
+                            '(' | ')' | '\\' => format!("\\{}", c),
+                            ' '..='~' => c.to_string(),
+                            _ => "?".to_string(),
+                        })
+                        .collect();
+                    format!("({})", escaped)
+                }
+            };
+            content.push_str(&format!(
+                "BT /F1 {} Tf {:.2} {:.2} Td {} Tj ET\n",
+                size, x, PDF_PAGE_H - y - size, encoded,
+            ));
+        }
+        let content_id = self.writer.add_stream("", content.as_bytes());
+        let xobjects: String = page.images.iter().map(|id| format!("/Im{} {} 0 R ", id, id)).collect();
+        self.writer.add(format!(
+            "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] \
+             /Resources << /Font << /F1 {} 0 R >> /XObject << {}>> >> /Contents {} 0 R >>",
+            self.pages_id, PDF_PAGE_W, PDF_PAGE_H, self.font_id, xobjects, content_id,
+        ))
+    }
+
+    pub(crate) fn push_page(&mut self, page: PdfPage) -> usize {
+        let id = self.build_page(page);
+        self.pages.push(id);
+        id
+    }
+
+    pub(crate) fn add_pair(&mut self, result: &BatchPairResult, diff: &BatchDiff) -> Result<(), String> {
+        let label = pair_label(result);
+
+        // 1ページ目: 全体のオーバーレイ + マーカー一覧 + 統計
+        let mut page = PdfPage::new();
+        page.text(PDF_MARGIN, PDF_MARGIN, 14.0, &label);
+        page.text(
+            PDF_MARGIN,
+            PDF_MARGIN + 20.0,
+            10.0,
+            &format!(
+                "{} x {} px   diff pixels: {}   markers: {}   probability: {:.1}%",
+                diff.width, diff.height, diff.diff_count, diff.markers.len(), diff.probability,
+            ),
+        );
+        let thumb_scale = (1400.0 / diff.width.max(diff.height) as f64).min(1.0);
+        let tw = ((diff.width as f64 * thumb_scale).round() as u32).max(1);
+        let th = ((diff.height as f64 * thumb_scale).round() as u32).max(1);
+        let base = diff.preview_b.resize_exact(tw, th, FilterType::Triangle);
+        let overlay = DynamicImage::ImageRgb8(downscale_diff_overlay(diff, tw, th, &base));
+        let (overlay_id, ow, oh) = self.writer.add_jpeg(&overlay)?;
+        let (ox, oy, odw, odh) = fit_in_box(ow, oh, PDF_MARGIN, 60.0, 480.0, PDF_PAGE_H - 60.0 - PDF_MARGIN);
+        page.image(overlay_id, ox, oy, odw, odh);
+        let to_page = odw / diff.width as f64;
+        for (i, m) in diff.markers.iter().enumerate() {
+            let b = &m.bbox;
+            let (rx, ry) = (ox + b.left as f64 * to_page, oy + b.top as f64 * to_page);
+            let (rw, rh) = (((b.right - b.left) as f64 * to_page).max(2.0), ((b.bottom - b.top) as f64 * to_page).max(2.0));
+            page.rect(rx, ry, rw, rh, [0.9, 0.1, 0.1]);
+            page.text(rx, (ry - 9.0).max(60.0), 7.0, &format!("{}", i + 1));
+        }
+
+        let table_x = 530.0;
+        page.text(table_x, 60.0, 9.0, "#   box (left, top) - (right, bottom)      pixels   prob.   +/-/~");
+        let max_rows = ((PDF_PAGE_H - 80.0 - PDF_MARGIN) / 12.0) as usize;
+        for (i, m) in diff.markers.iter().take(max_rows).enumerate() {
+            let b = &m.bbox;
+            page.text(
+                table_x,
+                76.0 + i as f64 * 12.0,
+                8.0,
+                &format!(
+                    "{:<3} ({}, {}) - ({}, {})   {}   {:.1}%   {}/{}/{}",
+                    i + 1, b.left, b.top, b.right, b.bottom, m.count, m.probability,
+                    m.added, m.removed, m.changed,
+                ),
+            );
+        }
+        if diff.markers.len() > max_rows {
+            page.text(table_x, 76.0 + max_rows as f64 * 12.0, 8.0, &format!("... {} more", diff.markers.len() - max_rows));
+        }
+        self.first_page_of_pair.insert(result.index, self.pages.len());
+        let first_page = self.push_page(page);
+        self.bookmarks.push((
+            format!(
+                "{:03} {} / {}",
+                result.index + 1,
+                result.name_a.as_deref().unwrap_or("-"),
+                result.name_b.as_deref().unwrap_or("-"),
+            ),
+            first_page,
+        ));
+
+        // 2ページ目以降: マーカー周辺のA/B拡大図（1ページに2件）
+        let crops = diff.markers.iter().take(PDF_MAX_CROPS_PER_PAIR).enumerate().collect::<Vec<_>>();
+        for chunk in crops.chunks(2) {
+            let mut page = PdfPage::new();
+            page.text(PDF_MARGIN, PDF_MARGIN, 10.0, &label);
+            for (slot, (i, m)) in chunk.iter().enumerate() {
+                let top = 50.0 + slot as f64 * 265.0;
+                let b = &m.bbox;
+                page.text(
+                    PDF_MARGIN,
+                    top,
+                    9.0,
+                    &format!(
+                        "#{}  ({}, {}) - ({}, {})   {} px   {:.1}%   added {} / removed {} / changed {}",
+                        i + 1, b.left, b.top, b.right, b.bottom, m.count, m.probability,
+                        m.added, m.removed, m.changed,
+                    ),
+                );
+                // マーカーの周囲に余白を付けて切り出す
+                let pad_x = ((b.right - b.left) / 4).max(32);
+                let pad_y = ((b.bottom - b.top) / 4).max(32);
+                let x1 = b.left.saturating_sub(pad_x);
+                let y1 = b.top.saturating_sub(pad_y);
+                let x2 = (b.right + pad_x).min(diff.width);
+                let y2 = (b.bottom + pad_y).min(diff.height);
+                let box_w = (PDF_PAGE_W - PDF_MARGIN * 2.0 - 20.0) / 2.0;
+                for (col, src) in [&diff.preview_a, &diff.preview_b].into_iter().enumerate() {
+                    let crop = shrink_for_pdf(&src.crop_imm(x1, y1, x2 - x1, y2 - y1), 1000);
+                    let (id, cw, ch) = self.writer.add_jpeg(&crop)?;
+                    let bx = PDF_MARGIN + col as f64 * (box_w + 20.0);
+                    let (ix, iy, iw, ih) = fit_in_box(cw, ch, bx, top + 16.0, box_w, 230.0);
+                    page.image(id, ix, iy, iw, ih);
+                    page.rect(ix, iy, iw, ih, [0.6, 0.6, 0.6]);
+                    page.text(bx, top + 16.0 + 232.0, 8.0, if col == 0 { "A" } else { "B" });
+                }
+            }
+            self.push_page(page);
+        }
+        // PDF_MAX_CROPS_PER_PAIRを超えた分は1ページ目の一覧で確認する
+        Ok(())
+    }
+
+    // 表紙（全ペアの一覧）を先頭に付けてPDFを完成させる
+    pub(crate) fn finish(mut self, results: &[BatchPairResult], cfg: &BatchConfig) -> Vec<u8> {
+        // 結果が0件でも表紙は1ページ作る
+        let chunks: Vec<&[BatchPairResult]> = if results.is_empty() {
+            vec![results]
+        } else {
+            results.chunks(PDF_SUMMARY_ROWS).collect()
+        };
+        let summary_pages = chunks.len();
+        let mut summary_ids = Vec::new();
+        for (n, chunk) in chunks.into_iter().enumerate() {
+            let mut page = PdfPage::new();
+            if n == 0 {
+                page.text(PDF_MARGIN, PDF_MARGIN, 16.0, "Kenban proof report");
+                page.text(
+                    PDF_MARGIN,
+                    PDF_MARGIN + 22.0,
+                    9.0,
+                    &format!(
+                        "{}   A: {}   B: {}   mode: {}",
+                        format_unix_time(unix_time_secs()), cfg.folder_a, cfg.folder_b, batch_mode_name(cfg.mode),
+                    ),
+                );
+                let exceeded = results.iter().filter(|r| r.exceeded).count();
+                page.text(
+                    PDF_MARGIN,
+                    PDF_MARGIN + 36.0,
+                    9.0,
+                    &format!(
+                        "{} pairs, {} above {:.0}% or missing, {} with differences",
+                        results.len(), exceeded, cfg.fail_probability,
+                        results.iter().filter(|r| r.status == BatchStatus::Diff).count(),
+                    ),
+                );
+            }
+            page.text(PDF_MARGIN, 90.0, 9.0, "#     A / B                                                       status        pixels     markers   prob.    PDF page");
+            for (i, r) in chunk.iter().enumerate() {
+                let status = serde_json::to_value(r.status)
+                    .ok()
+                    .and_then(|v| v.as_str().map(|s| s.to_string()))
+                    .unwrap_or_default();
+                let pdf_page = self
+                    .first_page_of_pair
+                    .get(&r.index)
+                    .map(|p| (p + summary_pages + 1).to_string())
+                    .unwrap_or_default();
+                let names = format!(
+                    "{} / {}",
+                    r.name_a.as_deref().unwrap_or("-"),
+                    r.name_b.as_deref().unwrap_or("-"),
+                );
+                let y = 106.0 + i as f64 * 12.0;
+                page.text(PDF_MARGIN, y, 8.0, &format!("{:03}", r.index + 1));
+                page.text(PDF_MARGIN + 30.0, y, 8.0, &names);
+                page.text(PDF_MARGIN + 330.0, y, 8.0, &format!("{}{}", status, if r.exceeded { " *" } else { "" }));
+                page.text(PDF_MARGIN + 400.0, y, 8.0, &r.diff_count.to_string());
+                page.text(PDF_MARGIN + 460.0, y, 8.0, &r.markers.len().to_string());
+                page.text(PDF_MARGIN + 510.0, y, 8.0, &format!("{:.1}%", r.diff_probability));
+                page.text(PDF_MARGIN + 560.0, y, 8.0, &pdf_page);
+            }
+            summary_ids.push(self.build_page(page));
+        }
+
+        // しおり（ファイル名は日本語のまま表示できる）
+        let outlines_id = self.writer.reserve();
+        let mut entries = vec![("一覧".to_string(), summary_ids[0])];
+        entries.extend(self.bookmarks.iter().cloned());
+        let ids: Vec<usize> = entries.iter().map(|_| self.writer.reserve()).collect();
+        for (i, (title, page)) in entries.iter().enumerate() {
+            let mut dict = format!(
+                "<< /Title {} /Parent {} 0 R /Dest [{} 0 R /Fit]",
+                pdf_utf16_string(title), outlines_id, page,
+            );
+            if i > 0 {
+                dict.push_str(&format!(" /Prev {} 0 R", ids[i - 1]));
+            }
+            if let Some(next) = ids.get(i + 1) {
+                dict.push_str(&format!(" /Next {} 0 R", next));
+            }
+            dict.push_str(" >>");
+            self.writer.set(ids[i], dict);
+        }
+        self.writer.set(
+            outlines_id,
+            format!(
+                "<< /Type /Outlines /First {} 0 R /Last {} 0 R /Count {} >>",
+                ids[0], ids[ids.len() - 1], ids.len(),
+            ),
+        );
+
+        let kids: Vec<usize> = summary_ids.into_iter().chain(self.pages.iter().copied()).collect();
+        let kids_str: String = kids.iter().map(|id| format!("{} 0 R ", id)).collect();
+        self.writer.set(
+            self.pages_id,
+            format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids_str, kids.len()),
+        );
+        let root = self.writer.add(format!(
+            "<< /Type /Catalog /Pages {} 0 R /Outlines {} 0 R /PageMode /UseOutlines >>",
+            self.pages_id, outlines_id,
+        ));
+        if let Some(font) = &self.font {
+            font.embed(&mut self.writer, self.font_id);
+        }
+        self.writer.finish(root)
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct PdfReportResult {
+    pub(crate) path: String,
+    pub(crate) pairs: usize,
+    pub(crate) diff_pages: usize, // 差分があってPDFに載ったペア数
+}
+
+// フォルダ一括比較を行い、差分のあるページの検版PDFを書き出す
+#[tauri::command]
+pub(crate) async fn export_pdf_report(config: BatchConfig, out_path: String) -> Result<PdfReportResult, String> {
+    let cfg = BatchConfig { pdf_path: Some(PathBuf::from(&out_path)), ..config };
+    let pairs = run_batch(&cfg)?;
+    Ok(PdfReportResult {
+        path: out_path,
+        pairs: pairs.len(),
+        diff_pages: pairs.iter().filter(|p| p.status == BatchStatus::Diff).count(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(haystack: &[u8], needle: &[u8]) -> usize {
+        haystack.windows(needle.len()).position(|w| w == needle).unwrap()
+    }
+
+    #[test]
+    fn xref_offsets_point_at_objects() {
+        let mut writer = PdfWriter::new();
+        let root = writer.reserve();
+        let unused = writer.reserve();
+        writer.add_stream("", b"\x00\xFFbinary\nendobj");
+        writer.add("(日本語)");
+        writer.set(root, "<< /Type /Catalog >>");
+        let pdf = writer.finish(root);
+
+        let startxref = find(&pdf, b"startxref\n") + 10;
+        let xref: usize = std::str::from_utf8(&pdf[startxref..]).unwrap().lines().next().unwrap().parse().unwrap();
+        assert!(pdf[xref..].starts_with(b"xref\n0 5\n0000000000 65535 f \n"));
+
+        // 20バイト固定の各エントリが "n 0 obj" の先頭を指す
+        let entries = xref + find(&pdf[xref..], b" f \n") + 4;
+        for id in 1..=4 {
+            let entry = &pdf[entries + (id - 1) * 20..entries + id * 20];
+            assert!(entry.ends_with(b" 00000 n \n"));
+            let offset: usize = std::str::from_utf8(&entry[..10]).unwrap().parse().unwrap();
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj\n", id).as_bytes()), "object {}", id);
+        }
+        assert!(pdf[find(&pdf, format!("{} 0 obj\n", unused).as_bytes())..].starts_with(b"2 0 obj\nnull\nendobj"));
+        assert!(find(&pdf, b"trailer\n<< /Size 5 /Root 1 0 R >>") > xref);
+        assert!(pdf.ends_with(b"%%EOF\n"));
+    }
+
+    #[test]
+    fn unreadable_font_is_an_error() {
+        let missing = std::env::temp_dir().join("kenban-test-missing-font.ttf");
+        assert!(ProofPdf::new(Some(&missing)).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use crate::*;
+use std::sync::Arc;
+
+// ============== タイルピラミッド（拡大表示用） ==============
+
+pub(crate) const DEFAULT_TILE_SIZE: u32 = 256;
+// ピラミッドは原寸の約4/3の画素を持つのでバイト数で上限を決める
+pub(crate) const TILE_STORE_BUDGET_MB: usize = 1024;
+
+// level 0 が原寸, level n は 1/2^n（最後のレベルは1タイルに収まる）
+// 原寸は複製せず結果画像とArcで共有し、縮小したレベルだけを持つ
+pub(crate) struct TilePyramid {
+    pub(crate) tile_size: u32,
+    pub(crate) base: Arc<StoredImage>,
+    pub(crate) reduced: Vec<DynamicImage>, // level 1以降
+}
+
+impl ByteSize for TilePyramid {
+    // 結果画像が先に破棄されても原寸はピラミッドが保持し続けるので含めて数える
+    fn byte_size(&self) -> usize {
+        self.base.byte_size() + self.reduced.iter().map(|level| level.as_bytes().len()).sum::<usize>()
+    }
+}
+
+impl TilePyramid {
+    pub(crate) fn build(base: Arc<StoredImage>, tile_size: u32) -> Result<Self, String> {
+        let (w, h) = base.dimensions();
+        let mut reduced: Vec<DynamicImage> = Vec::new();
+        if w.max(h) > tile_size {
+            // 最初の縮小だけ原寸から作る（比較用画素データは一時的に画像へ戻す）
+            let half = match &*base {
+                StoredImage::Image(img) => img.resize_exact(w.div_ceil(2), h.div_ceil(2), FilterType::Triangle),
+                StoredImage::Plane { plane, width, height } => plane_to_image(plane, *width, *height)?
+                    .resize_exact(w.div_ceil(2), h.div_ceil(2), FilterType::Triangle),
+            };
+            reduced.push(half);
+        }
+        while let Some(last) = reduced.last() {
+            let (w, h) = last.dimensions();
+            if w.max(h) <= tile_size {
+                break;
+            }
+            let next = last.resize_exact(w.div_ceil(2), h.div_ceil(2), FilterType::Triangle);
+            reduced.push(next);
+        }
+        Ok(Self { tile_size, base, reduced })
+    }
+
+    pub(crate) fn level_count(&self) -> usize {
+        self.reduced.len() + 1
+    }
+
+    pub(crate) fn level_dimensions(&self, level: usize) -> Option<(u32, u32)> {
+        match level {
+            0 => Some(self.base.dimensions()),
+            _ => self.reduced.get(level - 1).map(|img| img.dimensions()),
+        }
+    }
+
+    // 範囲外ならNone（右端・下端のタイルはtile_sizeより小さい）
+    pub(crate) fn tile(&self, level: usize, col: u32, row: u32) -> Result<Option<DynamicImage>, String> {
+        let Some((w, h)) = self.level_dimensions(level) else { return Ok(None) };
+        let (Some(x), Some(y)) = (col.checked_mul(self.tile_size), row.checked_mul(self.tile_size)) else {
+            return Ok(None);
+        };
+        if x >= w || y >= h {
+            return Ok(None);
+        }
+        let (tw, th) = (self.tile_size.min(w - x), self.tile_size.min(h - y));
+        match level {
+            0 => self.base.crop(x, y, tw, th).map(Some),
+            _ => Ok(Some(self.reduced[level - 1].crop_imm(x, y, tw, th))),
+        }
+    }
+}
+
+// "<id>/<level>/<col>_<row>.png" を分解
+pub(crate) fn parse_tile_path(path: &str) -> Option<(u64, usize, u32, u32)> {
+    let mut parts = path.split('/');
+    let id = parts.next()?.parse().ok()?;
+    let level = parts.next()?.parse().ok()?;
+    let name = parts.next()?;
+    let name = name.strip_suffix(".png").unwrap_or(name);
+    let (col, row) = name.split_once('_')?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((id, level, col.parse().ok()?, row.parse().ok()?))
+}
+
+#[derive(Serialize)]
+pub(crate) struct TileLevelInfo {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) columns: u32,
+    pub(crate) rows: u32,
+}
+
+#[derive(Serialize)]
+pub(crate) struct TilePyramidInfo {
+    pub(crate) id: u64,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) tile_size: u32,
+    pub(crate) levels: Vec<TileLevelInfo>, // [0]が原寸
+    pub(crate) url_template: String,       // {level} {col} {row} を置き換えて使う
+}
+
+// 画像ファイル、または binary で返した結果画像のURLからタイルピラミッドを作る
+#[tauri::command]
+pub(crate) async fn open_tile_pyramid(
+    state: State<'_, AppState>, source: String, tile_size: Option<u32>, layer_filter: Option<LayerFilter>,
+) -> Result<TilePyramidInfo, String> {
+    let tile_size = tile_size.unwrap_or(DEFAULT_TILE_SIZE).clamp(64, 2048);
+    let base = match stored_image_id(&source) {
+        // 結果画像は複製せず共有する（縮小はロックの外で行う）
+        Some(id) => state
+            .image_store
+            .lock()
+            .map_err(|e| e.to_string())?
+            .get(id)
+            .ok_or_else(|| format!("Image not found: {}", source))?,
+        None => Arc::new(StoredImage::Image(decode_image_file(&source, layer_filter.as_ref())?)),
+    };
+
+    let (width, height) = base.dimensions();
+    let pyramid = TilePyramid::build(base, tile_size)?;
+    let levels = (0..pyramid.level_count())
+        .filter_map(|level| pyramid.level_dimensions(level))
+        .map(|(w, h)| TileLevelInfo { width: w, height: h, columns: w.div_ceil(tile_size), rows: h.div_ceil(tile_size) })
+        .collect();
+    let id = state.tile_store.lock().map_err(|e| e.to_string())?.insert(pyramid);
+    Ok(TilePyramidInfo {
+        id,
+        width,
+        height,
+        tile_size,
+        levels,
+        url_template: image_uri(&format!("tiles/{}/{{level}}/{{col}}_{{row}}.png", id)),
+    })
+}
+
+// 表示を終えたピラミッドを解放
+#[tauri::command]
+pub(crate) fn close_tile_pyramid(state: State<'_, AppState>, id: u64) -> Result<(), String> {
+    state.tile_store.lock().map_err(|e| e.to_string())?.remove(id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tile_paths() {
+        assert_eq!(parse_tile_path("3/0/1_2.png"), Some((3, 0, 1, 2)));
+        assert_eq!(parse_tile_path("3/2/10_0"), Some((3, 2, 10, 0)));
+        for malformed in [
+            "", "3", "3/0", "3/0/1.png", "3/0/1_2/extra", "x/0/1_2.png", "3/x/1_2.png", "3/0/1_x.png",
+            "3//1_2.png", "3/0/_2.png", "3/0/1_2_3.png",
+        ] {
+            assert_eq!(parse_tile_path(malformed), None, "{}", malformed);
+        }
+        // 負の座標・範囲外の値は数値として読めない
+        assert_eq!(parse_tile_path("3/0/-1_2.png"), None);
+        assert_eq!(parse_tile_path("3/-1/1_2.png"), None);
+        assert_eq!(parse_tile_path("3/0/4294967296_0.png"), None);
+    }
+
+    fn gray_plane(width: u32, height: u32) -> StoredImage {
+        let buf = (0..width * height).map(|i| (i % 251) as u8).collect();
+        StoredImage::Plane { plane: PixelPlane::Gray(buf), width, height }
+    }
+
+    #[test]
+    fn pyramid_shares_the_stored_image() {
+        let base = Arc::new(gray_plane(300, 130));
+        let pyramid = TilePyramid::build(base.clone(), 64).unwrap();
+        assert_eq!(Arc::strong_count(&base), 2);
+        let dims: Vec<_> = (0..pyramid.level_count()).filter_map(|l| pyramid.level_dimensions(l)).collect();
+        assert_eq!(dims, [(300, 130), (150, 65), (75, 33), (38, 17)]);
+    }
+
+    #[test]
+    fn tiles_are_cropped_and_range_checked() {
+        let base = Arc::new(gray_plane(300, 130));
+        let pyramid = TilePyramid::build(base.clone(), 64).unwrap();
+        let expected = plane_to_image(&PixelPlane::Gray(base.to_raw().0), 300, 130).unwrap();
+
+        // 右下の端のタイルは小さい
+        let tile = pyramid.tile(0, 4, 2).unwrap().unwrap();
+        assert_eq!(tile.dimensions(), (44, 2));
+        assert_eq!(tile.as_bytes(), expected.crop_imm(256, 128, 44, 2).as_bytes());
+        assert_eq!(pyramid.tile(1, 2, 1).unwrap().unwrap().dimensions(), (22, 1));
+
+        assert!(pyramid.tile(0, 5, 0).unwrap().is_none());
+        assert!(pyramid.tile(0, 0, 3).unwrap().is_none());
+        assert!(pyramid.tile(4, 0, 0).unwrap().is_none());
+        assert!(pyramid.tile(0, u32::MAX, 0).unwrap().is_none());
+    }
+
+    #[test]
+    fn plane_crops_match_image_crops() {
+        let (w, h) = (19, 7);
+        let gray: Vec<u8> = (0..w * h).map(|i| if (i * 7) % 3 == 0 { 0 } else { 255 }).collect();
+        let bits = pack_bilevel(&gray, w as usize, h as usize).unwrap();
+        let rgba: Vec<u8> = gray.iter().flat_map(|&v| [v, v / 2, 255 - v, 255]).collect();
+        let image = DynamicImage::ImageLuma8(ImageBuffer::from_raw(w, h, gray.clone()).unwrap());
+        let bilevel = StoredImage::Plane { plane: PixelPlane::Bilevel(bits), width: w, height: h };
+        assert_eq!(bilevel.crop(3, 2, 11, 4).unwrap().as_bytes(), image.crop_imm(3, 2, 11, 4).as_bytes());
+        let rgba_image = DynamicImage::ImageRgba8(ImageBuffer::from_raw(w, h, rgba.clone()).unwrap());
+        let rgba_plane = StoredImage::rgba(rgba, w, h);
+        assert_eq!(rgba_plane.crop(9, 1, 10, 6).unwrap().as_bytes(), rgba_image.crop_imm(9, 1, 10, 6).as_bytes());
+    }
+}
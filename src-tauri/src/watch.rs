@@ -0,0 +1,339 @@
+use crate::*;
+
+// ============== 元ファイルの監視 ==============
+pub(crate) const SOURCE_CHANGED_EVENT: &str = "source-file-changed";
+// 保存時に連続して届くイベントをまとめる（最後のイベントからこの時間待ってから通知する）
+pub(crate) const SOURCE_EVENT_DEBOUNCE_MS: u64 = 300;
+// 監視・一括比較の対象
+pub(crate) const IMAGE_EXTENSIONS: &[&str] = &["tif", "tiff", "psd", "png", "jpg", "jpeg"];
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SourceChangeKind {
+    Modified,
+    Created,
+    Removed,
+}
+
+#[derive(Serialize, Clone)]
+pub(crate) struct SourceChangedEvent {
+    pub(crate) path: String,
+    pub(crate) kind: SourceChangeKind,
+    pub(crate) invalidated: usize, // 破棄したメモリキャッシュの件数
+}
+
+// キャッシュした画像のフォルダを監視（ディスクキャッシュはキーに更新日時を含むので対象外）
+pub(crate) struct SourceWatcher {
+    pub(crate) watcher: notify::RecommendedWatcher,
+    pub(crate) dirs: std::collections::HashSet<PathBuf>,
+}
+
+impl SourceWatcher {
+    pub(crate) fn new(app: tauri::AppHandle) -> Result<Self, String> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            let Ok(event) = res else { return };
+            let kind = match event.kind {
+                notify::EventKind::Create(_) => SourceChangeKind::Created,
+                notify::EventKind::Modify(_) => SourceChangeKind::Modified,
+                notify::EventKind::Remove(_) => SourceChangeKind::Removed,
+                _ => return,
+            };
+            for path in event.paths {
+                let ext = path
+                    .extension()
+                    .map(|e| e.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+                    let _ = tx.send((path, kind));
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to start file watcher: {}", e))?;
+        std::thread::spawn(move || source_event_loop(app, rx));
+        Ok(Self { watcher, dirs: std::collections::HashSet::new() })
+    }
+
+    // 上書き保存は一時ファイルからのリネームが多いのでファイルではなくフォルダを監視
+    pub(crate) fn watch_parent(&mut self, path: &str) {
+        use notify::Watcher;
+        let Some(dir) = std::path::Path::new(path).parent().filter(|d| !d.as_os_str().is_empty()) else {
+            return;
+        };
+        if self.dirs.contains(dir) {
+            return;
+        }
+        if self.watcher.watch(dir, notify::RecursiveMode::NonRecursive).is_ok() {
+            self.dirs.insert(dir.to_path_buf());
+        }
+    }
+
+    pub(crate) fn unwatch_all(&mut self) {
+        use notify::Watcher;
+        for dir in self.dirs.drain() {
+            let _ = self.watcher.unwatch(&dir);
+        }
+    }
+}
+
+// 変更イベントが落ち着いてから（書き込み途中ではなく保存後に）キャッシュを破棄して通知する
+// ウォッチャーが破棄される（送信側がなくなる）と終了する
+pub(crate) fn source_event_loop(app: tauri::AppHandle, rx: std::sync::mpsc::Receiver<(PathBuf, SourceChangeKind)>) {
+    use std::sync::mpsc::RecvTimeoutError;
+    let quiet = std::time::Duration::from_millis(SOURCE_EVENT_DEBOUNCE_MS);
+    while let Ok((path, kind)) = rx.recv() {
+        // 同じファイルのイベントは最後の種類だけ通知する
+        let mut pending = HashMap::from([(path, kind)]);
+        loop {
+            match rx.recv_timeout(quiet) {
+                Ok((path, kind)) => {
+                    pending.insert(path, kind);
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        for (path, kind) in pending {
+            let invalidated = app
+                .state::<AppState>()
+                .image_cache
+                .lock()
+                .map(|mut cache| cache.invalidate_source(&path))
+                .unwrap_or(0);
+            let _ = app.emit(SOURCE_CHANGED_EVENT, SourceChangedEvent {
+                path: path.to_string_lossy().to_string(),
+                kind,
+                invalidated,
+            });
+        }
+    }
+}
+
+// ============== 監視フォルダの自動比較 ==============
+pub(crate) const FOLDER_WATCH_RESULT_EVENT: &str = "folder-watch-result";
+pub(crate) const FOLDER_WATCH_ERROR_EVENT: &str = "folder-watch-error";
+// 転送中のファイルを比較しないよう、最後の変更からこの時間待ってから比較する
+pub(crate) const DEFAULT_WATCH_SETTLE_MS: u64 = 2000;
+
+// pairingがorderの場合、ペアは並び順で決まるので、途中にファイルが挿入されると以降の組み合わせがずれ、
+// ずれたペアはすべて新しいペアとして比較・通知される（挿入があり得るフォルダはpairing: nameで監視する）
+#[derive(Deserialize)]
+pub(crate) struct FolderWatchConfig {
+    #[serde(flatten)]
+    pub(crate) batch: BatchConfig,
+    #[serde(default)]
+    pub(crate) initial_scan: bool, // 開始時に既存のペアも比較する
+    pub(crate) settle_ms: Option<u64>,
+}
+
+#[derive(Serialize, Clone)]
+pub(crate) struct FolderWatchResultEvent {
+    pub(crate) watch_id: u64,
+    pub(crate) pair: BatchPairResult,
+}
+
+#[derive(Serialize, Clone)]
+pub(crate) struct FolderWatchErrorEvent {
+    pub(crate) watch_id: u64,
+    pub(crate) error: String,
+}
+
+// 監視1件分（ウォッチャーを破棄すると比較スレッドも終了し、stoppedで比較中のスキャンも止める）
+pub(crate) struct FolderWatch {
+    pub(crate) _watcher: notify::RecommendedWatcher,
+    pub(crate) stopped: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+// 実行中の監視
+#[derive(Default)]
+pub(crate) struct FolderWatches {
+    pub(crate) watchers: HashMap<u64, FolderWatch>,
+    pub(crate) next_id: u64,
+}
+
+pub(crate) type PairSignature = (Option<ImageSource>, Option<ImageSource>);
+
+// 前回の一覧から増えたペア・ファイルが更新されたペアを (並び順, ペア) で返し、knownを今回の一覧に更新する
+// ペアはA/Bのパスの組で識別するので、order指定で組み合わせがずれたペアも新しいペアになる
+pub(crate) fn changed_pairs(
+    pairs: Vec<FilePair>, known: &mut HashMap<FilePair, PairSignature>,
+) -> Vec<(usize, FilePair)> {
+    // 消えたペア（組み合わせの変化を含む）は忘れる
+    known.retain(|key, _| pairs.contains(key));
+    let mut changed = Vec::new();
+    for (i, key) in pairs.into_iter().enumerate() {
+        let signature = (
+            key.0.as_deref().map(ImageSource::from_path),
+            key.1.as_deref().map(ImageSource::from_path),
+        );
+        if known.get(&key) != Some(&signature) {
+            known.insert(key.clone(), signature);
+            changed.push((i, key));
+        }
+    }
+    changed
+}
+
+// フォルダを一覧し、前回からファイルが変わったペアだけ比較して通知する
+// 監視が止められたら残りのペアは比較しない
+pub(crate) fn scan_watched_folders(
+    app: &tauri::AppHandle, watch_id: u64, cfg: &BatchConfig,
+    known: &mut HashMap<FilePair, PairSignature>, notify_changes: bool,
+    stopped: &std::sync::atomic::AtomicBool,
+) {
+    let pairs = match list_batch_pairs(cfg) {
+        Ok(pairs) => pairs,
+        Err(error) => {
+            let _ = app.emit(FOLDER_WATCH_ERROR_EVENT, FolderWatchErrorEvent { watch_id, error });
+            return;
+        }
+    };
+    let changed = changed_pairs(pairs, known);
+    if !notify_changes {
+        return;
+    }
+    for (i, (a, b)) in changed {
+        if stopped.load(std::sync::atomic::Ordering::Relaxed) {
+            return;
+        }
+        let pair = run_batch_pair(i, a.as_deref(), b.as_deref(), cfg, None);
+        let _ = app.emit(FOLDER_WATCH_RESULT_EVENT, FolderWatchResultEvent { watch_id, pair });
+    }
+}
+
+pub(crate) fn folder_watch_loop(
+    app: tauri::AppHandle, watch_id: u64, cfg: BatchConfig, initial_scan: bool, settle: std::time::Duration,
+    rx: std::sync::mpsc::Receiver<()>, stopped: std::sync::Arc<std::sync::atomic::AtomicBool>,
+) {
+    use std::sync::mpsc::RecvTimeoutError;
+    let mut known = HashMap::new();
+    scan_watched_folders(&app, watch_id, &cfg, &mut known, initial_scan, &stopped);
+    // 監視が止まる（送信側が破棄される）までイベントを待つ
+    while rx.recv().is_ok() {
+        loop {
+            match rx.recv_timeout(settle) {
+                Ok(()) => continue,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        scan_watched_folders(&app, watch_id, &cfg, &mut known, true, &stopped);
+    }
+}
+
+// A/Bフォルダを監視し、追加・更新されたファイルのペアをバックグラウンドで比較する
+#[tauri::command]
+pub(crate) fn start_folder_watch(
+    app: tauri::AppHandle, state: State<'_, AppState>, config: FolderWatchConfig,
+) -> Result<u64, String> {
+    use notify::Watcher;
+    let FolderWatchConfig { batch, initial_scan, settle_ms } = config;
+    for folder in [&batch.folder_a, &batch.folder_b] {
+        if !std::path::Path::new(folder).is_dir() {
+            return Err(format!("Folder not found: {}", folder));
+        }
+    }
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let Ok(event) = res else { return };
+        if !matches!(
+            event.kind,
+            notify::EventKind::Create(_) | notify::EventKind::Modify(_) | notify::EventKind::Remove(_)
+        ) {
+            return;
+        }
+        let is_image = event.paths.iter().any(|p| {
+            p.extension()
+                .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.to_string_lossy().to_lowercase().as_str()))
+        });
+        if is_image {
+            let _ = tx.send(());
+        }
+    })
+    .map_err(|e| format!("Failed to start file watcher: {}", e))?;
+    for folder in [&batch.folder_a, &batch.folder_b] {
+        watcher
+            .watch(std::path::Path::new(folder), notify::RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Failed to watch {}: {}", folder, e))?;
+    }
+
+    let stopped = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let watch_id = {
+        let mut watches = state.folder_watches.lock().map_err(|e| e.to_string())?;
+        watches.next_id += 1;
+        let id = watches.next_id;
+        watches.watchers.insert(id, FolderWatch { _watcher: watcher, stopped: stopped.clone() });
+        id
+    };
+    let settle = std::time::Duration::from_millis(settle_ms.unwrap_or(DEFAULT_WATCH_SETTLE_MS));
+    std::thread::spawn(move || folder_watch_loop(app, watch_id, batch, initial_scan, settle, rx, stopped));
+    Ok(watch_id)
+}
+
+// 監視を停止（比較中のペアは最後まで処理し、残りのペアは比較しない）
+#[tauri::command]
+pub(crate) fn stop_folder_watch(state: State<'_, AppState>, watch_id: u64) -> Result<bool, String> {
+    let mut watches = state.folder_watches.lock().map_err(|e| e.to_string())?;
+    let Some(watch) = watches.watchers.remove(&watch_id) else {
+        return Ok(false);
+    };
+    watch.stopped.store(true, std::sync::atomic::Ordering::Relaxed);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folder_pairs(cfg: &BatchConfig) -> Vec<FilePair> {
+        list_batch_pairs(cfg).unwrap()
+    }
+
+    fn names(changed: &[(usize, FilePair)]) -> Vec<(usize, String, String)> {
+        let name = |p: &Option<String>| {
+            p.as_deref().map_or("-".to_string(), |p| PathBuf::from(p).file_name().unwrap().to_string_lossy().into_owned())
+        };
+        changed.iter().map(|(i, (a, b))| (*i, name(a), name(b))).collect()
+    }
+
+    #[test]
+    fn inserted_page_shifts_order_pairs_but_not_name_pairs() {
+        let root = std::env::temp_dir().join(format!("kenban-test-watch-{}", std::process::id()));
+        let (dir_a, dir_b) = (root.join("a"), root.join("b"));
+        fs::create_dir_all(&dir_a).unwrap();
+        fs::create_dir_all(&dir_b).unwrap();
+        for name in ["p001.png", "p003.png"] {
+            fs::write(dir_a.join(name), b"a").unwrap();
+            fs::write(dir_b.join(name), b"b").unwrap();
+        }
+        fs::write(dir_a.join("p002.png"), b"a").unwrap();
+
+        let mut cfg = BatchConfig {
+            folder_a: dir_a.to_string_lossy().into_owned(),
+            folder_b: dir_b.to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+        let mut known_order = HashMap::new();
+        changed_pairs(folder_pairs(&cfg), &mut known_order);
+        cfg.pairing = PairingMode::Name;
+        let mut known_name = HashMap::new();
+        changed_pairs(folder_pairs(&cfg), &mut known_name);
+        assert!(changed_pairs(folder_pairs(&cfg), &mut known_name).is_empty());
+
+        // Bに抜けていたp002を挿入
+        fs::write(dir_b.join("p002.png"), b"b").unwrap();
+        let by_name = names(&changed_pairs(folder_pairs(&cfg), &mut known_name));
+        cfg.pairing = PairingMode::Order;
+        let by_order = names(&changed_pairs(folder_pairs(&cfg), &mut known_order));
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(by_name, [(1, "p002.png".to_string(), "p002.png".to_string())]);
+        // 並び順では2組目以降がずれて新しいペアになる
+        assert_eq!(by_order, [
+            (1, "p002.png".to_string(), "p002.png".to_string()),
+            (2, "p003.png".to_string(), "p003.png".to_string()),
+        ]);
+        assert_eq!(known_order.len(), 3);
+    }
+}